@@ -0,0 +1,292 @@
+//! Account requests for the authenticated account. Logging in and creating
+//! accounts goes through the shared API crate, the account details and
+//! password are changed through the users API of the server. Errors from
+//! both are mapped to [AccountError] so they can be matched on
+
+use crate::{
+    core::{
+        api::ServerAuthError,
+        reqwest::{self, Response, StatusCode},
+        Url,
+    },
+    heartbeat::TOKEN_HEADER,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use thiserror::Error;
+
+/// Endpoint for the details of the authenticated account
+const ACCOUNT_ENDPOINT: &str = "api/users/self";
+/// Endpoint for changing the username and email of the authenticated account
+const DETAILS_ENDPOINT: &str = "api/users/self/details";
+/// Endpoint for changing the password of the authenticated account
+const PASSWORD_ENDPOINT: &str = "api/users/self/password";
+
+/// Errors that can occur while authenticating or managing an account
+#[derive(Debug, Clone, Error)]
+pub enum AccountError {
+    /// Failed to connect to the server
+    #[error("Failed to connect to server: {0}")]
+    ConnectionFailed(Arc<reqwest::Error>),
+    /// The email and password or the session were rejected
+    #[error("Incorrect email or password")]
+    InvalidCredentials,
+    /// The email is already used by another account
+    #[error("That email is already in use")]
+    EmailTaken,
+    /// The server rejected the provided details
+    #[error("The server rejected the provided details")]
+    InvalidDetails,
+    /// The server doesn't support the request
+    #[error("The server does not support this feature")]
+    Unsupported,
+    /// The server responded with an unexpected error status
+    #[error("Server error ({0})")]
+    Server(StatusCode),
+    /// The server response couldn't be read
+    #[error("Invalid server response: {0}")]
+    InvalidResponse(Arc<reqwest::Error>),
+}
+
+impl AccountError {
+    /// Maps an unsuccessful response `status` to the matching error
+    fn from_status(status: StatusCode) -> Self {
+        match status {
+            StatusCode::BAD_REQUEST => AccountError::InvalidDetails,
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => AccountError::InvalidCredentials,
+            StatusCode::CONFLICT => AccountError::EmailTaken,
+            StatusCode::NOT_FOUND | StatusCode::METHOD_NOT_ALLOWED => AccountError::Unsupported,
+            status => AccountError::Server(status),
+        }
+    }
+}
+
+impl From<ServerAuthError> for AccountError {
+    fn from(err: ServerAuthError) -> Self {
+        match err {
+            ServerAuthError::ConnectionFailed(err) => AccountError::ConnectionFailed(Arc::new(err)),
+            ServerAuthError::ErrorResponse(err) => match err.status() {
+                Some(status) => AccountError::from_status(status),
+                None => AccountError::InvalidResponse(Arc::new(err)),
+            },
+            ServerAuthError::InvalidResponse(err) => AccountError::InvalidResponse(Arc::new(err)),
+        }
+    }
+}
+
+/// Basic details about an account
+#[derive(Debug, Clone, Deserialize)]
+pub struct AccountInfo {
+    /// Unique ID of the account
+    pub id: u32,
+    /// Email address of the account
+    pub email: String,
+    /// Display username of the account
+    pub username: String,
+    /// When the account was created if provided by the server
+    #[serde(default)]
+    pub created_at: Option<String>,
+}
+
+/// Request to change account details, only the provided
+/// details are changed
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct UpdateAccountRequest {
+    /// New username for the account
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    /// New email for the account
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+}
+
+/// Request to change the account password
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangePasswordRequest {
+    /// The current account password
+    pub current_password: String,
+    /// The new account password
+    pub new_password: String,
+}
+
+/// Sends an account `request` mapping unsuccessful responses
+/// to the matching [AccountError]
+async fn send_request(request: reqwest::RequestBuilder) -> Result<Response, AccountError> {
+    let response = request
+        .send()
+        .await
+        .map_err(|err| AccountError::ConnectionFailed(Arc::new(err)))?;
+
+    let status = response.status();
+    if !status.is_success() {
+        return Err(AccountError::from_status(status));
+    }
+
+    Ok(response)
+}
+
+/// Reads the account details from a successful `response`
+async fn read_account(response: Response) -> Result<AccountInfo, AccountError> {
+    response
+        .json()
+        .await
+        .map_err(|err| AccountError::InvalidResponse(Arc::new(err)))
+}
+
+/// Joins the `endpoint` onto the server `base_url`
+fn endpoint_url(base_url: &Url, endpoint: &str) -> Url {
+    base_url
+        .join(endpoint)
+        .expect("Account endpoints are valid relative URLs")
+}
+
+/// Obtains the details of the authenticated account
+///
+/// ## Arguments
+/// * `http_client` - The HTTP client to make the request with
+/// * `base_url`    - The base URL of the server
+/// * `token`       - The authentication token
+pub async fn get_account(
+    http_client: reqwest::Client,
+    base_url: Url,
+    token: String,
+) -> Result<AccountInfo, AccountError> {
+    let request = http_client
+        .get(endpoint_url(&base_url, ACCOUNT_ENDPOINT))
+        .header(TOKEN_HEADER, token);
+    read_account(send_request(request).await?).await
+}
+
+/// Changes the username and/or email of the authenticated account
+/// returning the updated account details
+///
+/// ## Arguments
+/// * `http_client` - The HTTP client to make the request with
+/// * `base_url`    - The base URL of the server
+/// * `token`       - The authentication token
+/// * `request`     - The details to change
+pub async fn update_account(
+    http_client: reqwest::Client,
+    base_url: Url,
+    token: String,
+    request: UpdateAccountRequest,
+) -> Result<AccountInfo, AccountError> {
+    let request = http_client
+        .put(endpoint_url(&base_url, DETAILS_ENDPOINT))
+        .header(TOKEN_HEADER, token)
+        .json(&request);
+    read_account(send_request(request).await?).await
+}
+
+/// Changes the password of the authenticated account
+///
+/// ## Arguments
+/// * `http_client` - The HTTP client to make the request with
+/// * `base_url`    - The base URL of the server
+/// * `token`       - The authentication token
+/// * `request`     - The current and new password
+pub async fn change_password(
+    http_client: reqwest::Client,
+    base_url: Url,
+    token: String,
+    request: ChangePasswordRequest,
+) -> Result<(), AccountError> {
+    let request = http_client
+        .put(endpoint_url(&base_url, PASSWORD_ENDPOINT))
+        .header(TOKEN_HEADER, token)
+        .json(&request);
+    send_request(request).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        change_password, get_account, update_account, AccountError, ChangePasswordRequest,
+        UpdateAccountRequest,
+    };
+    use crate::{
+        core::reqwest::{self, StatusCode},
+        mock::{MockConfig, MockServer, MOCK_TOKEN},
+    };
+
+    #[tokio::test]
+    async fn test_account_requests() {
+        let server = MockServer::start(MockConfig::default()).await.unwrap();
+        let http_client = reqwest::Client::new();
+        let url = server.url().clone();
+
+        let account = get_account(http_client.clone(), url.clone(), MOCK_TOKEN.to_string())
+            .await
+            .unwrap();
+        assert_eq!(account.email, "test@example.com");
+
+        update_account(
+            http_client.clone(),
+            url.clone(),
+            MOCK_TOKEN.to_string(),
+            UpdateAccountRequest {
+                username: Some("Test".to_string()),
+                email: None,
+            },
+        )
+        .await
+        .unwrap();
+
+        let request = ChangePasswordRequest {
+            current_password: "password123".to_string(),
+            new_password: "password456".to_string(),
+        };
+        change_password(
+            http_client.clone(),
+            url.clone(),
+            MOCK_TOKEN.to_string(),
+            request.clone(),
+        )
+        .await
+        .unwrap();
+
+        assert!(matches!(
+            change_password(http_client, url, "expired-token".to_string(), request).await,
+            Err(AccountError::InvalidCredentials)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_account_error_status() {
+        let http_client = reqwest::Client::new();
+
+        for (status, expected) in [
+            (StatusCode::BAD_REQUEST, AccountError::InvalidDetails),
+            (StatusCode::FORBIDDEN, AccountError::InvalidCredentials),
+            (StatusCode::CONFLICT, AccountError::EmailTaken),
+            (StatusCode::NOT_FOUND, AccountError::Unsupported),
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                AccountError::Server(StatusCode::INTERNAL_SERVER_ERROR),
+            ),
+        ] {
+            let server = MockServer::start(MockConfig {
+                account_failure: Some(status),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+            let err = get_account(
+                http_client.clone(),
+                server.url().clone(),
+                MOCK_TOKEN.to_string(),
+            )
+            .await
+            .unwrap_err();
+            assert_eq!(
+                std::mem::discriminant(&err),
+                std::mem::discriminant(&expected),
+                "{} mapped to {:?}",
+                status,
+                err
+            );
+        }
+    }
+}
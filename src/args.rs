@@ -0,0 +1,57 @@
+//! Command line arguments accepted by the client
+
+use crate::update::UPDATED_ARG;
+use log::warn;
+use std::path::PathBuf;
+
+/// Arguments the client was launched with
+#[derive(Debug, Default)]
+pub struct Args {
+    /// Whether checking for updates is disabled, intended for managed
+    /// installs where updates are handled externally
+    pub no_update_check: bool,
+    /// Whether the client was launched by the previous version
+    /// after being updated
+    pub updated: bool,
+    /// Local directory to obtain updates from instead of GitHub
+    pub update_source: Option<PathBuf>,
+    /// Connection URL to run diagnostics against instead of showing the UI
+    pub diagnose: Option<String>,
+    /// Capture file to replay through the local servers instead of
+    /// showing the UI, only available in debug builds
+    #[cfg(debug_assertions)]
+    pub replay: Option<PathBuf>,
+}
+
+impl Args {
+    /// Parses the [`Args`] from the arguments of the current process
+    pub fn from_env() -> Self {
+        let mut args = Args::default();
+
+        // Skip the executable path
+        let mut values = std::env::args().skip(1);
+
+        while let Some(arg) = values.next() {
+            match arg.as_str() {
+                "--no-update-check" => args.no_update_check = true,
+                UPDATED_ARG => args.updated = true,
+                "--update-source" => match values.next() {
+                    Some(value) => args.update_source = Some(PathBuf::from(value)),
+                    None => warn!("Missing directory for --update-source"),
+                },
+                "--diagnose" => match values.next() {
+                    Some(value) => args.diagnose = Some(value),
+                    None => warn!("Missing connection URL for --diagnose"),
+                },
+                #[cfg(debug_assertions)]
+                "--replay" => match values.next() {
+                    Some(value) => args.replay = Some(PathBuf::from(value)),
+                    None => warn!("Missing capture file for --replay"),
+                },
+                arg => warn!("Ignoring unknown argument: {}", arg),
+            }
+        }
+
+        args
+    }
+}
@@ -0,0 +1,478 @@
+//! Opt-in capture of the traffic between the local servers and the
+//! connected server for debugging
+//!
+//! The Blaze and HTTP servers are provided by the shared crate and don't
+//! expose the traffic they proxy, but they reach the connected server
+//! through the session [proxy](crate::proxy) which records everything
+//! using a [Recorder] while capturing. Captures are written as JSON lines
+//! with one [CaptureRecord] per line
+
+use self::{packet::Packet, tdf::decode_body};
+use crate::{core::reqwest::StatusCode, hex::to_hex, logging::data_dir, proxy::ProxyRequest};
+use log::error;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+pub mod packet;
+#[cfg(debug_assertions)]
+pub mod replay;
+pub mod tdf;
+
+/// Text replacing redacted values
+pub const REDACTED: &str = "<redacted>";
+
+/// [REDACTED] percent-encoded for replacing query parameter values so
+/// the recorded path remains a valid URL
+const REDACTED_QUERY: &str = "%3Credacted%3E";
+
+/// Content type of form encoded bodies
+const FORM_CONTENT_TYPE: &str = "application/x-www-form-urlencoded";
+
+/// Headers that are always redacted, compared ignoring case
+const REDACTED_HEADERS: &[&str] = &["x-token", "authorization", "cookie", "set-cookie"];
+
+/// Parts of JSON keys, TDF tags and query parameters whose values are
+/// redacted, compared against the lowercase key
+const REDACTED_KEYS: &[&str] = &["token", "password", "association", "auth", "skey", "tokn"];
+
+/// Provides the per-user directory captures are written to
+pub fn capture_dir() -> PathBuf {
+    data_dir().join("captures")
+}
+
+/// Direction a Blaze packet was travelling
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    /// From the game to the connected server
+    ToServer,
+    /// From the connected server to the game
+    ToClient,
+}
+
+/// Single event recorded in a capture
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaptureRecord {
+    /// Milliseconds since the unix epoch when the event happened
+    pub time: u64,
+    /// ID of the proxied connection the event belongs to
+    pub connection: u32,
+    /// The recorded event
+    #[serde(flatten)]
+    pub event: CaptureEvent,
+}
+
+/// Events that are recorded in a capture
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CaptureEvent {
+    /// HTTP request sent to the connected server
+    HttpRequest {
+        /// The request method
+        method: String,
+        /// The request path and query
+        path: String,
+        /// The request headers
+        headers: Vec<(String, String)>,
+        /// The request body
+        body: String,
+    },
+    /// HTTP response from the connected server
+    HttpResponse {
+        /// The response status code
+        status: u16,
+        /// The response headers
+        headers: Vec<(String, String)>,
+        /// The response body, empty for upgraded connections
+        body: String,
+    },
+    /// Blaze packet sent over an upgraded connection
+    BlazePacket {
+        /// Direction the packet was travelling
+        direction: Direction,
+        /// The packet component
+        component: u16,
+        /// The packet command
+        command: u16,
+        /// The packet type
+        ty: String,
+        /// The packet sequence ID
+        id: u32,
+        /// The decoded TDF body, null if it couldn't be decoded
+        body: Value,
+        /// The raw packet bytes as hex, left out when redacting
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        raw: Option<String>,
+    },
+    /// The proxied connection was closed
+    Closed {
+        /// The reason the connection closed if it was an error
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+}
+
+/// Removes tokens and passwords from captured data
+#[derive(Debug, Clone)]
+pub struct Redactor {
+    /// Whether redaction is enabled
+    enabled: bool,
+}
+
+impl Redactor {
+    /// Creates a new redactor
+    ///
+    /// ## Arguments
+    /// * `enabled` - Whether values should be redacted
+    pub fn new(enabled: bool) -> Self {
+        Self { enabled }
+    }
+
+    /// Whether redaction is enabled
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Redacts the values of sensitive headers
+    pub fn redact_headers(&self, headers: &mut [(String, String)]) {
+        if !self.enabled {
+            return;
+        }
+
+        headers
+            .iter_mut()
+            .filter(|(name, _)| {
+                REDACTED_HEADERS
+                    .iter()
+                    .any(|header| name.eq_ignore_ascii_case(header))
+            })
+            .for_each(|(_, value)| *value = REDACTED.to_string());
+    }
+
+    /// Redacts the values of sensitive query parameters from a request path
+    pub fn redact_path(&self, path: &str) -> String {
+        if !self.enabled {
+            return path.to_string();
+        }
+
+        let Some((path, query)) = path.split_once('?') else {
+            return path.to_string();
+        };
+
+        format!("{}?{}", path, redact_form(query))
+    }
+
+    /// Redacts sensitive fields from a body with the provided `content_type`.
+    /// JSON and form encoded bodies have the values of sensitive keys
+    /// redacted, any other body can't be checked so is replaced entirely
+    ///
+    /// ## Arguments
+    /// * `content_type` - The Content-Type header of the body if present
+    /// * `body`         - The body text
+    pub fn redact_body(&self, content_type: Option<&str>, body: String) -> String {
+        if !self.enabled || body.is_empty() {
+            return body;
+        }
+
+        if let Ok(mut value) = serde_json::from_str::<Value>(&body) {
+            self.redact_value(&mut value);
+            return value.to_string();
+        }
+
+        let is_form = content_type.is_some_and(|value| {
+            value
+                .trim_start()
+                .to_lowercase()
+                .starts_with(FORM_CONTENT_TYPE)
+        });
+
+        if is_form {
+            redact_form(&body)
+        } else {
+            REDACTED.to_string()
+        }
+    }
+
+    /// Redacts the values of sensitive keys from a JSON or decoded TDF value
+    pub fn redact_value(&self, value: &mut Value) {
+        if !self.enabled {
+            return;
+        }
+
+        match value {
+            Value::Object(map) => map.iter_mut().for_each(|(key, value)| {
+                if is_redacted_key(key) {
+                    *value = Value::String(REDACTED.to_string());
+                } else {
+                    self.redact_value(value);
+                }
+            }),
+            Value::Array(values) => values.iter_mut().for_each(|value| self.redact_value(value)),
+            _ => {}
+        }
+    }
+}
+
+/// Redacts the values of sensitive keys from form encoded `pairs`
+/// such as a query string (e.g. token=value&page=2)
+fn redact_form(pairs: &str) -> String {
+    pairs
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((key, _)) if is_redacted_key(key) => format!("{}={}", key, REDACTED_QUERY),
+            _ => pair.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Checks whether the values of the `key` should be redacted
+fn is_redacted_key(key: &str) -> bool {
+    let key = key.to_lowercase();
+    REDACTED_KEYS.iter().any(|part| key.contains(part))
+}
+
+/// Writes capture records to a JSON lines file
+pub struct CaptureWriter {
+    /// Path to the capture file
+    path: PathBuf,
+    /// The open capture file
+    file: Mutex<BufWriter<File>>,
+}
+
+impl CaptureWriter {
+    /// Creates a new capture file in the capture directory named
+    /// using the current time
+    pub fn create() -> io::Result<Self> {
+        let dir = capture_dir();
+        fs::create_dir_all(&dir)?;
+
+        let path = dir.join(format!("capture-{}.jsonl", now_millis()));
+        let file = File::create(&path)?;
+
+        Ok(Self {
+            path,
+            file: Mutex::new(BufWriter::new(file)),
+        })
+    }
+
+    /// Path to the capture file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes an `event` for the provided `connection` to the file
+    ///
+    /// ## Arguments
+    /// * `connection` - ID of the connection the event belongs to
+    /// * `event`      - The event to write
+    pub fn write(&self, connection: u32, event: CaptureEvent) -> io::Result<()> {
+        let record = CaptureRecord {
+            time: now_millis(),
+            connection,
+            event,
+        };
+        let line = serde_json::to_string(&record)?;
+
+        let mut file = self
+            .file
+            .lock()
+            .map_err(|_| io::Error::other("Capture file lock poisoned"))?;
+        writeln!(file, "{}", line)?;
+        file.flush()
+    }
+}
+
+/// Records the traffic passing through the session proxy to a capture
+/// file, removing sensitive values using the [Redactor]
+pub struct Recorder {
+    /// Writer for the capture file
+    writer: CaptureWriter,
+    /// Redactor for removing sensitive values
+    redactor: Redactor,
+}
+
+impl Recorder {
+    /// Creates a new recorder
+    ///
+    /// ## Arguments
+    /// * `writer`   - Writer for the capture file
+    /// * `redactor` - Redactor for removing sensitive values
+    pub fn new(writer: CaptureWriter, redactor: Redactor) -> Self {
+        Self { writer, redactor }
+    }
+
+    /// Writes an event to the capture file, failures are logged
+    /// rather than interrupting the proxied connection
+    fn record(&self, connection: u32, event: CaptureEvent) {
+        if let Err(err) = self.writer.write(connection, event) {
+            error!("Failed to write capture record: {}", err);
+        }
+    }
+
+    /// Converts a request or response body to redacted text using
+    /// the Content-Type from its `headers`
+    fn body_text(&self, headers: &[(String, String)], body: &[u8]) -> String {
+        let content_type = headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+            .map(|(_, value)| value.as_str());
+
+        self.redactor
+            .redact_body(content_type, String::from_utf8_lossy(body).into_owned())
+    }
+
+    /// Records a request sent by one of the local servers
+    pub(crate) fn record_request(&self, connection: u32, request: &ProxyRequest) {
+        let mut headers = request.headers.clone();
+        self.redactor.redact_headers(&mut headers);
+
+        self.record(
+            connection,
+            CaptureEvent::HttpRequest {
+                method: request.method.clone(),
+                path: self.redactor.redact_path(&request.path),
+                headers,
+                body: self.body_text(&request.headers, &request.body),
+            },
+        );
+    }
+
+    /// Records a response from the connected server
+    pub fn record_response(
+        &self,
+        connection: u32,
+        status: StatusCode,
+        headers: &[(String, String)],
+        body: &[u8],
+    ) {
+        let body = self.body_text(headers, body);
+        let mut headers = headers.to_vec();
+        self.redactor.redact_headers(&mut headers);
+
+        self.record(
+            connection,
+            CaptureEvent::HttpResponse {
+                status: status.as_u16(),
+                headers,
+                body,
+            },
+        );
+    }
+
+    /// Records a Blaze packet sent over an upgraded connection
+    pub fn record_packet(&self, connection: u32, direction: Direction, packet: &Packet) {
+        let mut body = decode_body(&packet.body).unwrap_or(Value::Null);
+        self.redactor.redact_value(&mut body);
+
+        // Raw bytes can't be redacted so they are left out
+        let raw = if self.redactor.enabled() {
+            None
+        } else {
+            Some(to_hex(&packet.raw))
+        };
+
+        self.record(
+            connection,
+            CaptureEvent::BlazePacket {
+                direction,
+                component: packet.header.component,
+                command: packet.header.command,
+                ty: packet.header.ty.to_string(),
+                id: packet.header.seq,
+                body,
+                raw,
+            },
+        );
+    }
+
+    /// Records a proxied connection closing
+    pub fn record_closed(&self, connection: u32, error: Option<String>) {
+        self.record(connection, CaptureEvent::Closed { error });
+    }
+}
+
+/// Milliseconds since the unix epoch
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|value| value.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Redactor, REDACTED};
+    use serde_json::json;
+
+    #[test]
+    fn test_redact_path() {
+        let redactor = Redactor::new(true);
+        assert_eq!(
+            redactor.redact_path("/api/session?token=secret&page=2&Auth=secret"),
+            "/api/session?token=%3Credacted%3E&page=2&Auth=%3Credacted%3E"
+        );
+        assert_eq!(redactor.redact_path("/api/server"), "/api/server");
+        assert_eq!(redactor.redact_path("/api?flag"), "/api?flag");
+
+        let disabled = Redactor::new(false);
+        assert_eq!(
+            disabled.redact_path("/api?token=secret"),
+            "/api?token=secret"
+        );
+    }
+
+    #[test]
+    fn test_redact_headers_and_body() {
+        let redactor = Redactor::new(true);
+
+        let mut headers = vec![
+            ("X-Token".to_string(), "secret".to_string()),
+            ("Content-Type".to_string(), "application/json".to_string()),
+        ];
+        redactor.redact_headers(&mut headers);
+        assert_eq!(headers[0].1, REDACTED);
+        assert_eq!(headers[1].1, "application/json");
+
+        let body = json!({ "email": "a@b.c", "user": { "password": "secret" } }).to_string();
+        let redacted: serde_json::Value =
+            serde_json::from_str(&redactor.redact_body(Some("application/json"), body)).unwrap();
+        assert_eq!(
+            redacted,
+            json!({ "email": "a@b.c", "user": { "password": REDACTED } })
+        );
+    }
+
+    #[test]
+    fn test_redact_non_json_body() {
+        let redactor = Redactor::new(true);
+        let form = "email=a%40b.c&password=secret&remember=1".to_string();
+
+        assert_eq!(
+            redactor.redact_body(
+                Some("application/x-www-form-urlencoded; charset=UTF-8"),
+                form.clone()
+            ),
+            "email=a%40b.c&password=%3Credacted%3E&remember=1"
+        );
+
+        // Bodies that can't be checked are dropped, including form bodies
+        // without a matching content type
+        assert_eq!(
+            redactor.redact_body(Some("text/plain"), "token: secret".to_string()),
+            REDACTED
+        );
+        assert_eq!(redactor.redact_body(None, form.clone()), REDACTED);
+        assert_eq!(redactor.redact_body(None, String::new()), "");
+
+        let disabled = Redactor::new(false);
+        assert_eq!(disabled.redact_body(None, form.clone()), form);
+    }
+}
@@ -0,0 +1,167 @@
+//! Splitting the raw Blaze stream into packets
+//!
+//! Packets use the Fire2 framing from the Blaze SDK with a fixed
+//! 16 byte header followed by the metadata and then the TDF body:
+//!
+//! ```text
+//! length: u32, meta_length: u16, component: u16, command: u16,
+//! seq: u24, type: u8 (upper 3 bits), options: u8, reserved: u8
+//! ```
+
+use std::fmt::{self, Display};
+
+/// Length of the fixed packet header
+pub const HEADER_LENGTH: usize = 16;
+
+/// Largest packet body accepted before the stream is treated as
+/// not being Blaze packets
+pub const MAX_PACKET_LENGTH: usize = 16 * 1024 * 1024;
+
+/// Types of Blaze packets
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacketType {
+    /// Request expecting a response
+    Request,
+    /// Response to a request
+    Response,
+    /// Notification not tied to a request
+    Notify,
+    /// Error response to a request
+    Error,
+    /// Keep alive ping
+    Ping,
+    /// Reply to a keep alive ping
+    PingReply,
+    /// Unknown packet type
+    Unknown(u8),
+}
+
+impl From<u8> for PacketType {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => PacketType::Request,
+            1 => PacketType::Response,
+            2 => PacketType::Notify,
+            3 => PacketType::Error,
+            4 => PacketType::Ping,
+            5 => PacketType::PingReply,
+            value => PacketType::Unknown(value),
+        }
+    }
+}
+
+impl Display for PacketType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PacketType::Request => f.write_str("request"),
+            PacketType::Response => f.write_str("response"),
+            PacketType::Notify => f.write_str("notify"),
+            PacketType::Error => f.write_str("error"),
+            PacketType::Ping => f.write_str("ping"),
+            PacketType::PingReply => f.write_str("ping_reply"),
+            PacketType::Unknown(value) => write!(f, "unknown({})", value),
+        }
+    }
+}
+
+/// Header of a Blaze packet
+#[derive(Debug, Clone)]
+pub struct PacketHeader {
+    /// Length of the packet body
+    pub length: usize,
+    /// Length of the metadata before the body
+    pub meta_length: usize,
+    /// The packet component
+    pub component: u16,
+    /// The packet command
+    pub command: u16,
+    /// The packet sequence ID
+    pub seq: u32,
+    /// The packet type
+    pub ty: PacketType,
+    /// The packet options
+    pub options: u8,
+}
+
+impl PacketHeader {
+    /// Reads a header from the start of `bytes`, [None] if there
+    /// aren't enough bytes for a header
+    pub fn read(bytes: &[u8]) -> Option<Self> {
+        let bytes = bytes.get(..HEADER_LENGTH)?;
+        let u16_at = |index: usize| u16::from_be_bytes([bytes[index], bytes[index + 1]]);
+
+        Some(Self {
+            length: u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize,
+            meta_length: u16_at(4) as usize,
+            component: u16_at(6),
+            command: u16_at(8),
+            seq: u32::from_be_bytes([0, bytes[10], bytes[11], bytes[12]]),
+            ty: PacketType::from(bytes[13] >> 5),
+            options: bytes[14],
+        })
+    }
+
+    /// Total length of the packet including the header
+    pub fn total_length(&self) -> usize {
+        HEADER_LENGTH + self.meta_length + self.length
+    }
+}
+
+/// A complete Blaze packet
+#[derive(Debug, Clone)]
+pub struct Packet {
+    /// The packet header
+    pub header: PacketHeader,
+    /// The packet body
+    pub body: Vec<u8>,
+    /// The complete raw packet
+    pub raw: Vec<u8>,
+}
+
+/// Collects bytes from a stream and splits them into packets
+#[derive(Debug, Default)]
+pub struct PacketDecoder {
+    /// Bytes that haven't formed a complete packet yet
+    buffer: Vec<u8>,
+    /// Set once the stream stopped looking like Blaze packets
+    invalid: bool,
+}
+
+impl PacketDecoder {
+    /// Adds `bytes` from the stream to the decoder
+    pub fn push(&mut self, bytes: &[u8]) {
+        if !self.invalid {
+            self.buffer.extend_from_slice(bytes);
+        }
+    }
+
+    /// Whether the stream stopped looking like Blaze packets,
+    /// no more packets are decoded once this happens
+    pub fn is_invalid(&self) -> bool {
+        self.invalid
+    }
+
+    /// Takes the next complete packet from the decoder
+    pub fn next_packet(&mut self) -> Option<Packet> {
+        if self.invalid {
+            return None;
+        }
+
+        let header = PacketHeader::read(&self.buffer)?;
+        if header.length + header.meta_length > MAX_PACKET_LENGTH {
+            self.invalid = true;
+            self.buffer = Vec::new();
+            return None;
+        }
+
+        let total_length = header.total_length();
+        if self.buffer.len() < total_length {
+            return None;
+        }
+
+        let raw: Vec<u8> = self.buffer.drain(..total_length).collect();
+        let body = raw[HEADER_LENGTH + header.meta_length..].to_vec();
+
+        Some(Packet { header, body, raw })
+    }
+}
@@ -0,0 +1,723 @@
+//! Replaying captured sessions through the local servers
+//!
+//! A mock server stands in for the Pocket Ark server and answers with
+//! the responses recorded in a capture while the recorded requests and
+//! Blaze packets are sent to the local servers as the game would. The
+//! local servers only proxy the traffic so everything should arrive on
+//! both sides exactly as it was recorded, allowing the server-facing
+//! paths of [start_all_servers] to be checked without a game or server
+//!
+//! Blaze connections can only be replayed from captures that were made
+//! by a debug build with redaction disabled as the raw packets are left
+//! out otherwise. Replaying is a debugging tool so it is only included
+//! in debug builds
+
+use super::{
+    packet::{Packet, PacketDecoder},
+    CaptureEvent, CaptureRecord, Direction,
+};
+use crate::{
+    core::{
+        api::AuthToken,
+        reqwest::{self, Method, StatusCode},
+        servers::{stop_server_tasks, BLAZE_PORT, HTTP_PORT},
+        Url,
+    },
+    diagnostics::CheckStatus,
+    hex::from_hex,
+    proxy::{read_request, write_head, write_response},
+    servers::start_all_servers,
+    session::{Session, SessionHandle},
+    APP_VERSION,
+};
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+    fs::File,
+    io::{self, BufRead},
+    net::Ipv4Addr,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use thiserror::Error;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+    time::{sleep, timeout},
+};
+
+/// Time allowed for each expected packet to arrive
+pub const PACKET_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Time given to the local servers to start before replaying
+pub const STARTUP_DELAY: Duration = Duration::from_secs(1);
+
+/// Token given to the local servers, the mock server doesn't check it
+const REPLAY_TOKEN: &str = "replay";
+
+/// Errors that prevent a capture from being replayed
+#[derive(Debug, Error)]
+pub enum ReplayError {
+    /// The capture couldn't be read or the mock server couldn't start
+    #[error("{0}")]
+    Io(#[from] io::Error),
+    /// The capture has nothing to replay
+    #[error("Capture does not contain any HTTP requests")]
+    Empty,
+}
+
+/// Local servers the replayed game traffic is sent to
+#[derive(Debug, Clone, Copy)]
+enum ReplayServers {
+    /// The local servers from [start_all_servers] on their usual ports
+    Local,
+    /// Only the session proxy, Blaze connections are upgraded by the
+    /// replay itself in place of the local Blaze server. Used by the
+    /// tests as the local servers need their fixed ports
+    #[cfg(test)]
+    Proxy,
+}
+
+/// How the replayed game connects to the local Blaze server
+enum BlazeTarget {
+    /// Directly to the local Blaze server
+    Local,
+    /// By upgrading a connection through the session proxy at the URL
+    #[cfg(test)]
+    Upgrade(Url),
+}
+
+/// Recorded Blaze packet
+#[derive(Debug, Clone)]
+pub struct ReplayPacket {
+    /// Direction the packet was travelling
+    pub direction: Direction,
+    /// The packet component
+    pub component: u16,
+    /// The packet command
+    pub command: u16,
+    /// The raw packet if the capture wasn't redacted
+    pub raw: Option<Vec<u8>>,
+}
+
+/// Recorded HTTP request along with its response and any
+/// Blaze packets sent after an upgrade
+#[derive(Debug, Clone)]
+pub struct Exchange {
+    /// The request method
+    pub method: String,
+    /// The request path and query
+    pub path: String,
+    /// The request headers
+    pub headers: Vec<(String, String)>,
+    /// The request body
+    pub body: String,
+    /// The response status code if a response was recorded
+    pub status: Option<u16>,
+    /// The response headers
+    pub response_headers: Vec<(String, String)>,
+    /// The response body
+    pub response_body: String,
+    /// Packets sent after the connection was upgraded
+    pub packets: Vec<ReplayPacket>,
+}
+
+impl Exchange {
+    /// Whether the connection was upgraded for Blaze
+    pub fn is_upgrade(&self) -> bool {
+        self.status == Some(StatusCode::SWITCHING_PROTOCOLS.as_u16())
+    }
+
+    /// Name of the exchange for the report
+    fn name(&self) -> String {
+        format!("{} {}", self.method, self.path)
+    }
+}
+
+/// Reads all the records from the capture file at `path`
+pub fn read_capture(path: &Path) -> io::Result<Vec<CaptureRecord>> {
+    let file = io::BufReader::new(File::open(path)?);
+
+    file.lines()
+        .filter(|line| !matches!(line, Ok(line) if line.trim().is_empty()))
+        .map(|line| Ok(serde_json::from_str(&line?)?))
+        .collect()
+}
+
+/// Groups the capture `records` into the exchanges made on each connection
+pub fn exchanges_from_records(records: Vec<CaptureRecord>) -> Vec<Exchange> {
+    let mut exchanges: Vec<Exchange> = Vec::new();
+    // Index of the exchange for each open connection
+    let mut connections: HashMap<u32, usize> = HashMap::new();
+
+    for record in records {
+        if let CaptureEvent::HttpRequest {
+            method,
+            path,
+            headers,
+            body,
+        } = record.event
+        {
+            connections.insert(record.connection, exchanges.len());
+            exchanges.push(Exchange {
+                method,
+                path,
+                headers,
+                body,
+                status: None,
+                response_headers: Vec::new(),
+                response_body: String::new(),
+                packets: Vec::new(),
+            });
+            continue;
+        }
+
+        let Some(exchange) = connections
+            .get(&record.connection)
+            .and_then(|index| exchanges.get_mut(*index))
+        else {
+            continue;
+        };
+
+        match record.event {
+            CaptureEvent::HttpResponse {
+                status,
+                headers,
+                body,
+            } => {
+                exchange.status = Some(status);
+                exchange.response_headers = headers;
+                exchange.response_body = body;
+            }
+            CaptureEvent::BlazePacket {
+                direction,
+                component,
+                command,
+                raw,
+                ..
+            } => exchange.packets.push(ReplayPacket {
+                direction,
+                component,
+                command,
+                raw: raw.as_deref().and_then(from_hex),
+            }),
+            CaptureEvent::Closed { .. } => {
+                connections.remove(&record.connection);
+            }
+            CaptureEvent::HttpRequest { .. } => {}
+        }
+    }
+
+    exchanges
+}
+
+/// Result of replaying a single exchange
+#[derive(Debug, Clone)]
+pub struct ReplayCheck {
+    /// Name of the exchange
+    pub name: String,
+    /// Outcome of the replay
+    pub status: CheckStatus,
+    /// Details about the outcome
+    pub detail: String,
+}
+
+/// Report containing the result of replaying each exchange
+#[derive(Debug, Clone, Default)]
+pub struct ReplayReport {
+    /// The capture that was replayed
+    pub capture: PathBuf,
+    /// The results of each exchange
+    pub checks: Vec<ReplayCheck>,
+}
+
+impl ReplayReport {
+    /// Whether every exchange replayed the same as it was captured
+    pub fn passed(&self) -> bool {
+        !self
+            .checks
+            .iter()
+            .any(|check| check.status == CheckStatus::Fail)
+    }
+}
+
+impl Display for ReplayReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Pocket Ark Client Replay")?;
+        writeln!(f, "Client version: v{}", APP_VERSION)?;
+        writeln!(f, "Capture: {}", self.capture.display())?;
+        writeln!(f)?;
+
+        for check in &self.checks {
+            writeln!(f, "[{}] {}: {}", check.status, check.name, check.detail)?;
+        }
+
+        writeln!(f)?;
+        writeln!(f, "Result: {}", if self.passed() { "PASS" } else { "FAIL" })
+    }
+}
+
+/// Adds a check result to the shared `report`
+fn push_check(report: &Mutex<ReplayReport>, name: String, status: CheckStatus, detail: String) {
+    if let Ok(report) = &mut report.lock() {
+        report.checks.push(ReplayCheck {
+            name,
+            status,
+            detail,
+        });
+    }
+}
+
+/// Replays the capture at `path` through the local servers producing
+/// a report of any differences
+///
+/// ## Arguments
+/// * `http_client` - The HTTP client for the local servers to use
+/// * `path`        - Path to the capture file
+pub async fn run_replay(
+    http_client: reqwest::Client,
+    path: &Path,
+) -> Result<ReplayReport, ReplayError> {
+    replay(http_client, path, ReplayServers::Local).await
+}
+
+/// Replays the capture at `path` through the provided local `servers`
+async fn replay(
+    http_client: reqwest::Client,
+    path: &Path,
+    servers: ReplayServers,
+) -> Result<ReplayReport, ReplayError> {
+    let exchanges = exchanges_from_records(read_capture(path)?);
+    if exchanges.is_empty() {
+        return Err(ReplayError::Empty);
+    }
+
+    let report = Arc::new(Mutex::new(ReplayReport {
+        capture: path.to_path_buf(),
+        checks: Vec::new(),
+    }));
+
+    // Start the mock server in place of the Pocket Ark server
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+    let base_url =
+        Url::parse(&format!("http://{}/", listener.local_addr()?)).map_err(io::Error::other)?;
+    let mock = Arc::new(MockServer {
+        used: Mutex::new(vec![false; exchanges.len()]),
+        exchanges: exchanges.clone(),
+        report: report.clone(),
+    });
+    let mock_task = tokio::spawn(mock.run(listener));
+
+    let session = SessionHandle::new(Session {
+        base_url: Arc::new(base_url),
+        association: Arc::new(None),
+        token: AuthToken::from(REPLAY_TOKEN),
+    });
+
+    // Start the servers the game would connect to
+    let (game_url, blaze_target, proxy_task): (_, _, Option<JoinHandle<io::Result<()>>>) =
+        match servers {
+            ReplayServers::Local => {
+                start_all_servers(http_client, session);
+                sleep(STARTUP_DELAY).await;
+
+                let url = Url::parse(&format!("https://{}:{}/", Ipv4Addr::LOCALHOST, HTTP_PORT))
+                    .map_err(io::Error::other)?;
+                (url, BlazeTarget::Local, None)
+            }
+            #[cfg(test)]
+            ReplayServers::Proxy => {
+                let proxy = crate::proxy::SessionProxy::bind()?;
+                let url = proxy.url().clone();
+                let task = tokio::spawn(proxy.run(http_client, session, None));
+                (url.clone(), BlazeTarget::Upgrade(url), Some(task))
+            }
+        };
+
+    // The local HTTP server uses a self signed certificate
+    let game_client = reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .build()
+        .map_err(io::Error::other)?;
+
+    for exchange in &exchanges {
+        let result = if !exchange.is_upgrade() {
+            replay_http(&game_client, &game_url, exchange).await
+        } else if exchange.packets.iter().any(|packet| packet.raw.is_none()) {
+            push_check(
+                &report,
+                exchange.name(),
+                CheckStatus::Skipped,
+                "Capture is redacted so packets can't be replayed".to_string(),
+            );
+            continue;
+        } else {
+            replay_blaze(&blaze_target, exchange).await
+        };
+
+        let (status, detail) = match result {
+            Ok(detail) => (CheckStatus::Pass, detail),
+            Err(detail) => (CheckStatus::Fail, detail),
+        };
+        push_check(&report, exchange.name(), status, detail);
+    }
+
+    match proxy_task {
+        Some(task) => task.abort(),
+        None => stop_server_tasks(),
+    }
+    mock_task.abort();
+
+    let report = report
+        .lock()
+        .map(|report| report.clone())
+        .unwrap_or_default();
+    Ok(report)
+}
+
+/// Sends a recorded HTTP request to the local HTTP server at
+/// `game_url` and compares the response with the recording
+async fn replay_http(
+    client: &reqwest::Client,
+    game_url: &Url,
+    exchange: &Exchange,
+) -> Result<String, String> {
+    let method = Method::from_bytes(exchange.method.as_bytes()).map_err(|err| err.to_string())?;
+    let url = game_url
+        .join(exchange.path.trim_start_matches('/'))
+        .map_err(|err| err.to_string())?;
+
+    let mut builder = client.request(method, url);
+    if let Some((_, value)) = exchange
+        .headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+    {
+        builder = builder.header("Content-Type", value.as_str());
+    }
+
+    let response = builder
+        .body(exchange.body.clone())
+        .send()
+        .await
+        .map_err(|err| format!("Request failed: {}", err))?;
+
+    let status = response.status().as_u16();
+    let body = response
+        .text()
+        .await
+        .map_err(|err| format!("Failed to read response: {}", err))?;
+
+    let expected = exchange.status.unwrap_or_default();
+    if status != expected {
+        Err(format!("Expected status {} but got {}", expected, status))
+    } else if body != exchange.response_body {
+        Err("Response body differs from the capture".to_string())
+    } else {
+        Ok(format!("Status {}", status))
+    }
+}
+
+/// Connects to the local Blaze server as the game and plays the
+/// recorded packets
+async fn replay_blaze(target: &BlazeTarget, exchange: &Exchange) -> Result<String, String> {
+    let count = match target {
+        BlazeTarget::Local => {
+            let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, BLAZE_PORT))
+                .await
+                .map_err(|err| format!("Failed to connect to Blaze server: {}", err))?;
+            play_packets(&mut stream, &exchange.packets, Direction::ToServer).await?
+        }
+        #[cfg(test)]
+        BlazeTarget::Upgrade(url) => {
+            let mut stream = upgrade_connection(url, exchange)
+                .await
+                .map_err(|err| format!("Failed to upgrade connection: {}", err))?;
+            play_packets(&mut stream, &exchange.packets, Direction::ToServer).await?
+        }
+    };
+
+    Ok(format!("{} packets matched", count))
+}
+
+/// Sends the recorded upgrade request to the server at `url` as the
+/// local Blaze server would, returning the upgraded stream
+#[cfg(test)]
+async fn upgrade_connection(url: &Url, exchange: &Exchange) -> io::Result<BufReader<TcpStream>> {
+    use tokio::io::AsyncBufReadExt;
+
+    let address = url
+        .socket_addrs(|| None)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Missing address"))?;
+    let mut stream = BufReader::new(TcpStream::connect(address).await?);
+
+    let mut head = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\n",
+        exchange.method, exchange.path, address
+    );
+    for (name, value) in &exchange.headers {
+        if !name.eq_ignore_ascii_case("host") {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+    }
+    head.push_str("\r\n");
+    stream.get_mut().write_all(head.as_bytes()).await?;
+
+    // Read the response head leaving any packets in the buffer
+    let mut line = String::new();
+    stream.read_line(&mut line).await?;
+    if line.split_whitespace().nth(1) != Some("101") {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Connection was not upgraded: {}", line.trim_end()),
+        ));
+    }
+    loop {
+        line.clear();
+        if stream.read_line(&mut line).await? == 0 || line.trim_end().is_empty() {
+            break;
+        }
+    }
+
+    Ok(stream)
+}
+
+/// Plays the recorded `packets` over `stream`, packets travelling in
+/// the `send` direction are written while the others are expected to
+/// be received exactly as recorded
+async fn play_packets<S>(
+    stream: &mut S,
+    packets: &[ReplayPacket],
+    send: Direction,
+) -> Result<usize, String>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut decoder = PacketDecoder::default();
+
+    for (index, packet) in packets.iter().enumerate() {
+        let Some(raw) = &packet.raw else {
+            return Err("Capture is redacted so packets can't be replayed".to_string());
+        };
+
+        if packet.direction == send {
+            stream
+                .write_all(raw)
+                .await
+                .map_err(|err| format!("Failed to send packet {}: {}", index, err))?;
+            continue;
+        }
+
+        let received = timeout(PACKET_TIMEOUT, read_packet(stream, &mut decoder))
+            .await
+            .map_err(|_| format!("Timed out waiting for packet {}", index))?
+            .map_err(|err| format!("Failed to receive packet {}: {}", index, err))?;
+
+        if received.raw != *raw {
+            return Err(format!(
+                "Packet {} differs, expected {}:{} but got {}:{}",
+                index,
+                packet.component,
+                packet.command,
+                received.header.component,
+                received.header.command
+            ));
+        }
+    }
+
+    Ok(packets.len())
+}
+
+/// Reads the next complete packet from `stream`
+async fn read_packet<S>(stream: &mut S, decoder: &mut PacketDecoder) -> io::Result<Packet>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut buffer = [0u8; 8192];
+
+    loop {
+        if let Some(packet) = decoder.next_packet() {
+            return Ok(packet);
+        }
+
+        if decoder.is_invalid() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Stream is not Blaze packets",
+            ));
+        }
+
+        let count = stream.read(&mut buffer).await?;
+        if count == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        decoder.push(&buffer[..count]);
+    }
+}
+
+/// Server standing in for the Pocket Ark server which answers
+/// with the recorded responses
+struct MockServer {
+    /// The recorded exchanges
+    exchanges: Vec<Exchange>,
+    /// Which exchanges have already been answered
+    used: Mutex<Vec<bool>>,
+    /// Report to add unexpected requests to
+    report: Arc<Mutex<ReplayReport>>,
+}
+
+impl MockServer {
+    /// Accepts connections from the local servers until the task is stopped
+    async fn run(self: Arc<Self>, listener: TcpListener) {
+        while let Ok((stream, _)) = listener.accept().await {
+            let mock = self.clone();
+            tokio::spawn(async move {
+                if let Err(err) = mock.handle(stream).await {
+                    push_check(
+                        &mock.report,
+                        "Mock server".to_string(),
+                        CheckStatus::Fail,
+                        err.to_string(),
+                    );
+                }
+            });
+        }
+    }
+
+    /// Takes the first unanswered exchange matching the request
+    fn take_exchange(&self, method: &str, path: &str) -> Option<&Exchange> {
+        let mut used = self.used.lock().ok()?;
+        let index = self
+            .exchanges
+            .iter()
+            .enumerate()
+            .position(|(index, exchange)| {
+                !used[index] && exchange.method == method && exchange.path == path
+            })?;
+        used[index] = true;
+        self.exchanges.get(index)
+    }
+
+    /// Answers a single request from the local servers
+    async fn handle(&self, stream: TcpStream) -> io::Result<()> {
+        let mut stream = BufReader::new(stream);
+        let request = read_request(&mut stream).await?;
+
+        let Some(exchange) = self.take_exchange(&request.method, &request.path) else {
+            push_check(
+                &self.report,
+                format!("{} {}", request.method, request.path),
+                CheckStatus::Fail,
+                "Request was not in the capture".to_string(),
+            );
+            return write_response(stream.get_mut(), StatusCode::NOT_FOUND, &[], &[]).await;
+        };
+
+        let status = exchange
+            .status
+            .and_then(|status| StatusCode::from_u16(status).ok())
+            .unwrap_or(StatusCode::BAD_GATEWAY);
+
+        if !exchange.is_upgrade() {
+            return write_response(
+                stream.get_mut(),
+                status,
+                &exchange.response_headers,
+                exchange.response_body.as_bytes(),
+            )
+            .await;
+        }
+
+        write_head(stream.get_mut(), status, &exchange.response_headers).await?;
+
+        // Differences are reported by the game side of the replay, the
+        // mock side only reports why the connection couldn't continue
+        if let Err(err) = play_packets(&mut stream, &exchange.packets, Direction::ToClient).await {
+            push_check(
+                &self.report,
+                format!("{} (server side)", exchange.name()),
+                CheckStatus::Fail,
+                err,
+            );
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{replay, ReplayServers};
+    use crate::{core::reqwest, diagnostics::CheckStatus};
+    use std::path::PathBuf;
+
+    /// Path to the capture fixture with the provided `name`
+    fn fixture(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name)
+    }
+
+    /// Replays the fixture through the session proxy returning the
+    /// name, status and detail of each check
+    async fn replay_fixture(name: &str) -> Vec<(String, CheckStatus, String)> {
+        let report = replay(reqwest::Client::new(), &fixture(name), ReplayServers::Proxy)
+            .await
+            .unwrap();
+        assert!(report.passed(), "{}", report);
+
+        report
+            .checks
+            .into_iter()
+            .map(|check| (check.name, check.status, check.detail))
+            .collect()
+    }
+
+    fn check(name: &str, status: CheckStatus, detail: &str) -> (String, CheckStatus, String) {
+        (name.to_string(), status, detail.to_string())
+    }
+
+    #[tokio::test]
+    async fn test_replay_blaze_fixture() {
+        let checks = replay_fixture("replay-blaze.jsonl").await;
+
+        assert_eq!(
+            checks,
+            [
+                check("GET /api/server", CheckStatus::Pass, "Status 200"),
+                check(
+                    "GET /api/server/upgrade",
+                    CheckStatus::Pass,
+                    "4 packets matched"
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_replay_redacted_fixture() {
+        let checks = replay_fixture("replay-redacted.jsonl").await;
+
+        // Redacted packets can't be replayed but the requests still match
+        assert_eq!(
+            checks,
+            [
+                check("POST /api/users/login", CheckStatus::Pass, "Status 200"),
+                check(
+                    "GET /api/users/self?token=%3Credacted%3E",
+                    CheckStatus::Pass,
+                    "Status 200"
+                ),
+                check(
+                    "GET /api/server/upgrade",
+                    CheckStatus::Skipped,
+                    "Capture is redacted so packets can't be replayed"
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,239 @@
+//! Schema-less decoding of TDF packet bodies into JSON for captures
+//!
+//! Values are decoded using only the type information in the body so
+//! the field names are the raw TDF tags (e.g "AUTH")
+
+use crate::hex::to_hex;
+use serde_json::{Map, Number, Value};
+use thiserror::Error;
+
+/// Deepest nesting of groups, lists and maps that will be decoded
+const MAX_DEPTH: usize = 32;
+
+/// Errors that can occur while decoding a TDF body
+#[derive(Debug, Error)]
+pub enum TdfError {
+    /// The body ended before a value was complete
+    #[error("Unexpected end of body")]
+    UnexpectedEof,
+    /// The body contained a value type that isn't known
+    #[error("Unknown value type {0:#x}")]
+    UnknownType(u8),
+    /// The body was nested too deeply
+    #[error("Body nested too deeply")]
+    TooDeep,
+}
+
+/// Types of TDF values
+mod ty {
+    pub const VAR_INT: u8 = 0x0;
+    pub const STRING: u8 = 0x1;
+    pub const BLOB: u8 = 0x2;
+    pub const GROUP: u8 = 0x3;
+    pub const LIST: u8 = 0x4;
+    pub const MAP: u8 = 0x5;
+    pub const TAGGED_UNION: u8 = 0x6;
+    pub const VAR_INT_LIST: u8 = 0x7;
+    pub const OBJECT_TYPE: u8 = 0x8;
+    pub const OBJECT_ID: u8 = 0x9;
+    pub const FLOAT: u8 = 0xA;
+}
+
+/// Key of a tagged union that has no value set
+const UNION_UNSET: u8 = 0x7F;
+
+/// Decodes a complete TDF packet `body` into a JSON object
+pub fn decode_body(body: &[u8]) -> Result<Value, TdfError> {
+    let mut reader = TdfReader {
+        bytes: body,
+        cursor: 0,
+    };
+    let mut fields = Map::new();
+
+    // The top level group has no terminator
+    while reader.cursor < body.len() {
+        let (tag, value) = reader.read_field(0)?;
+        fields.insert(tag, value);
+    }
+
+    Ok(Value::Object(fields))
+}
+
+/// Reader over the bytes of a TDF body
+struct TdfReader<'a> {
+    /// The body being read
+    bytes: &'a [u8],
+    /// Position of the next byte to read
+    cursor: usize,
+}
+
+impl TdfReader<'_> {
+    fn read_byte(&mut self) -> Result<u8, TdfError> {
+        let byte = *self.bytes.get(self.cursor).ok_or(TdfError::UnexpectedEof)?;
+        self.cursor += 1;
+        Ok(byte)
+    }
+
+    fn read_slice(&mut self, length: usize) -> Result<&[u8], TdfError> {
+        let end = self
+            .cursor
+            .checked_add(length)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or(TdfError::UnexpectedEof)?;
+        let slice = &self.bytes[self.cursor..end];
+        self.cursor = end;
+        Ok(slice)
+    }
+
+    /// Reads a tag and decodes it into its label, each of the 4
+    /// characters is stored in 6 bits
+    fn read_tag(&mut self) -> Result<String, TdfError> {
+        let bytes = self.read_slice(3)?;
+        let value = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+
+        Ok((0..4)
+            .map(|index| ((value >> (18 - index * 6)) & 0x3F) as u8)
+            .filter(|bits| *bits != 0)
+            .map(|bits| (((bits & 0x20) << 1) | (bits & 0x1F)) as char)
+            .collect())
+    }
+
+    /// Reads a variable length integer, the first byte holds a sign
+    /// bit and 6 value bits with the following bytes holding 7
+    fn read_var_int(&mut self) -> Result<i64, TdfError> {
+        let first = self.read_byte()?;
+        let negative = first & 0x40 != 0;
+        let mut value = (first & 0x3F) as u64;
+        let mut shift = 6;
+        let mut more = first & 0x80 != 0;
+
+        while more {
+            let byte = self.read_byte()?;
+            if shift < 64 {
+                value |= ((byte & 0x7F) as u64) << shift;
+            }
+            shift += 7;
+            more = byte & 0x80 != 0;
+        }
+
+        let value = value as i64;
+        Ok(if negative { -value } else { value })
+    }
+
+    fn read_length(&mut self) -> Result<usize, TdfError> {
+        Ok(self.read_var_int()?.max(0) as usize)
+    }
+
+    fn read_field(&mut self, depth: usize) -> Result<(String, Value), TdfError> {
+        let tag = self.read_tag()?;
+        let ty = self.read_byte()?;
+        let value = self.read_value(ty, depth)?;
+        Ok((tag, value))
+    }
+
+    fn read_value(&mut self, ty: u8, depth: usize) -> Result<Value, TdfError> {
+        if depth > MAX_DEPTH {
+            return Err(TdfError::TooDeep);
+        }
+
+        Ok(match ty {
+            ty::VAR_INT => Value::Number(self.read_var_int()?.into()),
+            ty::STRING => {
+                let length = self.read_length()?;
+                let bytes = self.read_slice(length)?;
+                // Strings include a null terminator
+                let bytes = bytes.strip_suffix(&[0]).unwrap_or(bytes);
+                Value::String(String::from_utf8_lossy(bytes).into_owned())
+            }
+            ty::BLOB => {
+                let length = self.read_length()?;
+                Value::String(to_hex(self.read_slice(length)?))
+            }
+            ty::GROUP => self.read_group(depth + 1)?,
+            ty::LIST => {
+                let value_ty = self.read_byte()?;
+                let length = self.read_length()?;
+                let values = (0..length)
+                    .map(|_| self.read_value(value_ty, depth + 1))
+                    .collect::<Result<_, _>>()?;
+                Value::Array(values)
+            }
+            ty::MAP => {
+                let key_ty = self.read_byte()?;
+                let value_ty = self.read_byte()?;
+                let length = self.read_length()?;
+                let mut map = Map::new();
+                for _ in 0..length {
+                    let key = match self.read_value(key_ty, depth + 1)? {
+                        Value::String(key) => key,
+                        key => key.to_string(),
+                    };
+                    let value = self.read_value(value_ty, depth + 1)?;
+                    map.insert(key, value);
+                }
+                Value::Object(map)
+            }
+            ty::TAGGED_UNION => {
+                let key = self.read_byte()?;
+                if key == UNION_UNSET {
+                    Value::Null
+                } else {
+                    let (tag, value) = self.read_field(depth + 1)?;
+                    let mut map = Map::new();
+                    map.insert("key".to_string(), Value::Number(key.into()));
+                    map.insert(tag, value);
+                    Value::Object(map)
+                }
+            }
+            ty::VAR_INT_LIST => {
+                let length = self.read_length()?;
+                let values = (0..length)
+                    .map(|_| self.read_var_int().map(|value| Value::Number(value.into())))
+                    .collect::<Result<_, _>>()?;
+                Value::Array(values)
+            }
+            ty::OBJECT_TYPE => {
+                let values = (0..2)
+                    .map(|_| self.read_var_int().map(|value| Value::Number(value.into())))
+                    .collect::<Result<_, _>>()?;
+                Value::Array(values)
+            }
+            ty::OBJECT_ID => {
+                let values = (0..3)
+                    .map(|_| self.read_var_int().map(|value| Value::Number(value.into())))
+                    .collect::<Result<_, _>>()?;
+                Value::Array(values)
+            }
+            ty::FLOAT => {
+                let bytes = self.read_slice(4)?;
+                let value = f32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+                Number::from_f64(value as f64)
+                    .map(Value::Number)
+                    .unwrap_or(Value::Null)
+            }
+            ty => return Err(TdfError::UnknownType(ty)),
+        })
+    }
+
+    /// Reads the fields of a group until its terminator
+    fn read_group(&mut self, depth: usize) -> Result<Value, TdfError> {
+        let mut fields = Map::new();
+
+        // Some groups are prefixed with a marker byte
+        if self.bytes.get(self.cursor) == Some(&2) {
+            self.cursor += 1;
+        }
+
+        loop {
+            if self.bytes.get(self.cursor) == Some(&0) {
+                self.cursor += 1;
+                break;
+            }
+
+            let (tag, value) = self.read_field(depth)?;
+            fields.insert(tag, value);
+        }
+
+        Ok(Value::Object(fields))
+    }
+}
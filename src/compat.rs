@@ -0,0 +1,250 @@
+//! Compatibility checking between the client and Pocket Ark server versions
+
+use crate::{
+    core::{api::LookupData, reqwest, Version},
+    update::GITHUB_REPOSITORY,
+    APP_VERSION,
+};
+use log::{debug, warn};
+use serde::Deserialize;
+
+/// Minimum server version supported by this client (inclusive)
+pub const MIN_SERVER_VERSION: Version = Version::new(0, 1, 0);
+/// Maximum server version supported by this client (exclusive)
+pub const MAX_SERVER_VERSION: Version = Version::new(0, 2, 0);
+
+/// Compatibility details optionally published by the server
+#[derive(Debug, Default, Deserialize)]
+struct ServerCompatibility {
+    /// Minimum client version the server requires
+    #[serde(default)]
+    min_client_version: Option<String>,
+}
+
+/// Result of checking the compatibility with a server
+#[derive(Debug, Clone)]
+pub enum Compatibility {
+    /// The server is supported
+    Compatible,
+    /// The server may not be fully supported but connecting is allowed
+    Warning(String),
+    /// The server cannot be connected to with this client
+    Incompatible(String),
+}
+
+/// Creates the URL to the client release page for the provided `version`
+fn release_url(version: Option<&Version>) -> String {
+    match version {
+        Some(version) => format!(
+            "https://github.com/{}/releases/tag/v{}",
+            GITHUB_REPOSITORY, version
+        ),
+        None => format!("https://github.com/{}/releases", GITHUB_REPOSITORY),
+    }
+}
+
+/// Obtains the minimum client version required by the server if the
+/// server publishes one
+///
+/// ## Arguments
+/// * `http_client` - The HTTP client to make the request with
+/// * `lookup_data` - The server lookup data
+async fn get_min_client_version(
+    http_client: &reqwest::Client,
+    lookup_data: &LookupData,
+) -> Option<Version> {
+    let url = lookup_data.url.join("api/server").ok()?;
+
+    let details: ServerCompatibility = match http_client.get(url).send().await {
+        Ok(response) => response.json().await.unwrap_or_default(),
+        Err(err) => {
+            warn!("Failed to get server compatibility details: {}", err);
+            return None;
+        }
+    };
+
+    let version = details.min_client_version?;
+    match version.trim_start_matches('v').parse::<Version>() {
+        Ok(value) => Some(value),
+        Err(err) => {
+            warn!("Server published invalid minimum client version: {}", err);
+            None
+        }
+    }
+}
+
+/// Checks the compatibility between this client and the server
+/// described by the `lookup_data`
+///
+/// ## Arguments
+/// * `http_client` - The HTTP client to make requests with
+/// * `lookup_data` - The server lookup data
+pub async fn check_compatibility(
+    http_client: &reqwest::Client,
+    lookup_data: &LookupData,
+) -> Compatibility {
+    let client_version = Version::parse(APP_VERSION).expect("Failed to parse app version");
+    let min_client_version = get_min_client_version(http_client, lookup_data).await;
+
+    let compatibility = compare_versions(
+        &client_version,
+        &lookup_data.version,
+        min_client_version.as_ref(),
+    );
+
+    debug!(
+        "Server v{} compatibility: {:?}",
+        lookup_data.version, compatibility
+    );
+
+    compatibility
+}
+
+/// Compares the client and server versions against the supported
+/// ranges to determine the compatibility
+///
+/// ## Arguments
+/// * `client_version`     - The version of this client
+/// * `server_version`     - The version of the server
+/// * `min_client_version` - Minimum client version the server requires
+pub fn compare_versions(
+    client_version: &Version,
+    server_version: &Version,
+    min_client_version: Option<&Version>,
+) -> Compatibility {
+    // Server requires a newer client
+    if let Some(min_client_version) = min_client_version {
+        if client_version < min_client_version {
+            return Compatibility::Incompatible(format!(
+                "This server requires client version v{} or newer but you are using v{}.\n\n\
+                Download the required client version from:\n{}",
+                min_client_version,
+                client_version,
+                release_url(Some(min_client_version))
+            ));
+        }
+    }
+
+    // Server is older than the client supports
+    if *server_version < MIN_SERVER_VERSION {
+        return Compatibility::Incompatible(format!(
+            "This server is running v{} but this client requires server v{} or newer. \
+            Ask the server owner to update the server or use an older client from:\n{}",
+            server_version,
+            MIN_SERVER_VERSION,
+            release_url(None)
+        ));
+    }
+
+    // Server is newer than the client supports
+    if *server_version >= MAX_SERVER_VERSION {
+        return Compatibility::Warning(format!(
+            "This server is running v{} which is newer than this client supports \
+            (below v{}). Some features may not work, check for a newer client at:\n{}",
+            server_version,
+            MAX_SERVER_VERSION,
+            release_url(None)
+        ));
+    }
+
+    Compatibility::Compatible
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        check_compatibility, compare_versions, Compatibility, MAX_SERVER_VERSION,
+        MIN_SERVER_VERSION,
+    };
+    use crate::{
+        core::{api::LookupData, reqwest, Version},
+        mock::{MockConfig, MockServer},
+    };
+    use std::sync::Arc;
+
+    #[test]
+    fn test_compare_server_versions() {
+        let client = Version::new(0, 1, 0);
+
+        for (server, expected) in [
+            // Below the minimum
+            (Version::new(0, 0, 9), "incompatible"),
+            // The minimum is inclusive
+            (MIN_SERVER_VERSION, "compatible"),
+            (Version::new(0, 1, 5), "compatible"),
+            // The maximum is exclusive
+            (MAX_SERVER_VERSION, "warning"),
+            (Version::new(1, 0, 0), "warning"),
+        ] {
+            let result = match compare_versions(&client, &server, None) {
+                Compatibility::Compatible => "compatible",
+                Compatibility::Warning(_) => "warning",
+                Compatibility::Incompatible(_) => "incompatible",
+            };
+            assert_eq!(result, expected, "server v{}", server);
+        }
+    }
+
+    #[test]
+    fn test_compare_min_client_version() {
+        let client = Version::new(0, 1, 0);
+        let server = MIN_SERVER_VERSION;
+
+        // The required release is linked
+        let newer = Version::new(0, 2, 0);
+        match compare_versions(&client, &server, Some(&newer)) {
+            Compatibility::Incompatible(message) => {
+                assert!(message.contains("/releases/tag/v0.2.0"), "{}", message)
+            }
+            value => panic!("Expected incompatible, got {:?}", value),
+        }
+
+        // The minimum client version is inclusive
+        assert!(matches!(
+            compare_versions(&client, &server, Some(&client)),
+            Compatibility::Compatible
+        ));
+
+        // Takes priority over an unsupported server
+        assert!(matches!(
+            compare_versions(&client, &MAX_SERVER_VERSION, Some(&newer)),
+            Compatibility::Incompatible(_)
+        ));
+    }
+
+    /// Checks the compatibility with a mock server publishing the
+    /// provided `min_client_version`
+    async fn check_min_client_version(min_client_version: Option<&str>) -> Compatibility {
+        let server = MockServer::start(MockConfig {
+            min_client_version: min_client_version.map(str::to_string),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let lookup_data = LookupData {
+            url: Arc::new(server.url().clone()),
+            association: Arc::new(None),
+            version: MIN_SERVER_VERSION,
+        };
+        check_compatibility(&reqwest::Client::new(), &lookup_data).await
+    }
+
+    #[tokio::test]
+    async fn test_check_published_min_client_version() {
+        assert!(matches!(
+            check_min_client_version(Some("v99.0.0")).await,
+            Compatibility::Incompatible(_)
+        ));
+        assert!(matches!(
+            check_min_client_version(None).await,
+            Compatibility::Compatible
+        ));
+
+        // Unparseable versions are ignored
+        assert!(matches!(
+            check_min_client_version(Some("not-a-version")).await,
+            Compatibility::Compatible
+        ));
+    }
+}
@@ -1,16 +1,163 @@
 use crate::ui::show_error;
-use log::debug;
+use log::{debug, LevelFilter};
 use serde::{Deserialize, Serialize};
-use std::{env::current_exe, path::PathBuf};
+use std::{env::current_exe, net::Ipv4Addr, path::PathBuf};
 
 /// Name of the file that stores saved pocket ark configuration info
 pub const CONFIG_FILE_NAME: &str = "pocket-ark-client.json";
+/// UDP port the game uses for peer connections
+pub const GAME_PEER_PORT: u16 = 3659;
 
 /// Structure of the configuration file
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
 pub struct ClientConfig {
     /// The saved connection URL to use
-    pub connection_url: String,
+    pub connection_url: Option<String>,
+    /// The channel to check for client updates on
+    pub update_channel: UpdateChannel,
+    /// Version the user has chosen to not be asked about updating to
+    pub skipped_version: Option<String>,
+    /// Path to the game executable, saved when the game is patched
+    pub game_path: Option<PathBuf>,
+    /// Level of the client logs, overridden by the `RUST_LOG` variable
+    pub log_level: LogLevel,
+    /// Settings for capturing the traffic of the local servers
+    pub capture: CaptureConfig,
+    /// Whether the tunnel server should be used
+    pub tunnel: TunnelMode,
+    /// Settings for mapping the game ports on the router
+    pub port_mapping: PortMappingConfig,
+    /// Settings for launching the game from the client
+    pub launch: LaunchConfig,
+}
+
+/// Modes for choosing whether the tunnel server is used
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TunnelMode {
+    /// Tunnel when the server provides an association
+    #[default]
+    Auto,
+    /// Always tunnel
+    On,
+    /// Never tunnel
+    Off,
+}
+
+/// Settings for capturing the traffic of the local servers
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CaptureConfig {
+    /// Whether traffic should be captured to a file
+    pub enabled: bool,
+    /// Whether tokens and passwords should be removed from captures, only
+    /// debug builds can disable this as the captures would contain the
+    /// account credentials
+    pub redact_tokens: bool,
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            redact_tokens: true,
+        }
+    }
+}
+
+/// Settings for mapping the game ports on the router
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PortMappingConfig {
+    /// Whether ports should be mapped while connected
+    pub enabled: bool,
+    /// The UDP ports to map
+    pub ports: Vec<u16>,
+    /// Address of the NAT-PMP gateway, read from the routing table
+    /// when not set
+    pub gateway: Option<Ipv4Addr>,
+}
+
+impl Default for PortMappingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ports: vec![GAME_PEER_PORT],
+            gateway: None,
+        }
+    }
+}
+
+/// Settings for launching the game from the client
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LaunchConfig {
+    /// How the game is started
+    pub method: LaunchMethod,
+    /// Whether the game should be launched once logged in
+    pub on_login: bool,
+    /// Whether the client should disconnect and close when the game exits
+    pub close_on_exit: bool,
+}
+
+/// Ways of starting the game
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LaunchMethod {
+    /// Through Steam for Steam installs, otherwise the game executable
+    #[default]
+    Auto,
+    /// Through the Steam launcher URI
+    Steam,
+    /// By running the game executable
+    Exe,
+}
+
+/// Release channels the client can be updated from
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UpdateChannel {
+    /// Only stable releases
+    #[default]
+    Stable,
+    /// Stable and pre-releases
+    Beta,
+    /// Updates are not checked for
+    Disabled,
+}
+
+/// Levels the client can log at
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    /// Logging disabled
+    Off,
+    /// Only errors
+    Error,
+    /// Warnings and errors
+    Warn,
+    /// General information
+    Info,
+    /// Details useful for debugging
+    #[default]
+    Debug,
+    /// Everything
+    Trace,
+}
+
+impl LogLevel {
+    /// Converts the level into a [`LevelFilter`]
+    pub fn to_filter(self) -> LevelFilter {
+        match self {
+            LogLevel::Off => LevelFilter::Off,
+            LogLevel::Error => LevelFilter::Error,
+            LogLevel::Warn => LevelFilter::Warn,
+            LogLevel::Info => LevelFilter::Info,
+            LogLevel::Debug => LevelFilter::Debug,
+            LogLevel::Trace => LevelFilter::Trace,
+        }
+    }
 }
 
 /// Provides a [`PathBuf`] to the configuration file
@@ -67,3 +214,15 @@ pub fn write_config_file(config: ClientConfig) {
         show_error("Failed to save client config", &err.to_string());
     }
 }
+
+/// Reads the current config file, applies the changes from `update`
+/// and writes the result back to the config file. Used so that changing
+/// one setting doesn't discard the others
+pub fn update_config_file<F>(update: F)
+where
+    F: FnOnce(&mut ClientConfig),
+{
+    let mut config = read_config_file().unwrap_or_default();
+    update(&mut config);
+    write_config_file(config);
+}
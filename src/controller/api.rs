@@ -0,0 +1,210 @@
+//! Abstraction over the server API, local servers and settings used
+//! by the controller so that it can be driven by a mock server
+
+use super::ControllerError;
+use crate::{
+    account::{
+        change_password, get_account, update_account, AccountError, AccountInfo,
+        ChangePasswordRequest, UpdateAccountRequest,
+    },
+    compat::{check_compatibility, Compatibility},
+    config::update_config_file,
+    core::{
+        api::{
+            create_user, login_user, lookup_server, AuthToken, CreateUserRequest, LoginUserRequest,
+            LookupData,
+        },
+        reqwest, Url,
+    },
+    servers::{start_all_servers, stop_all_servers},
+    session::SessionHandle,
+    update::source::BoxFuture,
+};
+
+/// Server API requests made by the controller
+pub trait ServerApi: Send + Sync {
+    /// Looks up the server at the provided `target` URL
+    fn lookup(&self, target: String) -> BoxFuture<'_, Result<LookupData, ControllerError>>;
+
+    /// Checks the compatibility of the looked up server
+    fn check_compatibility<'a>(
+        &'a self,
+        lookup_data: &'a LookupData,
+    ) -> BoxFuture<'a, Compatibility>;
+
+    /// Logs into an existing account
+    fn login(
+        &self,
+        base_url: Url,
+        request: LoginUserRequest,
+    ) -> BoxFuture<'_, Result<AuthToken, ControllerError>>;
+
+    /// Creates a new account
+    fn create(
+        &self,
+        base_url: Url,
+        request: CreateUserRequest,
+    ) -> BoxFuture<'_, Result<AuthToken, ControllerError>>;
+
+    /// Obtains the details of the authenticated account
+    fn account(
+        &self,
+        base_url: Url,
+        token: String,
+    ) -> BoxFuture<'_, Result<AccountInfo, ControllerError>>;
+
+    /// Changes the details of the authenticated account
+    fn update_account(
+        &self,
+        base_url: Url,
+        token: String,
+        request: UpdateAccountRequest,
+    ) -> BoxFuture<'_, Result<AccountInfo, ControllerError>>;
+
+    /// Changes the password of the authenticated account
+    fn change_password(
+        &self,
+        base_url: Url,
+        token: String,
+        request: ChangePasswordRequest,
+    ) -> BoxFuture<'_, Result<(), ControllerError>>;
+}
+
+/// Starts and stops the local servers for a session
+pub trait ServerLauncher: Send {
+    /// Starts the servers using the provided `session`
+    fn start(&self, session: SessionHandle);
+
+    /// Stops any running servers
+    fn stop(&self);
+}
+
+/// Persists the settings changed by the controller
+pub trait SettingsStore: Send {
+    /// Saves the URL of the server that was successfully connected to
+    fn save_connection_url(&self, url: String);
+}
+
+/// [ServerApi] implementation making requests to a real server
+pub struct HttpServerApi {
+    /// The HTTP client to make requests with
+    http_client: reqwest::Client,
+}
+
+impl HttpServerApi {
+    /// Creates a new server API using the provided `http_client`
+    pub fn new(http_client: reqwest::Client) -> Self {
+        Self { http_client }
+    }
+}
+
+impl ServerApi for HttpServerApi {
+    fn lookup(&self, target: String) -> BoxFuture<'_, Result<LookupData, ControllerError>> {
+        Box::pin(async move {
+            lookup_server(self.http_client.clone(), target)
+                .await
+                .map_err(|err| ControllerError::Lookup(err.to_string()))
+        })
+    }
+
+    fn check_compatibility<'a>(
+        &'a self,
+        lookup_data: &'a LookupData,
+    ) -> BoxFuture<'a, Compatibility> {
+        Box::pin(check_compatibility(&self.http_client, lookup_data))
+    }
+
+    fn login(
+        &self,
+        base_url: Url,
+        request: LoginUserRequest,
+    ) -> BoxFuture<'_, Result<AuthToken, ControllerError>> {
+        Box::pin(async move {
+            login_user(self.http_client.clone(), base_url, request)
+                .await
+                .map_err(|err| ControllerError::Login(AccountError::from(err)))
+        })
+    }
+
+    fn create(
+        &self,
+        base_url: Url,
+        request: CreateUserRequest,
+    ) -> BoxFuture<'_, Result<AuthToken, ControllerError>> {
+        Box::pin(async move {
+            create_user(self.http_client.clone(), base_url, request)
+                .await
+                .map_err(|err| ControllerError::Create(AccountError::from(err)))
+        })
+    }
+
+    fn account(
+        &self,
+        base_url: Url,
+        token: String,
+    ) -> BoxFuture<'_, Result<AccountInfo, ControllerError>> {
+        Box::pin(async move {
+            get_account(self.http_client.clone(), base_url, token)
+                .await
+                .map_err(ControllerError::Account)
+        })
+    }
+
+    fn update_account(
+        &self,
+        base_url: Url,
+        token: String,
+        request: UpdateAccountRequest,
+    ) -> BoxFuture<'_, Result<AccountInfo, ControllerError>> {
+        Box::pin(async move {
+            update_account(self.http_client.clone(), base_url, token, request)
+                .await
+                .map_err(ControllerError::Account)
+        })
+    }
+
+    fn change_password(
+        &self,
+        base_url: Url,
+        token: String,
+        request: ChangePasswordRequest,
+    ) -> BoxFuture<'_, Result<(), ControllerError>> {
+        Box::pin(async move {
+            change_password(self.http_client.clone(), base_url, token, request)
+                .await
+                .map_err(ControllerError::Account)
+        })
+    }
+}
+
+/// [ServerLauncher] implementation running the local servers
+pub struct LocalServers {
+    /// The HTTP client to use on the servers
+    http_client: reqwest::Client,
+}
+
+impl LocalServers {
+    /// Creates a new launcher using the provided `http_client`
+    pub fn new(http_client: reqwest::Client) -> Self {
+        Self { http_client }
+    }
+}
+
+impl ServerLauncher for LocalServers {
+    fn start(&self, session: SessionHandle) {
+        start_all_servers(self.http_client.clone(), session);
+    }
+
+    fn stop(&self) {
+        stop_all_servers();
+    }
+}
+
+/// [SettingsStore] implementation saving to the client config file
+pub struct ConfigFileStore;
+
+impl SettingsStore for ConfigFileStore {
+    fn save_connection_url(&self, url: String) {
+        update_config_file(|config| config.connection_url = Some(url));
+    }
+}
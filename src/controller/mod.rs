@@ -0,0 +1,616 @@
+//! Front-end agnostic controller owning the connect, login, create,
+//! running and disconnect state transitions. Front-ends drive the
+//! controller by running the futures it provides and applying the
+//! resulting [Transition] once they complete
+
+use self::api::{
+    ConfigFileStore, HttpServerApi, LocalServers, ServerApi, ServerLauncher, SettingsStore,
+};
+use crate::{
+    account::{AccountError, AccountInfo, ChangePasswordRequest, UpdateAccountRequest},
+    compat::Compatibility,
+    core::{
+        api::{AuthToken, CreateUserRequest, LoginUserRequest, LookupData},
+        reqwest, Url,
+    },
+    heartbeat::{ConnectionStatus, Credentials, Heartbeat, HeartbeatEvent},
+    session::{Session, SessionHandle},
+    target::{parse_target, TargetError},
+    update::source::BoxFuture,
+};
+use log::debug;
+use std::sync::Arc;
+use thiserror::Error;
+
+pub mod api;
+#[cfg(test)]
+mod tests;
+
+/// Errors that can occur while driving the controller
+#[derive(Debug, Clone, Error)]
+pub enum ControllerError {
+    /// The connection URL was invalid
+    #[error(transparent)]
+    InvalidTarget(#[from] TargetError),
+    /// Failed to lookup the server
+    #[error("{0}")]
+    Lookup(String),
+    /// The server cannot be connected to with this client
+    #[error("{0}")]
+    Incompatible(String),
+    /// Failed to login
+    #[error("{0}")]
+    Login(AccountError),
+    /// Failed to create an account
+    #[error("{0}")]
+    Create(AccountError),
+    /// Failed to load or change the account
+    #[error("{0}")]
+    Account(AccountError),
+    /// The action isn't possible in the current state
+    #[error("Action not available in the current state")]
+    InvalidState,
+}
+
+impl ControllerError {
+    /// Title to show the error to the user with, [None] for errors
+    /// that aren't shown to the user
+    pub fn title(&self) -> Option<&'static str> {
+        Some(match self {
+            ControllerError::InvalidTarget(_) | ControllerError::Lookup(_) => "Failed to connect",
+            ControllerError::Incompatible(_) => "Incompatible server",
+            ControllerError::Login(_) => "Failed to login",
+            ControllerError::Create(_) => "Failed to create account",
+            ControllerError::Account(_) => "Failed to update account",
+            ControllerError::InvalidState => return None,
+        })
+    }
+}
+
+/// State of the connection to the server
+#[derive(Debug, Clone, Default)]
+pub enum ConnectionState {
+    /// Not connected to a server
+    #[default]
+    Disconnected,
+    /// Looking up the server
+    Connecting,
+    /// Connected to the server, the user must authenticate
+    Connected(LookupData),
+    /// Logging in or creating an account
+    Authenticating(LookupData),
+    /// Authenticated and running the servers
+    Running {
+        /// The connected server details
+        lookup_data: LookupData,
+        /// The current authentication token
+        token: AuthToken,
+        /// Connectivity reported by the heartbeat
+        status: ConnectionStatus,
+    },
+}
+
+/// Result of a completed controller future to be applied
+/// using [Controller::apply]
+#[derive(Debug, Clone)]
+pub enum Transition {
+    /// The server lookup and compatibility check completed, incompatible
+    /// servers complete with [ControllerError::Incompatible]
+    Connected(Result<(LookupData, Compatibility), ControllerError>),
+    /// Logging in or creating an account completed
+    Authenticated(Result<AuthToken, ControllerError>),
+    /// Loading the account details completed
+    AccountLoaded(Result<AccountInfo, ControllerError>),
+    /// Changing the account details completed
+    AccountUpdated(Result<AccountInfo, ControllerError>),
+    /// Changing the password completed with the new password
+    PasswordChanged(Result<String, ControllerError>),
+}
+
+impl Transition {
+    /// Whether the transition moves between connection states rather
+    /// than just updating details for the current state
+    pub fn changes_state(&self) -> bool {
+        matches!(
+            self,
+            Transition::Connected(_) | Transition::Authenticated(_)
+        )
+    }
+
+    /// Title and message to show the user when the transition succeeded
+    pub fn success_message(&self) -> Option<(&'static str, &'static str)> {
+        match self {
+            Transition::AccountUpdated(Ok(_)) => {
+                Some(("Account updated", "Your account details have been updated"))
+            }
+            Transition::PasswordChanged(Ok(_)) => {
+                Some(("Password changed", "Your password has been changed"))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Controller for the application state shared by the front-ends
+pub struct Controller {
+    /// API for making requests to the server
+    api: Arc<dyn ServerApi>,
+    /// Launcher for the local servers
+    launcher: Box<dyn ServerLauncher>,
+    /// Store for persisting settings
+    settings: Box<dyn SettingsStore>,
+    /// Http client for the heartbeat
+    http_client: reqwest::Client,
+    /// Current connection state
+    state: ConnectionState,
+    /// Whether to remember the connection URL
+    remember: bool,
+    /// Credentials from the last login used to login again when the token expires
+    credentials: Option<Credentials>,
+    /// Handle to the session used by the running servers
+    session: Option<SessionHandle>,
+    /// Details of the authenticated account once loaded
+    account: Option<AccountInfo>,
+    /// Compatibility warning from the last connection for the
+    /// front-end to confirm with the user
+    compatibility_warning: Option<String>,
+}
+
+impl Default for Controller {
+    fn default() -> Self {
+        Self::from_client(reqwest::Client::new(), false)
+    }
+}
+
+impl Controller {
+    /// Creates a new controller
+    ///
+    /// ## Arguments
+    /// * `api`         - API for making requests to the server
+    /// * `launcher`    - Launcher for the local servers
+    /// * `settings`    - Store for persisting settings
+    /// * `http_client` - Http client for the heartbeat
+    /// * `remember`    - Whether to remember the connection URL
+    pub fn new(
+        api: Arc<dyn ServerApi>,
+        launcher: Box<dyn ServerLauncher>,
+        settings: Box<dyn SettingsStore>,
+        http_client: reqwest::Client,
+        remember: bool,
+    ) -> Self {
+        Self {
+            api,
+            launcher,
+            settings,
+            http_client,
+            state: ConnectionState::Disconnected,
+            remember,
+            credentials: None,
+            session: None,
+            account: None,
+            compatibility_warning: None,
+        }
+    }
+
+    /// Creates a new controller using the real server API
+    /// and local servers
+    ///
+    /// ## Arguments
+    /// * `http_client` - Http client for sending requests
+    /// * `remember`    - Whether to remember the connection URL
+    pub fn from_client(http_client: reqwest::Client, remember: bool) -> Self {
+        Self::new(
+            Arc::new(HttpServerApi::new(http_client.clone())),
+            Box::new(LocalServers::new(http_client.clone())),
+            Box::new(ConfigFileStore),
+            http_client,
+            remember,
+        )
+    }
+
+    /// The current connection state
+    pub fn state(&self) -> &ConnectionState {
+        &self.state
+    }
+
+    /// Takes the compatibility warning from the last connection, the
+    /// front-end should [Controller::disconnect] if the user declines it
+    pub fn take_compatibility_warning(&mut self) -> Option<String> {
+        self.compatibility_warning.take()
+    }
+
+    /// Details of the connected server if connected
+    pub fn lookup_data(&self) -> Option<&LookupData> {
+        match &self.state {
+            ConnectionState::Connected(lookup_data)
+            | ConnectionState::Authenticating(lookup_data)
+            | ConnectionState::Running { lookup_data, .. } => Some(lookup_data),
+            ConnectionState::Disconnected | ConnectionState::Connecting => None,
+        }
+    }
+
+    /// Details of the authenticated account if loaded
+    pub fn account(&self) -> Option<&AccountInfo> {
+        self.account.as_ref()
+    }
+
+    /// Whether the connection URL is remembered
+    pub fn remember(&self) -> bool {
+        self.remember
+    }
+
+    /// Sets whether the connection URL should be remembered
+    pub fn set_remember(&mut self, remember: bool) {
+        self.remember = remember;
+    }
+
+    /// Starts connecting to the server at `target`, the returned future
+    /// looks up the server and checks its compatibility. Once applied any
+    /// compatibility warning is available from [Controller::take_compatibility_warning]
+    /// for the front-end to confirm with the user
+    ///
+    /// ## Arguments
+    /// * `target` - The connection URL entered by the user
+    pub fn connect(
+        &mut self,
+        target: &str,
+    ) -> Result<BoxFuture<'static, Transition>, ControllerError> {
+        if !matches!(self.state, ConnectionState::Disconnected) {
+            return Err(ControllerError::InvalidState);
+        }
+
+        let target = parse_target(target)?.to_string();
+        let api = self.api.clone();
+
+        self.state = ConnectionState::Connecting;
+
+        Ok(Box::pin(async move {
+            let result: Result<(LookupData, Compatibility), ControllerError> = async {
+                let lookup_data = api.lookup(target).await?;
+
+                match api.check_compatibility(&lookup_data).await {
+                    Compatibility::Incompatible(message) => {
+                        Err(ControllerError::Incompatible(message))
+                    }
+                    compatibility => Ok((lookup_data, compatibility)),
+                }
+            }
+            .await;
+
+            Transition::Connected(result)
+        }))
+    }
+
+    /// Starts logging into an existing account
+    ///
+    /// ## Arguments
+    /// * `email`    - The account email
+    /// * `password` - The account password
+    pub fn login(
+        &mut self,
+        email: String,
+        password: String,
+    ) -> Result<BoxFuture<'static, Transition>, ControllerError> {
+        let base_url = self.start_authenticating(&email, &password)?;
+        let api = self.api.clone();
+        let request = LoginUserRequest { email, password };
+
+        Ok(Box::pin(async move {
+            Transition::Authenticated(api.login(base_url, request).await)
+        }))
+    }
+
+    /// Starts creating a new account
+    ///
+    /// ## Arguments
+    /// * `email`    - The account email
+    /// * `username` - The account username
+    /// * `password` - The account password
+    pub fn create(
+        &mut self,
+        email: String,
+        username: String,
+        password: String,
+    ) -> Result<BoxFuture<'static, Transition>, ControllerError> {
+        let base_url = self.start_authenticating(&email, &password)?;
+        let api = self.api.clone();
+        let request = CreateUserRequest {
+            email,
+            username,
+            password,
+        };
+
+        Ok(Box::pin(async move {
+            Transition::Authenticated(api.create(base_url, request).await)
+        }))
+    }
+
+    /// Starts loading the details of the authenticated account
+    pub fn load_account(&self) -> Result<BoxFuture<'static, Transition>, ControllerError> {
+        let (base_url, token) = self.running_session()?;
+        let api = self.api.clone();
+
+        Ok(Box::pin(async move {
+            Transition::AccountLoaded(api.account(base_url, token).await)
+        }))
+    }
+
+    /// Starts changing the username and/or email of the authenticated account
+    ///
+    /// ## Arguments
+    /// * `request` - The details to change
+    pub fn update_account(
+        &self,
+        request: UpdateAccountRequest,
+    ) -> Result<BoxFuture<'static, Transition>, ControllerError> {
+        let (base_url, token) = self.running_session()?;
+        let api = self.api.clone();
+
+        Ok(Box::pin(async move {
+            Transition::AccountUpdated(api.update_account(base_url, token, request).await)
+        }))
+    }
+
+    /// Starts changing the password of the authenticated account
+    ///
+    /// ## Arguments
+    /// * `current_password` - The current account password
+    /// * `new_password`     - The new account password
+    pub fn change_password(
+        &self,
+        current_password: String,
+        new_password: String,
+    ) -> Result<BoxFuture<'static, Transition>, ControllerError> {
+        let (base_url, token) = self.running_session()?;
+        let api = self.api.clone();
+        let request = ChangePasswordRequest {
+            current_password,
+            new_password: new_password.clone(),
+        };
+
+        Ok(Box::pin(async move {
+            let result = api.change_password(base_url, token, request).await;
+            Transition::PasswordChanged(result.map(|_| new_password))
+        }))
+    }
+
+    /// Base URL and token of the running session
+    fn running_session(&self) -> Result<(Url, String), ControllerError> {
+        let ConnectionState::Running {
+            lookup_data, token, ..
+        } = &self.state
+        else {
+            return Err(ControllerError::InvalidState);
+        };
+
+        Ok((lookup_data.url.as_ref().clone(), token.to_string()))
+    }
+
+    /// Moves into the authenticating state storing the credentials,
+    /// returns the base URL of the server to authenticate with
+    fn start_authenticating(
+        &mut self,
+        email: &str,
+        password: &str,
+    ) -> Result<Url, ControllerError> {
+        let ConnectionState::Connected(lookup_data) = &self.state else {
+            return Err(ControllerError::InvalidState);
+        };
+
+        let lookup_data = lookup_data.clone();
+        let base_url = lookup_data.url.as_ref().clone();
+
+        // Kept for logging in again once the token expires
+        self.credentials = Some(Credentials {
+            email: email.to_string(),
+            password: password.to_string(),
+        });
+        self.state = ConnectionState::Authenticating(lookup_data);
+
+        Ok(base_url)
+    }
+
+    /// Applies the result of a completed controller future. Results
+    /// that no longer match the current state are ignored
+    ///
+    /// ## Arguments
+    /// * `transition` - The completed transition
+    pub fn apply(&mut self, transition: Transition) -> Result<(), ControllerError> {
+        match (transition, &self.state) {
+            (Transition::Connected(result), ConnectionState::Connecting) => match result {
+                Ok((lookup_data, compatibility)) => {
+                    self.compatibility_warning = match compatibility {
+                        Compatibility::Warning(message) => Some(message),
+                        _ => None,
+                    };
+                    self.state = ConnectionState::Connected(lookup_data);
+                    Ok(())
+                }
+                Err(err) => {
+                    self.state = ConnectionState::Disconnected;
+                    Err(err)
+                }
+            },
+            (Transition::Authenticated(result), ConnectionState::Authenticating(lookup_data)) => {
+                let lookup_data = lookup_data.clone();
+                match result {
+                    Ok(token) => {
+                        self.start_running(lookup_data, token);
+                        Ok(())
+                    }
+                    Err(err) => {
+                        self.credentials = None;
+                        self.state = ConnectionState::Connected(lookup_data);
+                        Err(err)
+                    }
+                }
+            }
+            (
+                Transition::AccountLoaded(result) | Transition::AccountUpdated(result),
+                ConnectionState::Running { .. },
+            ) => {
+                let account = result?;
+
+                // Keep the email used for logging in again up to date
+                if let Some(credentials) = &mut self.credentials {
+                    credentials.email = account.email.clone();
+                }
+
+                self.account = Some(account);
+                Ok(())
+            }
+            (Transition::PasswordChanged(result), ConnectionState::Running { .. }) => {
+                let password = result?;
+
+                // Keep the password used for logging in again up to date
+                if let Some(credentials) = &mut self.credentials {
+                    credentials.password = password;
+                }
+                Ok(())
+            }
+            // Stale result from a previous state
+            _ => Ok(()),
+        }
+    }
+
+    /// Starts or updates the servers for the authenticated session
+    fn start_running(&mut self, lookup_data: LookupData, token: AuthToken) {
+        let session = Session {
+            base_url: lookup_data.url.clone(),
+            association: lookup_data.association.clone(),
+            token: token.clone(),
+        };
+
+        match &self.session {
+            // Servers are already running, swap their session
+            Some(handle) => {
+                debug!("Updating server session");
+                handle.replace(session);
+            }
+            None => {
+                debug!("Starting servers");
+                let handle = SessionHandle::new(session);
+                self.launcher.start(handle.clone());
+                self.session = Some(handle);
+            }
+        }
+
+        // Save the connection URL
+        if self.remember {
+            self.settings
+                .save_connection_url(lookup_data.url.to_string());
+        }
+
+        self.state = ConnectionState::Running {
+            lookup_data,
+            token,
+            status: ConnectionStatus::Connected,
+        };
+    }
+
+    /// Returns to the connected state, stopping the servers
+    pub fn logout(&mut self) {
+        if self.lookup_data().is_none() {
+            return;
+        }
+
+        self.expire_session();
+    }
+
+    /// Returns to the connected state after the session expired, stopping
+    /// the servers until the user logs in again
+    fn expire_session(&mut self) {
+        let Some(lookup_data) = self.lookup_data().cloned() else {
+            return;
+        };
+
+        self.stop_servers();
+        self.credentials = None;
+        self.account = None;
+        self.state = ConnectionState::Connected(lookup_data);
+    }
+
+    /// Disconnects from the server, stopping the servers
+    pub fn disconnect(&mut self) {
+        self.stop_servers();
+        self.credentials = None;
+        self.account = None;
+        self.compatibility_warning = None;
+        self.state = ConnectionState::Disconnected;
+    }
+
+    /// Restarts the local servers for the running session, used to
+    /// recover after one of the servers stopped
+    pub fn restart_servers(&mut self) -> Result<(), ControllerError> {
+        let Some(handle) = &self.session else {
+            return Err(ControllerError::InvalidState);
+        };
+
+        debug!("Restarting servers");
+        self.launcher.start(handle.clone());
+        Ok(())
+    }
+
+    /// Stops the servers if they are running
+    fn stop_servers(&mut self) {
+        if self.session.take().is_some() {
+            debug!("Stopping servers");
+            self.launcher.stop();
+        }
+    }
+
+    /// Creates the heartbeat for the running session
+    pub fn heartbeat(&self) -> Option<Heartbeat> {
+        let ConnectionState::Running {
+            lookup_data, token, ..
+        } = &self.state
+        else {
+            return None;
+        };
+
+        Some(Heartbeat {
+            http_client: self.http_client.clone(),
+            base_url: lookup_data.url.as_ref().clone(),
+            token: token.clone(),
+            credentials: self.credentials.clone(),
+        })
+    }
+
+    /// Handles an event from the heartbeat. The heartbeat has already tried
+    /// logging in again when the session expires, the email of the expired
+    /// account is returned so the user can login again. The servers are stopped
+    /// until the user logs in again
+    ///
+    /// ## Arguments
+    /// * `event` - The heartbeat event
+    pub fn handle_heartbeat(&mut self, event: HeartbeatEvent) -> Option<String> {
+        let ConnectionState::Running { token, status, .. } = &mut self.state else {
+            return None;
+        };
+
+        match event {
+            HeartbeatEvent::TokenRefreshed(value) => {
+                // Update the token used by the running servers
+                if let Some(handle) = &self.session {
+                    handle.set_token(value.clone());
+                }
+                *token = value;
+                *status = ConnectionStatus::Connected;
+                None
+            }
+            HeartbeatEvent::Status(ConnectionStatus::AuthExpired) => {
+                let email = self
+                    .credentials
+                    .as_ref()
+                    .map(|credentials| credentials.email.clone())
+                    .unwrap_or_default();
+                self.expire_session();
+                Some(email)
+            }
+            HeartbeatEvent::Status(value) => {
+                *status = value;
+                None
+            }
+        }
+    }
+}
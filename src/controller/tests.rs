@@ -0,0 +1,440 @@
+use super::{
+    api::{ServerApi, ServerLauncher, SettingsStore},
+    ConnectionState, Controller, ControllerError,
+};
+use crate::{
+    account::{AccountError, AccountInfo, ChangePasswordRequest, UpdateAccountRequest},
+    compat::Compatibility,
+    core::{
+        api::{AuthToken, CreateUserRequest, LoginUserRequest, LookupData},
+        reqwest, Url, Version,
+    },
+    heartbeat::{ConnectionStatus, HeartbeatEvent},
+    session::SessionHandle,
+    update::source::BoxFuture,
+};
+use std::sync::{Arc, Mutex};
+
+/// Address of the mock server
+const SERVER_URL: &str = "http://127.0.0.1:5000/";
+
+/// Compatibility reported by the mock server API
+enum MockCompatibility {
+    Compatible,
+    Warning,
+    Incompatible,
+}
+
+/// [ServerApi] answering from the configured responses
+struct MockApi {
+    /// Compatibility reported for the looked up server
+    compatibility: MockCompatibility,
+    /// Token given to successful logins, logins fail without one
+    token: Mutex<Option<AuthToken>>,
+}
+
+impl MockApi {
+    fn new(compatibility: MockCompatibility, token: Option<&str>) -> Arc<Self> {
+        Arc::new(Self {
+            compatibility,
+            token: Mutex::new(token.map(AuthToken::from)),
+        })
+    }
+
+    fn set_token(&self, token: &str) {
+        *self.token.lock().unwrap() = Some(AuthToken::from(token));
+    }
+}
+
+impl ServerApi for MockApi {
+    fn lookup(&self, target: String) -> BoxFuture<'_, Result<LookupData, ControllerError>> {
+        Box::pin(async move {
+            let url =
+                Url::parse(&target).map_err(|err| ControllerError::Lookup(err.to_string()))?;
+            Ok(LookupData {
+                url: Arc::new(url),
+                association: Arc::new(Some("association".to_string())),
+                version: Version::new(0, 1, 0),
+            })
+        })
+    }
+
+    fn check_compatibility<'a>(
+        &'a self,
+        _lookup_data: &'a LookupData,
+    ) -> BoxFuture<'a, Compatibility> {
+        let compatibility = match self.compatibility {
+            MockCompatibility::Compatible => Compatibility::Compatible,
+            MockCompatibility::Warning => Compatibility::Warning("Newer server".to_string()),
+            MockCompatibility::Incompatible => {
+                Compatibility::Incompatible("Older server".to_string())
+            }
+        };
+        Box::pin(async move { compatibility })
+    }
+
+    fn login(
+        &self,
+        _base_url: Url,
+        _request: LoginUserRequest,
+    ) -> BoxFuture<'_, Result<AuthToken, ControllerError>> {
+        let token = self.token.lock().unwrap().clone();
+        Box::pin(
+            async move { token.ok_or(ControllerError::Login(AccountError::InvalidCredentials)) },
+        )
+    }
+
+    fn create(
+        &self,
+        base_url: Url,
+        request: CreateUserRequest,
+    ) -> BoxFuture<'_, Result<AuthToken, ControllerError>> {
+        self.login(
+            base_url,
+            LoginUserRequest {
+                email: request.email,
+                password: request.password,
+            },
+        )
+    }
+
+    fn account(
+        &self,
+        _base_url: Url,
+        _token: String,
+    ) -> BoxFuture<'_, Result<AccountInfo, ControllerError>> {
+        Box::pin(async { Err(ControllerError::Account(AccountError::Unsupported)) })
+    }
+
+    fn update_account(
+        &self,
+        _base_url: Url,
+        _token: String,
+        _request: UpdateAccountRequest,
+    ) -> BoxFuture<'_, Result<AccountInfo, ControllerError>> {
+        Box::pin(async { Err(ControllerError::Account(AccountError::Unsupported)) })
+    }
+
+    fn change_password(
+        &self,
+        _base_url: Url,
+        _token: String,
+        _request: ChangePasswordRequest,
+    ) -> BoxFuture<'_, Result<(), ControllerError>> {
+        Box::pin(async { Err(ControllerError::Account(AccountError::Unsupported)) })
+    }
+}
+
+/// Calls made to the mock launcher and store
+#[derive(Default)]
+struct Calls {
+    /// Number of times the servers were started
+    starts: usize,
+    /// Number of times the servers were stopped
+    stops: usize,
+    /// Session the servers were last started with
+    session: Option<SessionHandle>,
+    /// Connection URLs that were saved
+    saved_urls: Vec<String>,
+}
+
+type SharedCalls = Arc<Mutex<Calls>>;
+
+struct MockLauncher(SharedCalls);
+
+impl ServerLauncher for MockLauncher {
+    fn start(&self, session: SessionHandle) {
+        let calls = &mut *self.0.lock().unwrap();
+        calls.starts += 1;
+        calls.session = Some(session);
+    }
+
+    fn stop(&self) {
+        self.0.lock().unwrap().stops += 1;
+    }
+}
+
+struct MockStore(SharedCalls);
+
+impl SettingsStore for MockStore {
+    fn save_connection_url(&self, url: String) {
+        self.0.lock().unwrap().saved_urls.push(url);
+    }
+}
+
+fn create_controller(api: Arc<MockApi>, remember: bool) -> (Controller, SharedCalls) {
+    let calls = SharedCalls::default();
+    let controller = Controller::new(
+        api,
+        Box::new(MockLauncher(calls.clone())),
+        Box::new(MockStore(calls.clone())),
+        reqwest::Client::new(),
+        remember,
+    );
+    (controller, calls)
+}
+
+async fn connect(controller: &mut Controller) -> Result<(), ControllerError> {
+    let future = controller.connect(SERVER_URL)?;
+    controller.apply(future.await)
+}
+
+async fn login(controller: &mut Controller) -> Result<(), ControllerError> {
+    let future = controller.login("test@example.com".to_string(), "password".to_string())?;
+    controller.apply(future.await)
+}
+
+/// Token of the running session
+fn session_token(calls: &SharedCalls) -> String {
+    let calls = calls.lock().unwrap();
+    let session = calls.session.as_ref().expect("Servers not started");
+    session.current().token.to_string()
+}
+
+#[tokio::test]
+async fn test_connect_compatible() {
+    let (mut controller, _) =
+        create_controller(MockApi::new(MockCompatibility::Compatible, None), false);
+
+    let future = controller.connect(SERVER_URL).unwrap();
+    assert!(matches!(controller.state(), ConnectionState::Connecting));
+    controller.apply(future.await).unwrap();
+
+    assert!(matches!(controller.state(), ConnectionState::Connected(_)));
+    assert!(controller.take_compatibility_warning().is_none());
+
+    // Connecting again requires disconnecting first
+    assert!(matches!(
+        controller.connect(SERVER_URL),
+        Err(ControllerError::InvalidState)
+    ));
+}
+
+#[tokio::test]
+async fn test_connect_incompatible() {
+    let (mut controller, _) =
+        create_controller(MockApi::new(MockCompatibility::Incompatible, None), false);
+
+    let err = connect(&mut controller).await.unwrap_err();
+
+    assert!(matches!(&err, ControllerError::Incompatible(message) if message == "Older server"));
+    assert_eq!(err.title(), Some("Incompatible server"));
+    assert!(matches!(controller.state(), ConnectionState::Disconnected));
+}
+
+#[tokio::test]
+async fn test_connect_warning() {
+    let (mut controller, _) =
+        create_controller(MockApi::new(MockCompatibility::Warning, None), false);
+
+    connect(&mut controller).await.unwrap();
+
+    // The warning is left for the front-end to confirm once
+    assert!(matches!(controller.state(), ConnectionState::Connected(_)));
+    assert_eq!(
+        controller.take_compatibility_warning().as_deref(),
+        Some("Newer server")
+    );
+    assert!(controller.take_compatibility_warning().is_none());
+}
+
+#[tokio::test]
+async fn test_connect_invalid_target() {
+    let (mut controller, _) =
+        create_controller(MockApi::new(MockCompatibility::Compatible, None), false);
+
+    let err = controller.connect("").err().expect("Empty target accepted");
+
+    assert!(matches!(err, ControllerError::InvalidTarget(_)));
+    assert!(matches!(controller.state(), ConnectionState::Disconnected));
+}
+
+#[tokio::test]
+async fn test_login_starts_servers() {
+    let (mut controller, calls) = create_controller(
+        MockApi::new(MockCompatibility::Compatible, Some("token")),
+        true,
+    );
+
+    connect(&mut controller).await.unwrap();
+    login(&mut controller).await.unwrap();
+
+    assert!(matches!(
+        controller.state(),
+        ConnectionState::Running { .. }
+    ));
+    assert!(controller.heartbeat().is_some());
+    assert_eq!(session_token(&calls), "token");
+
+    let calls = calls.lock().unwrap();
+    assert_eq!(calls.starts, 1);
+    assert_eq!(calls.saved_urls, [SERVER_URL]);
+}
+
+#[tokio::test]
+async fn test_login_without_remember() {
+    let (mut controller, calls) = create_controller(
+        MockApi::new(MockCompatibility::Compatible, Some("token")),
+        false,
+    );
+
+    connect(&mut controller).await.unwrap();
+    login(&mut controller).await.unwrap();
+
+    assert!(calls.lock().unwrap().saved_urls.is_empty());
+}
+
+#[tokio::test]
+async fn test_login_failure() {
+    let (mut controller, calls) =
+        create_controller(MockApi::new(MockCompatibility::Compatible, None), true);
+
+    connect(&mut controller).await.unwrap();
+    let err = login(&mut controller).await.unwrap_err();
+
+    assert!(matches!(err, ControllerError::Login(_)));
+    assert!(matches!(controller.state(), ConnectionState::Connected(_)));
+    assert!(controller.heartbeat().is_none());
+
+    let calls = calls.lock().unwrap();
+    assert_eq!(calls.starts, 0);
+    assert!(calls.saved_urls.is_empty());
+}
+
+#[tokio::test]
+async fn test_login_requires_connection() {
+    let (mut controller, _) = create_controller(
+        MockApi::new(MockCompatibility::Compatible, Some("token")),
+        false,
+    );
+
+    assert!(matches!(
+        login(&mut controller).await,
+        Err(ControllerError::InvalidState)
+    ));
+}
+
+#[tokio::test]
+async fn test_logout_stops_servers() {
+    let (mut controller, calls) = create_controller(
+        MockApi::new(MockCompatibility::Compatible, Some("token")),
+        false,
+    );
+
+    connect(&mut controller).await.unwrap();
+    login(&mut controller).await.unwrap();
+    controller.logout();
+
+    assert!(matches!(controller.state(), ConnectionState::Connected(_)));
+    assert!(controller.heartbeat().is_none());
+    assert_eq!(calls.lock().unwrap().stops, 1);
+
+    // Logging in again starts new servers
+    login(&mut controller).await.unwrap();
+    assert_eq!(calls.lock().unwrap().starts, 2);
+}
+
+#[tokio::test]
+async fn test_disconnect_stops_servers() {
+    let (mut controller, calls) = create_controller(
+        MockApi::new(MockCompatibility::Compatible, Some("token")),
+        false,
+    );
+
+    connect(&mut controller).await.unwrap();
+    login(&mut controller).await.unwrap();
+    controller.disconnect();
+
+    assert!(matches!(controller.state(), ConnectionState::Disconnected));
+    assert_eq!(calls.lock().unwrap().stops, 1);
+}
+
+#[tokio::test]
+async fn test_heartbeat_token_refreshed() {
+    let (mut controller, calls) = create_controller(
+        MockApi::new(MockCompatibility::Compatible, Some("token")),
+        false,
+    );
+
+    connect(&mut controller).await.unwrap();
+    login(&mut controller).await.unwrap();
+
+    controller.handle_heartbeat(HeartbeatEvent::Status(ConnectionStatus::Reauthenticating));
+    let expired =
+        controller.handle_heartbeat(HeartbeatEvent::TokenRefreshed(AuthToken::from("refreshed")));
+
+    assert!(expired.is_none());
+    assert!(matches!(
+        controller.state(),
+        ConnectionState::Running {
+            status: ConnectionStatus::Connected,
+            ..
+        }
+    ));
+    assert_eq!(session_token(&calls), "refreshed");
+    assert_eq!(
+        controller.heartbeat().unwrap().token.to_string(),
+        "refreshed"
+    );
+}
+
+#[tokio::test]
+async fn test_heartbeat_status() {
+    let (mut controller, _) = create_controller(
+        MockApi::new(MockCompatibility::Compatible, Some("token")),
+        false,
+    );
+
+    connect(&mut controller).await.unwrap();
+    login(&mut controller).await.unwrap();
+
+    let expired =
+        controller.handle_heartbeat(HeartbeatEvent::Status(ConnectionStatus::Unreachable));
+
+    assert!(expired.is_none());
+    assert!(matches!(
+        controller.state(),
+        ConnectionState::Running {
+            status: ConnectionStatus::Unreachable,
+            ..
+        }
+    ));
+}
+
+#[tokio::test]
+async fn test_heartbeat_auth_expired() {
+    let api = MockApi::new(MockCompatibility::Compatible, Some("token"));
+    let (mut controller, calls) = create_controller(api.clone(), false);
+
+    connect(&mut controller).await.unwrap();
+    login(&mut controller).await.unwrap();
+
+    let expired =
+        controller.handle_heartbeat(HeartbeatEvent::Status(ConnectionStatus::AuthExpired));
+
+    // The servers are stopped until the user logs in again
+    assert_eq!(expired.as_deref(), Some("test@example.com"));
+    assert!(matches!(controller.state(), ConnectionState::Connected(_)));
+    assert_eq!(calls.lock().unwrap().stops, 1);
+
+    // Logging in again starts the servers with the new token
+    api.set_token("new-token");
+    login(&mut controller).await.unwrap();
+
+    assert_eq!(calls.lock().unwrap().starts, 2);
+    assert_eq!(session_token(&calls), "new-token");
+}
+
+#[tokio::test]
+async fn test_heartbeat_ignored_when_not_running() {
+    let (mut controller, _) =
+        create_controller(MockApi::new(MockCompatibility::Compatible, None), false);
+
+    connect(&mut controller).await.unwrap();
+
+    let expired =
+        controller.handle_heartbeat(HeartbeatEvent::Status(ConnectionStatus::AuthExpired));
+
+    assert!(expired.is_none());
+    assert!(matches!(controller.state(), ConnectionState::Connected(_)));
+}
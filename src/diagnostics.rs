@@ -0,0 +1,532 @@
+//! Connection diagnostics for troubleshooting connection and login problems
+//!
+//! Runs a series of checks against the target server and the local system,
+//! producing a plain text report that can be copied and sent to support
+
+use crate::{
+    compat::{check_compatibility, Compatibility},
+    core::{
+        api::lookup_server,
+        reqwest,
+        servers::{BLAZE_PORT, HTTP_PORT, QOS_PORT, REDIRECTOR_PORT},
+    },
+    hosts::{HostEntryGuard, HOSTS_PATH},
+    patch::{get_patch_status, PatchStatus},
+    target::parse_target,
+    APP_VERSION,
+};
+use openssl::ssl::{SslConnector, SslMethod, SslVerifyMode};
+use std::{
+    fmt::{self, Display},
+    net::{Ipv4Addr, SocketAddr, TcpStream as StdTcpStream},
+    path::PathBuf,
+    time::Duration,
+};
+use tokio::{
+    net::{lookup_host, TcpListener, TcpStream, UdpSocket},
+    task::spawn_blocking,
+    time::timeout,
+};
+
+/// Time allowed for each network check
+pub const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Outcome of a single diagnostic check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStatus {
+    /// The check passed
+    Pass,
+    /// The check found a possible problem
+    Warn,
+    /// The check failed
+    Fail,
+    /// The check couldn't be run because an earlier check failed
+    Skipped,
+}
+
+impl Display for CheckStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CheckStatus::Pass => "PASS",
+            CheckStatus::Warn => "WARN",
+            CheckStatus::Fail => "FAIL",
+            CheckStatus::Skipped => "SKIP",
+        })
+    }
+}
+
+/// Result of a single diagnostic check
+#[derive(Debug, Clone)]
+pub struct DiagnosticCheck {
+    /// Name of the check
+    pub name: &'static str,
+    /// Outcome of the check
+    pub status: CheckStatus,
+    /// Details about the outcome
+    pub detail: String,
+}
+
+/// Report containing the result of all the diagnostic checks
+#[derive(Debug, Clone, Default)]
+pub struct DiagnosticsReport {
+    /// The target that was diagnosed
+    pub target: String,
+    /// The results of each check
+    pub checks: Vec<DiagnosticCheck>,
+}
+
+impl DiagnosticsReport {
+    /// Adds a check result to the report
+    fn push(&mut self, name: &'static str, status: CheckStatus, detail: impl Into<String>) {
+        self.checks.push(DiagnosticCheck {
+            name,
+            status,
+            detail: detail.into(),
+        });
+    }
+
+    /// Adds a skipped check to the report
+    fn skip(&mut self, name: &'static str) {
+        self.push(name, CheckStatus::Skipped, "Skipped due to earlier failure");
+    }
+}
+
+impl Display for DiagnosticsReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Pocket Ark Client Diagnostics")?;
+        writeln!(f, "Client version: v{}", APP_VERSION)?;
+        writeln!(
+            f,
+            "Platform: {} {}",
+            std::env::consts::OS,
+            std::env::consts::ARCH
+        )?;
+        writeln!(f, "Target: {}", self.target)?;
+        writeln!(f)?;
+
+        for check in &self.checks {
+            writeln!(f, "[{}] {}: {}", check.status, check.name, check.detail)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Runs all the diagnostic checks against the provided `target`
+/// connection URL producing a report
+///
+/// ## Arguments
+/// * `http_client` - The HTTP client to use for requests
+/// * `target`      - The connection URL to diagnose
+/// * `game_path`   - Path to the game executable if known
+pub async fn run_diagnostics(
+    http_client: reqwest::Client,
+    target: String,
+    game_path: Option<PathBuf>,
+) -> DiagnosticsReport {
+    let mut report = DiagnosticsReport {
+        target: target.clone(),
+        checks: Vec::new(),
+    };
+
+    check_local_system(&mut report, game_path);
+    check_local_ports(&mut report).await;
+    check_server(&mut report, http_client, &target).await;
+
+    report
+}
+
+/// Checks the hosts file entry and the game patch status
+fn check_local_system(report: &mut DiagnosticsReport, game_path: Option<PathBuf>) {
+    if HostEntryGuard::is_entry_active() {
+        report.push("Hosts entry", CheckStatus::Pass, "Redirect entry is active");
+    } else {
+        report.push(
+            "Hosts entry",
+            CheckStatus::Fail,
+            format!(
+                "Redirect entry is missing from {}, run the client as admin",
+                HOSTS_PATH
+            ),
+        );
+    }
+
+    match game_path {
+        Some(path) => {
+            let (status, detail) = match get_patch_status(&path) {
+                PatchStatus::Patched => (CheckStatus::Pass, "Game is patched"),
+                PatchStatus::Unpatched => (CheckStatus::Fail, "Game is not patched"),
+                PatchStatus::MissingGame => (CheckStatus::Warn, "Game executable was not found"),
+                PatchStatus::Unknown => (CheckStatus::Warn, "Patch file is not recognized"),
+            };
+            report.push(
+                "Game patch",
+                status,
+                format!("{} ({})", detail, path.display()),
+            );
+        }
+        None => report.push(
+            "Game patch",
+            CheckStatus::Warn,
+            "Game path unknown, patch the game from the client to record it",
+        ),
+    }
+}
+
+/// Checks whether the local ports used by the servers are free
+async fn check_local_ports(report: &mut DiagnosticsReport) {
+    let tcp_ports = [
+        ("Redirector", REDIRECTOR_PORT),
+        ("Blaze", BLAZE_PORT),
+        ("HTTP", HTTP_PORT),
+    ];
+
+    let mut in_use = Vec::new();
+
+    for (name, port) in tcp_ports {
+        if TcpListener::bind((Ipv4Addr::UNSPECIFIED, port))
+            .await
+            .is_err()
+        {
+            in_use.push(format!("{} (TCP {})", name, port));
+        }
+    }
+
+    if UdpSocket::bind((Ipv4Addr::UNSPECIFIED, QOS_PORT))
+        .await
+        .is_err()
+    {
+        in_use.push(format!("QoS (UDP {})", QOS_PORT));
+    }
+
+    if in_use.is_empty() {
+        report.push("Local ports", CheckStatus::Pass, "All local ports are free");
+    } else {
+        report.push(
+            "Local ports",
+            CheckStatus::Warn,
+            format!(
+                "Ports in use: {}. This is expected while connected, otherwise \
+                another program is using them",
+                in_use.join(", ")
+            ),
+        );
+    }
+}
+
+/// Checks the DNS, TCP, TLS and lookup of the server at `target`
+async fn check_server(report: &mut DiagnosticsReport, http_client: reqwest::Client, target: &str) {
+    const NETWORK_CHECKS: [&str; 5] = [
+        "DNS",
+        "TCP connection",
+        "TLS handshake",
+        "Server lookup",
+        "Server version",
+    ];
+
+    let url = match parse_target(target) {
+        Ok(value) => {
+            report.push("Connection URL", CheckStatus::Pass, value.to_string());
+            value
+        }
+        Err(err) => {
+            report.push("Connection URL", CheckStatus::Fail, err.to_string());
+            NETWORK_CHECKS
+                .into_iter()
+                .for_each(|name| report.skip(name));
+            return;
+        }
+    };
+
+    let host = url.host_str().unwrap_or_default().to_string();
+    let port = url.port_or_known_default().unwrap_or(80);
+
+    // DNS resolution
+    let addresses: Vec<SocketAddr> = match timeout(
+        CHECK_TIMEOUT,
+        lookup_host((host.trim_matches(['[', ']']), port)),
+    )
+    .await
+    {
+        Ok(Ok(value)) => value.collect(),
+        Ok(Err(err)) => {
+            report.push("DNS", CheckStatus::Fail, err.to_string());
+            Vec::new()
+        }
+        Err(_) => {
+            report.push("DNS", CheckStatus::Fail, "Timed out");
+            Vec::new()
+        }
+    };
+
+    let Some(address) = addresses.first().copied() else {
+        if !report.checks.iter().any(|check| check.name == "DNS") {
+            report.push("DNS", CheckStatus::Fail, "No addresses found");
+        }
+        NETWORK_CHECKS[1..]
+            .iter()
+            .for_each(|name| report.skip(name));
+        return;
+    };
+
+    let addresses = addresses
+        .iter()
+        .map(|address| address.ip().to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    report.push(
+        "DNS",
+        CheckStatus::Pass,
+        format!("{} -> {}", host, addresses),
+    );
+
+    // TCP reachability
+    match timeout(CHECK_TIMEOUT, TcpStream::connect(address)).await {
+        Ok(Ok(_)) => report.push(
+            "TCP connection",
+            CheckStatus::Pass,
+            format!("Connected to {}", address),
+        ),
+        Ok(Err(err)) => {
+            report.push("TCP connection", CheckStatus::Fail, err.to_string());
+            NETWORK_CHECKS[2..]
+                .iter()
+                .for_each(|name| report.skip(name));
+            return;
+        }
+        Err(_) => {
+            report.push("TCP connection", CheckStatus::Fail, "Timed out");
+            NETWORK_CHECKS[2..]
+                .iter()
+                .for_each(|name| report.skip(name));
+            return;
+        }
+    }
+
+    // TLS handshake
+    if url.scheme() == "https" {
+        let result = spawn_blocking(move || check_tls(address, &host)).await;
+        match result {
+            Ok(Ok(detail)) => report.push("TLS handshake", CheckStatus::Pass, detail),
+            Ok(Err(err)) => report.push("TLS handshake", CheckStatus::Fail, err),
+            Err(err) => report.push("TLS handshake", CheckStatus::Fail, err.to_string()),
+        }
+    } else {
+        report.push(
+            "TLS handshake",
+            CheckStatus::Skipped,
+            "Server doesn't use HTTPS",
+        );
+    }
+
+    // Server lookup
+    let lookup_data = match lookup_server(http_client.clone(), url.to_string()).await {
+        Ok(value) => value,
+        Err(err) => {
+            report.push("Server lookup", CheckStatus::Fail, err.to_string());
+            report.skip("Server version");
+            return;
+        }
+    };
+
+    report.push(
+        "Server lookup",
+        CheckStatus::Pass,
+        format!(
+            "Found server at {} (association {})",
+            lookup_data.url,
+            if lookup_data.association.is_some() {
+                "supported"
+            } else {
+                "unsupported"
+            }
+        ),
+    );
+
+    // Server version compatibility
+    let (status, detail) = match check_compatibility(&http_client, &lookup_data).await {
+        Compatibility::Compatible => (CheckStatus::Pass, "Compatible".to_string()),
+        Compatibility::Warning(message) => (CheckStatus::Warn, message),
+        Compatibility::Incompatible(message) => (CheckStatus::Fail, message),
+    };
+    report.push(
+        "Server version",
+        status,
+        format!("v{}: {}", lookup_data.version, detail),
+    );
+}
+
+/// Performs a TLS handshake with the server at `address`, certificate
+/// verification is reported but doesn't fail the check
+fn check_tls(address: SocketAddr, host: &str) -> Result<String, String> {
+    let stream =
+        StdTcpStream::connect_timeout(&address, CHECK_TIMEOUT).map_err(|err| err.to_string())?;
+    stream
+        .set_read_timeout(Some(CHECK_TIMEOUT))
+        .map_err(|err| err.to_string())?;
+
+    let mut builder = SslConnector::builder(SslMethod::tls()).map_err(|err| err.to_string())?;
+    // Verification result is checked after the handshake instead
+    builder.set_verify(SslVerifyMode::NONE);
+    let connector = builder.build();
+
+    let stream = connector
+        .connect(host, stream)
+        .map_err(|err| err.to_string())?;
+    let ssl = stream.ssl();
+
+    let version = ssl.version_str();
+    let verify = ssl.verify_result();
+
+    Ok(format!(
+        "{} with {} (certificate: {})",
+        version,
+        ssl.current_cipher()
+            .map(|cipher| cipher.name())
+            .unwrap_or("unknown cipher"),
+        verify.error_string()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_server, CheckStatus, DiagnosticsReport};
+    use crate::{
+        core::reqwest,
+        mock::{MockConfig, MockServer},
+    };
+    use std::net::{Ipv4Addr, TcpListener};
+
+    /// Runs the server checks against `target`
+    async fn diagnose(target: &str) -> DiagnosticsReport {
+        let mut report = DiagnosticsReport {
+            target: target.to_string(),
+            checks: Vec::new(),
+        };
+        check_server(&mut report, reqwest::Client::new(), target).await;
+        report
+    }
+
+    /// Asserts the status of each check in the `report`
+    fn assert_statuses(report: &DiagnosticsReport, expected: &[(&str, CheckStatus)]) {
+        let statuses: Vec<_> = report
+            .checks
+            .iter()
+            .map(|check| (check.name, check.status))
+            .collect();
+        assert_eq!(statuses, expected, "{}", report);
+    }
+
+    #[tokio::test]
+    async fn test_report_invalid_url() {
+        let report = diagnose("ftp://localhost").await;
+        assert_statuses(
+            &report,
+            &[
+                ("Connection URL", CheckStatus::Fail),
+                ("DNS", CheckStatus::Skipped),
+                ("TCP connection", CheckStatus::Skipped),
+                ("TLS handshake", CheckStatus::Skipped),
+                ("Server lookup", CheckStatus::Skipped),
+                ("Server version", CheckStatus::Skipped),
+            ],
+        );
+    }
+
+    #[tokio::test]
+    async fn test_report_dns_failure() {
+        // The .invalid TLD never resolves
+        let report = diagnose("http://pocket-ark.invalid").await;
+        assert_statuses(
+            &report,
+            &[
+                ("Connection URL", CheckStatus::Pass),
+                ("DNS", CheckStatus::Fail),
+                ("TCP connection", CheckStatus::Skipped),
+                ("TLS handshake", CheckStatus::Skipped),
+                ("Server lookup", CheckStatus::Skipped),
+                ("Server version", CheckStatus::Skipped),
+            ],
+        );
+    }
+
+    #[tokio::test]
+    async fn test_report_tcp_refused() {
+        // Nothing is listening once the listener is dropped
+        let port = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        let report = diagnose(&format!("http://127.0.0.1:{}", port)).await;
+        assert_statuses(
+            &report,
+            &[
+                ("Connection URL", CheckStatus::Pass),
+                ("DNS", CheckStatus::Pass),
+                ("TCP connection", CheckStatus::Fail),
+                ("TLS handshake", CheckStatus::Skipped),
+                ("Server lookup", CheckStatus::Skipped),
+                ("Server version", CheckStatus::Skipped),
+            ],
+        );
+    }
+
+    #[tokio::test]
+    async fn test_report_tls_error() {
+        let server = MockServer::start(MockConfig {
+            tls_failure: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let report = diagnose(server.url().as_str()).await;
+        assert_statuses(
+            &report,
+            &[
+                ("Connection URL", CheckStatus::Pass),
+                ("DNS", CheckStatus::Pass),
+                ("TCP connection", CheckStatus::Pass),
+                ("TLS handshake", CheckStatus::Fail),
+                ("Server lookup", CheckStatus::Fail),
+                ("Server version", CheckStatus::Skipped),
+            ],
+        );
+    }
+
+    /// Diagnoses a mock server reporting `version`
+    async fn diagnose_version(version: &str) -> DiagnosticsReport {
+        let server = MockServer::start(MockConfig {
+            version: version.to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        diagnose(server.url().as_str()).await
+    }
+
+    #[tokio::test]
+    async fn test_report_version() {
+        for (version, expected) in [
+            ("0.1.0", CheckStatus::Pass),
+            ("0.0.1", CheckStatus::Fail),
+            ("1.0.0", CheckStatus::Warn),
+        ] {
+            let report = diagnose_version(version).await;
+            assert_statuses(
+                &report,
+                &[
+                    ("Connection URL", CheckStatus::Pass),
+                    ("DNS", CheckStatus::Pass),
+                    ("TCP connection", CheckStatus::Pass),
+                    ("TLS handshake", CheckStatus::Skipped),
+                    ("Server lookup", CheckStatus::Pass),
+                    ("Server version", expected),
+                ],
+            );
+        }
+    }
+}
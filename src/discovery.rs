@@ -0,0 +1,242 @@
+//! LAN discovery of Pocket Ark servers
+//!
+//! The client sends a discovery query to the discovery multicast group and
+//! as a broadcast on the discovery port. Servers on the network respond
+//! directly to the sender with a JSON [`DiscoveryBeacon`] describing the server.
+//!
+//! Servers don't answer discovery queries yet so the UI entry is hidden unless
+//! built with the `lan-discovery` feature, the server side of the protocol is
+//! implemented by the responder in the tests of this module
+
+use crate::core::Url;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    io,
+    net::{Ipv4Addr, SocketAddr},
+    time::Duration,
+};
+use tokio::{
+    net::UdpSocket,
+    time::{timeout_at, Instant},
+};
+
+/// Whether LAN discovery is shown in the UI
+pub const DISCOVERY_ENABLED: bool = cfg!(feature = "lan-discovery");
+/// Multicast group servers listen for discovery queries on
+pub const DISCOVERY_GROUP: Ipv4Addr = Ipv4Addr::new(239, 255, 42, 99);
+/// Port servers listen for discovery queries on
+pub const DISCOVERY_PORT: u16 = 42099;
+/// Message sent to request servers respond with a beacon
+pub const DISCOVERY_QUERY: &[u8] = b"POCKET_ARK_DISCOVER";
+/// How long to wait for servers to respond
+pub const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Beacon sent by a server in response to a discovery query
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DiscoveryBeacon {
+    /// Display name of the server
+    pub name: String,
+    /// Version of the server
+    pub version: String,
+    /// Number of players currently connected
+    #[serde(default)]
+    pub player_count: u32,
+    /// Port the server HTTP API is available on
+    pub port: u16,
+    /// Whether the server uses HTTPS
+    #[serde(default)]
+    pub secure: bool,
+}
+
+/// Server found through LAN discovery
+#[derive(Debug, Clone)]
+pub struct DiscoveredServer {
+    /// The beacon the server responded with
+    pub beacon: DiscoveryBeacon,
+    /// Connection URL for the server
+    pub url: Url,
+}
+
+impl std::fmt::Display for DiscoveredServer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} (v{}, {} players) {}",
+            self.beacon.name, self.beacon.version, self.beacon.player_count, self.url
+        )
+    }
+}
+
+/// Creates the connection URL for a server that sent the `beacon` from `addr`
+fn beacon_url(beacon: &DiscoveryBeacon, addr: SocketAddr) -> Option<Url> {
+    let scheme = if beacon.secure { "https" } else { "http" };
+    // Format the IP as a host, wrapping IPv6 addresses in brackets
+    let host = SocketAddr::new(addr.ip(), beacon.port);
+    Url::parse(&format!("{}://{}/", scheme, host)).ok()
+}
+
+/// Discovers Pocket Ark servers on the local network, waits for
+/// [`DISCOVERY_TIMEOUT`] collecting the servers that respond
+pub async fn discover_servers() -> io::Result<Vec<DiscoveredServer>> {
+    let targets = [
+        SocketAddr::from((DISCOVERY_GROUP, DISCOVERY_PORT)),
+        // Broadcast is best effort as some networks block it
+        SocketAddr::from((Ipv4Addr::BROADCAST, DISCOVERY_PORT)),
+    ];
+
+    discover_servers_at(&targets, DISCOVERY_TIMEOUT).await
+}
+
+/// Sends the discovery query to each of the `targets` and collects the
+/// servers that respond within the `timeout`. Fails only if the query
+/// couldn't be sent to any of the targets
+///
+/// ## Arguments
+/// * `targets` - The addresses to send the query to
+/// * `timeout` - How long to wait for servers to respond
+pub async fn discover_servers_at(
+    targets: &[SocketAddr],
+    timeout: Duration,
+) -> io::Result<Vec<DiscoveredServer>> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    socket.set_broadcast(true)?;
+    socket.set_multicast_loop_v4(true)?;
+
+    debug!("Sending LAN discovery query");
+
+    let mut sent = false;
+    let mut last_error = None;
+
+    for target in targets {
+        match socket.send_to(DISCOVERY_QUERY, target).await {
+            Ok(_) => sent = true,
+            Err(err) => {
+                warn!("Failed to send LAN discovery query to {}: {}", target, err);
+                last_error = Some(err);
+            }
+        }
+    }
+
+    if let (false, Some(err)) = (sent, last_error) {
+        return Err(err);
+    }
+
+    let deadline = Instant::now() + timeout;
+    let mut servers: Vec<DiscoveredServer> = Vec::new();
+    let mut buffer = [0u8; 1024];
+
+    while let Ok(result) = timeout_at(deadline, socket.recv_from(&mut buffer)).await {
+        // Errors like ICMP unreachable replies to the broadcast only affect
+        // a single response, keep waiting for the other servers
+        let (length, addr) = match result {
+            Ok(value) => value,
+            Err(err) => {
+                debug!("Failed to receive discovery beacon: {}", err);
+                continue;
+            }
+        };
+
+        let beacon: DiscoveryBeacon = match serde_json::from_slice(&buffer[..length]) {
+            Ok(value) => value,
+            Err(err) => {
+                debug!("Ignoring invalid discovery beacon from {}: {}", addr, err);
+                continue;
+            }
+        };
+
+        let Some(url) = beacon_url(&beacon, addr) else {
+            continue;
+        };
+
+        // Servers may respond to both the multicast and broadcast query
+        if servers.iter().any(|server| server.url == url) {
+            continue;
+        }
+
+        debug!("Discovered server {} at {}", beacon.name, url);
+        servers.push(DiscoveredServer { beacon, url });
+    }
+
+    Ok(servers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{discover_servers_at, DiscoveryBeacon, DISCOVERY_QUERY};
+    use std::{
+        net::{Ipv4Addr, SocketAddr},
+        time::Duration,
+    };
+    use tokio::net::UdpSocket;
+
+    /// Server side of the discovery protocol, answers each query received
+    /// with the `replies` in order
+    async fn start_responder(replies: Vec<Vec<u8>>) -> SocketAddr {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let addr = socket.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let mut buffer = [0u8; 64];
+            while let Ok((length, addr)) = socket.recv_from(&mut buffer).await {
+                if &buffer[..length] != DISCOVERY_QUERY {
+                    continue;
+                }
+
+                for reply in &replies {
+                    let _ = socket.send_to(reply, addr).await;
+                }
+            }
+        });
+
+        addr
+    }
+
+    fn beacon(name: &str, port: u16) -> Vec<u8> {
+        serde_json::to_vec(&DiscoveryBeacon {
+            name: name.to_string(),
+            version: "0.1.0".to_string(),
+            player_count: 2,
+            port,
+            secure: false,
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_discover_loopback_server() {
+        let responder = start_responder(vec![
+            b"not a beacon".to_vec(),
+            beacon("Local", 8080),
+            // Duplicate from answering both queries
+            beacon("Local", 8080),
+        ])
+        .await;
+
+        let servers = discover_servers_at(&[responder], Duration::from_millis(500))
+            .await
+            .unwrap();
+
+        assert_eq!(servers.len(), 1);
+        assert_eq!(servers[0].beacon.name, "Local");
+        assert_eq!(servers[0].beacon.player_count, 2);
+        assert_eq!(servers[0].url.as_str(), "http://127.0.0.1:8080/");
+    }
+
+    #[tokio::test]
+    async fn test_discover_continues_after_unreachable_target() {
+        // Nothing listens on the closed port
+        let closed = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let closed_addr = closed.local_addr().unwrap();
+        drop(closed);
+
+        let responder = start_responder(vec![beacon("Local", 8080)]).await;
+
+        let targets: [SocketAddr; 2] = [closed_addr, responder];
+        let servers = discover_servers_at(&targets, Duration::from_millis(500))
+            .await
+            .unwrap();
+
+        assert_eq!(servers.len(), 1);
+    }
+}
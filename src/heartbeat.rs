@@ -0,0 +1,211 @@
+//! Background heartbeat for checking the server is reachable and the
+//! authentication token is still valid while running
+
+use crate::{
+    account::{get_account, AccountError},
+    core::{
+        api::{login_user, AuthToken, LoginUserRequest},
+        reqwest, Url,
+    },
+};
+use log::{debug, warn};
+use std::time::Duration;
+use tokio::time::sleep;
+
+/// Time between each heartbeat
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+/// Header the authentication token is sent in
+pub const TOKEN_HEADER: &str = "X-Token";
+
+/// Connectivity state of the connection to the server
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionStatus {
+    /// Server is reachable and the token is valid
+    Connected,
+    /// Server couldn't be reached on the last heartbeat
+    Unreachable,
+    /// Token expired and the client is logging in again
+    Reauthenticating,
+    /// Token expired and the user must login again
+    AuthExpired,
+}
+
+impl std::fmt::Display for ConnectionStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ConnectionStatus::Connected => "Server reachable",
+            ConnectionStatus::Unreachable => "Server unreachable, retrying...",
+            ConnectionStatus::Reauthenticating => "Session expired, logging in again...",
+            ConnectionStatus::AuthExpired => "Session expired, please login again",
+        })
+    }
+}
+
+/// Credentials kept in memory for logging in again once the token expires
+#[derive(Debug, Clone)]
+pub struct Credentials {
+    /// Email of the account
+    pub email: String,
+    /// Password of the account
+    pub password: String,
+}
+
+/// Events produced by the heartbeat
+#[derive(Debug, Clone)]
+pub enum HeartbeatEvent {
+    /// The connection status changed
+    Status(ConnectionStatus),
+    /// The token was refreshed by logging in again
+    TokenRefreshed(AuthToken),
+}
+
+/// Result of a single heartbeat check
+enum CheckResult {
+    /// Server reachable and token valid
+    Ok,
+    /// Server couldn't be reached or didn't accept the check
+    Unreachable,
+    /// Server rejected the token
+    TokenExpired,
+}
+
+/// Checks the server is reachable and still accepts the `token` by
+/// loading the details of the authenticated account
+///
+/// ## Arguments
+/// * `http_client` - The HTTP client to make the request with
+/// * `base_url`    - The base URL of the server
+/// * `token`       - The authentication token to check
+async fn check_session(http_client: &reqwest::Client, base_url: &Url, token: &str) -> CheckResult {
+    match get_account(http_client.clone(), base_url.clone(), token.to_string()).await {
+        Ok(_) => CheckResult::Ok,
+        Err(AccountError::InvalidCredentials) => CheckResult::TokenExpired,
+        Err(err) => {
+            warn!("Heartbeat check failed: {}", err);
+            CheckResult::Unreachable
+        }
+    }
+}
+
+/// Heartbeat for a running session
+pub struct Heartbeat {
+    /// The HTTP client to make requests with
+    pub http_client: reqwest::Client,
+    /// The base URL of the server
+    pub base_url: Url,
+    /// The current authentication token
+    pub token: AuthToken,
+    /// Credentials to login again with if available
+    pub credentials: Option<Credentials>,
+}
+
+impl Heartbeat {
+    /// Runs the heartbeat until the token expires and can't be refreshed,
+    /// reporting changes through `on_event`. When the token expires the
+    /// credentials are used to login again if present
+    ///
+    /// ## Arguments
+    /// * `on_event` - Callback for heartbeat events
+    pub async fn run<F>(self, mut on_event: F)
+    where
+        F: FnMut(HeartbeatEvent),
+    {
+        let Heartbeat {
+            http_client,
+            base_url,
+            mut token,
+            credentials,
+        } = self;
+
+        let mut last_status = ConnectionStatus::Connected;
+        let mut set_status = |status: ConnectionStatus, on_event: &mut F| {
+            if status != last_status {
+                debug!("Connection status changed: {:?}", status);
+                last_status = status;
+                on_event(HeartbeatEvent::Status(status));
+            }
+        };
+
+        loop {
+            sleep(HEARTBEAT_INTERVAL).await;
+
+            match check_session(&http_client, &base_url, &token).await {
+                CheckResult::Ok => set_status(ConnectionStatus::Connected, &mut on_event),
+                CheckResult::Unreachable => {
+                    set_status(ConnectionStatus::Unreachable, &mut on_event)
+                }
+                CheckResult::TokenExpired => {
+                    let Some(credentials) = &credentials else {
+                        set_status(ConnectionStatus::AuthExpired, &mut on_event);
+                        return;
+                    };
+
+                    set_status(ConnectionStatus::Reauthenticating, &mut on_event);
+
+                    let request = LoginUserRequest {
+                        email: credentials.email.clone(),
+                        password: credentials.password.clone(),
+                    };
+
+                    match login_user(http_client.clone(), base_url.clone(), request).await {
+                        Ok(value) => {
+                            debug!("Refreshed authentication token");
+                            token = value;
+                            on_event(HeartbeatEvent::TokenRefreshed(token.clone()));
+                            set_status(ConnectionStatus::Connected, &mut on_event);
+                        }
+                        Err(err) => {
+                            warn!("Failed to refresh authentication token: {}", err);
+                            set_status(ConnectionStatus::AuthExpired, &mut on_event);
+                            return;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_session, CheckResult};
+    use crate::{
+        core::reqwest::{self, StatusCode},
+        mock::{MockConfig, MockServer, MOCK_TOKEN},
+    };
+
+    #[tokio::test]
+    async fn test_check_session() {
+        let server = MockServer::start(MockConfig::default()).await.unwrap();
+        let http_client = reqwest::Client::new();
+        let url = server.url();
+
+        assert!(matches!(
+            check_session(&http_client, url, MOCK_TOKEN).await,
+            CheckResult::Ok
+        ));
+        assert!(matches!(
+            check_session(&http_client, url, "expired-token").await,
+            CheckResult::TokenExpired
+        ));
+
+        // Only successful responses pass the check
+        for status in [StatusCode::NOT_FOUND, StatusCode::INTERNAL_SERVER_ERROR] {
+            let server = MockServer::start(MockConfig {
+                account_failure: Some(status),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+            assert!(
+                matches!(
+                    check_session(&http_client, server.url(), MOCK_TOKEN).await,
+                    CheckResult::Unreachable
+                ),
+                "{} passed the check",
+                status
+            );
+        }
+    }
+}
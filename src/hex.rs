@@ -0,0 +1,51 @@
+//! Hex encoding shared by the update checksums and traffic captures
+
+/// Encodes the provided `bytes` as lowercase hex
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(
+        String::with_capacity(bytes.len() * 2),
+        |mut output, byte| {
+            output.push_str(&format!("{:02x}", byte));
+            output
+        },
+    )
+}
+
+/// Decodes lowercase or uppercase hex into bytes, [None] if the
+/// hex is malformed. Only used for replaying captures
+#[cfg(debug_assertions)]
+pub fn from_hex(value: &str) -> Option<Vec<u8>> {
+    if !value.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..value.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(value.get(index..index + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::to_hex;
+
+    #[test]
+    fn test_to_hex() {
+        assert_eq!(to_hex(&[0x00, 0x0f, 0xa0, 0xff]), "000fa0ff");
+        assert_eq!(to_hex(&[]), "");
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    fn test_from_hex() {
+        use super::from_hex;
+
+        let bytes = [0x00, 0x0f, 0xa0, 0xff];
+        assert_eq!(from_hex("000fa0ff").unwrap(), bytes);
+        assert_eq!(from_hex("000FA0FF").unwrap(), bytes);
+
+        assert!(from_hex("abc").is_none());
+        assert!(from_hex("zz").is_none());
+        assert!(from_hex("é0").is_none());
+    }
+}
@@ -63,6 +63,24 @@ impl HostEntryGuard {
         }
     }
 
+    /// Leaves the entry in the hosts file instead of removing it, used
+    /// when an updated client has taken over the entry
+    pub fn hand_over(self) {
+        std::mem::forget(self);
+    }
+
+    /// Checks whether the hosts file currently contains the
+    /// redirect entry
+    pub fn is_entry_active() -> bool {
+        Self::read_hosts_file()
+            .map(|text| {
+                text.lines().any(|line| {
+                    !Self::filter_not_host_line(&line) && line.trim().starts_with(HOST_VALUE)
+                })
+            })
+            .unwrap_or(false)
+    }
+
     /// Reads the contents of the hosts file
     fn read_hosts_file() -> Result<String, HostsError> {
         let path = Path::new(HOSTS_PATH);
@@ -0,0 +1,368 @@
+//! Launching the game from the client once connected
+//!
+//! The game is started through Steam when it was installed there, otherwise
+//! the game executable is run directly which starts it through the EA app.
+//! The launched process is kept so it can be reaped once it exits, the game
+//! can still be started by a launcher so the process list is polled to find
+//! when the game starts and exits
+
+use crate::config::{ClientConfig, LaunchMethod};
+use log::{debug, error, info};
+use std::{
+    fmt::{self, Display},
+    io,
+    path::{Path, PathBuf},
+    process::{Child, Command},
+    sync::OnceLock,
+    time::Duration,
+};
+use thiserror::Error;
+use tokio::{process::Command as AsyncCommand, sync::watch, time::sleep};
+
+/// Steam app ID of Mass Effect Andromeda
+pub const STEAM_APP_ID: u32 = 1238000;
+/// Name of the game executable
+pub const GAME_EXE: &str = "MassEffectAndromeda.exe";
+
+/// Time between checks of the process list
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// Time allowed for the game to appear after launching, launchers
+/// can take a while to start and check for updates
+const START_TIMEOUT: Duration = Duration::from_secs(180);
+
+/// Errors that can occur while launching the game
+#[derive(Debug, Error)]
+pub enum LaunchError {
+    /// The game path hasn't been recorded
+    #[error("Game path unknown, patch the game from the client to record it")]
+    UnknownGamePath,
+    /// The recorded game path no longer exists
+    #[error("The game executable was not found at {0}")]
+    MissingGame(PathBuf),
+    /// The game is already running or launching
+    #[error("The game is already running")]
+    AlreadyRunning,
+    /// Failed to start the launcher or game
+    #[error("Failed to start the game: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// Status of the launched game
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub enum GameStatus {
+    /// The game hasn't been launched from the client
+    #[default]
+    NotLaunched,
+    /// Waiting for the game process to start
+    Launching,
+    /// The game process is running
+    Running,
+    /// The game process didn't start in time
+    NotStarted,
+    /// The game process has exited
+    Exited,
+}
+
+impl GameStatus {
+    /// Whether the game is starting or running
+    pub fn is_active(&self) -> bool {
+        matches!(self, GameStatus::Launching | GameStatus::Running)
+    }
+}
+
+impl Display for GameStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            GameStatus::NotLaunched => "Not launched",
+            GameStatus::Launching => "Launching...",
+            GameStatus::Running => "Running",
+            GameStatus::NotStarted => "Didn't start",
+            GameStatus::Exited => "Exited",
+        })
+    }
+}
+
+/// Sender publishing the current [GameStatus]
+fn game_status_sender() -> &'static watch::Sender<GameStatus> {
+    static SENDER: OnceLock<watch::Sender<GameStatus>> = OnceLock::new();
+    SENDER.get_or_init(|| watch::channel(GameStatus::NotLaunched).0)
+}
+
+/// Obtains the current status of the launched game
+pub fn game_status() -> GameStatus {
+    game_status_sender().borrow().clone()
+}
+
+/// Subscribes to changes in the status of the launched game
+pub fn subscribe_game_status() -> watch::Receiver<GameStatus> {
+    game_status_sender().subscribe()
+}
+
+fn set_game_status(status: GameStatus) {
+    game_status_sender().send_replace(status);
+}
+
+/// How the game will be started
+enum LaunchTarget {
+    /// Launcher URI opened through the system
+    Uri(String),
+    /// Game executable run directly
+    Exe(PathBuf),
+}
+
+/// Process started to launch the game
+struct Launched {
+    /// The started launcher or game process
+    child: Child,
+    /// Whether the process is the game itself rather than a launcher
+    is_game: bool,
+}
+
+/// Launches the game using the method from the `config` and starts
+/// watching the game process, see [game_status]
+///
+/// ## Arguments
+/// * `config` - The client config with the game path and launch method
+pub fn launch_game(config: &ClientConfig) -> Result<(), LaunchError> {
+    if game_status().is_active() {
+        return Err(LaunchError::AlreadyRunning);
+    }
+
+    let game_path = config
+        .game_path
+        .as_deref()
+        .ok_or(LaunchError::UnknownGamePath)?;
+
+    let launched = match launch_target(game_path, config.launch.method)? {
+        LaunchTarget::Uri(uri) => {
+            info!("Launching game through {}", uri);
+            Launched {
+                child: open_uri(&uri)?,
+                is_game: false,
+            }
+        }
+        LaunchTarget::Exe(path) => {
+            info!("Launching game executable {}", path.display());
+            let mut command = Command::new(&path);
+            if let Some(parent) = path.parent() {
+                command.current_dir(parent);
+            }
+            Launched {
+                child: command.spawn()?,
+                is_game: true,
+            }
+        }
+    };
+
+    set_game_status(GameStatus::Launching);
+    tokio::spawn(watch_game(launched));
+
+    Ok(())
+}
+
+/// Decides how to launch the game installed at `game_path`
+fn launch_target(game_path: &Path, method: LaunchMethod) -> Result<LaunchTarget, LaunchError> {
+    // Steam installs live in a steamapps library folder
+    let steam_install = game_path
+        .components()
+        .any(|component| component.as_os_str().eq_ignore_ascii_case("steamapps"));
+
+    match method {
+        LaunchMethod::Steam => Ok(steam_target()),
+        LaunchMethod::Auto if steam_install => Ok(steam_target()),
+        LaunchMethod::Auto | LaunchMethod::Exe => {
+            if !game_path.exists() {
+                return Err(LaunchError::MissingGame(game_path.to_path_buf()));
+            }
+            Ok(LaunchTarget::Exe(game_path.to_path_buf()))
+        }
+    }
+}
+
+fn steam_target() -> LaunchTarget {
+    LaunchTarget::Uri(format!("steam://rungameid/{}", STEAM_APP_ID))
+}
+
+/// Opens the `uri` with the handler registered on the system
+fn open_uri(uri: &str) -> io::Result<Child> {
+    let program = if cfg!(target_os = "windows") {
+        "explorer"
+    } else if cfg!(target_os = "macos") {
+        "open"
+    } else {
+        "xdg-open"
+    };
+
+    Command::new(program).arg(uri).spawn()
+}
+
+/// Waits for the game process to start then for it to exit,
+/// updating the [GameStatus]
+///
+/// ## Arguments
+/// * `launched` - The process started to launch the game
+async fn watch_game(launched: Launched) {
+    let mut launched = Some(launched);
+    let mut waited = Duration::ZERO;
+
+    while !is_game_running(&mut launched).await {
+        if waited >= START_TIMEOUT {
+            error!("Game didn't start within {}s", START_TIMEOUT.as_secs());
+            set_game_status(GameStatus::NotStarted);
+            return;
+        }
+
+        sleep(POLL_INTERVAL).await;
+        waited += POLL_INTERVAL;
+    }
+
+    debug!("Game process started");
+    set_game_status(GameStatus::Running);
+
+    while is_game_running(&mut launched).await {
+        sleep(POLL_INTERVAL).await;
+    }
+
+    info!("Game process exited");
+    set_game_status(GameStatus::Exited);
+}
+
+/// Whether the game process is running, the `launched` process is reaped
+/// once it exits so it isn't left behind for the process list to find
+async fn is_game_running(launched: &mut Option<Launched>) -> bool {
+    if let Some(process) = launched {
+        match process.child.try_wait() {
+            Ok(None) if process.is_game => return true,
+            Ok(None) => {}
+            Ok(Some(status)) => {
+                debug!("Launched process exited with {}", status);
+                *launched = None;
+            }
+            Err(err) => {
+                error!("Failed to check the launched process: {}", err);
+                *launched = None;
+            }
+        }
+    }
+
+    find_game_process().await
+}
+
+/// Whether the game process is in the process list
+#[cfg(windows)]
+async fn find_game_process() -> bool {
+    /// Stops the process list command from opening a console window
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+    let output = AsyncCommand::new("tasklist")
+        .args(["/FI", &format!("IMAGENAME eq {}", GAME_EXE), "/NH"])
+        .creation_flags(CREATE_NO_WINDOW)
+        .output()
+        .await;
+
+    match output {
+        Ok(output) => String::from_utf8_lossy(&output.stdout)
+            .to_ascii_lowercase()
+            .contains(&GAME_EXE.to_ascii_lowercase()),
+        Err(err) => {
+            error!("Failed to check for the game process: {}", err);
+            false
+        }
+    }
+}
+
+/// Whether the game process is in the process list
+#[cfg(not(windows))]
+async fn find_game_process() -> bool {
+    match AsyncCommand::new("pgrep")
+        .args(["-f", GAME_EXE])
+        .output()
+        .await
+    {
+        Ok(output) => output.status.success(),
+        Err(err) => {
+            error!("Failed to check for the game process: {}", err);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{launch_game, launch_target, LaunchError, LaunchTarget, GAME_EXE, STEAM_APP_ID};
+    use crate::config::{ClientConfig, LaunchMethod};
+    use std::{fs, path::Path};
+
+    /// Steam library install of the game, doesn't need to exist
+    const STEAM_PATH: &str =
+        "C:/Program Files (x86)/Steam/SteamApps/common/Mass Effect Andromeda/MassEffectAndromeda.exe";
+    /// EA app install of the game that doesn't exist
+    const MISSING_PATH: &str =
+        "C:/Program Files/EA Games/Mass Effect Andromeda/MassEffectAndromeda.exe";
+
+    fn is_steam(target: &LaunchTarget) -> bool {
+        matches!(target, LaunchTarget::Uri(uri) if *uri == format!("steam://rungameid/{}", STEAM_APP_ID))
+    }
+
+    #[test]
+    fn test_steam_path_detection() {
+        let steam = Path::new(STEAM_PATH);
+        assert!(is_steam(&launch_target(steam, LaunchMethod::Auto).unwrap()));
+        assert!(is_steam(
+            &launch_target(steam, LaunchMethod::Steam).unwrap()
+        ));
+
+        // Only a whole steamapps folder marks a Steam install
+        let other = Path::new("C:/Games/mysteamapps/MassEffectAndromeda.exe");
+        assert!(matches!(
+            launch_target(other, LaunchMethod::Auto),
+            Err(LaunchError::MissingGame(_))
+        ));
+    }
+
+    #[test]
+    fn test_launch_methods() {
+        let dir =
+            std::env::temp_dir().join(format!("pocket-ark-launch-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let game_path = dir.join(GAME_EXE);
+        fs::write(&game_path, []).unwrap();
+
+        let auto = launch_target(&game_path, LaunchMethod::Auto);
+        let exe = launch_target(&game_path, LaunchMethod::Exe);
+        let steam = launch_target(&game_path, LaunchMethod::Steam);
+        let _ = fs::remove_dir_all(&dir);
+
+        assert!(matches!(auto, Ok(LaunchTarget::Exe(path)) if path == game_path));
+        assert!(matches!(exe, Ok(LaunchTarget::Exe(path)) if path == game_path));
+        assert!(is_steam(&steam.unwrap()));
+
+        // Forcing the executable for a Steam install runs it directly
+        let steam_path = Path::new(STEAM_PATH);
+        assert!(matches!(
+            launch_target(steam_path, LaunchMethod::Exe),
+            Err(LaunchError::MissingGame(path)) if path == steam_path
+        ));
+    }
+
+    #[test]
+    fn test_missing_game() {
+        let missing = Path::new(MISSING_PATH);
+        for method in [LaunchMethod::Auto, LaunchMethod::Exe] {
+            assert!(matches!(
+                launch_target(missing, method),
+                Err(LaunchError::MissingGame(path)) if path == missing
+            ));
+        }
+
+        // Steam doesn't need the executable path
+        assert!(is_steam(
+            &launch_target(missing, LaunchMethod::Steam).unwrap()
+        ));
+
+        assert!(matches!(
+            launch_game(&ClientConfig::default()),
+            Err(LaunchError::UnknownGamePath)
+        ));
+    }
+}
@@ -0,0 +1,410 @@
+//! Logging to rotating files in the per-user data directory, along with
+//! an in-memory buffer of recent lines for the in-app log viewer
+//!
+//! Logs are still written to stderr through `env_logger`, which also
+//! handles level filtering so `RUST_LOG` can override the configured level
+
+use crate::config::LogLevel;
+use log::{Level, Log, Metadata, Record};
+use std::{
+    collections::VecDeque,
+    env,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::Command,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Name of the directory the client data is stored in
+pub const DATA_DIR_NAME: &str = "PocketArkClient";
+/// Name of the current log file, rotated files have a number appended
+pub const LOG_FILE_NAME: &str = "pocket-ark-client.log";
+/// Size in bytes a log file can reach before it is rotated
+pub const MAX_LOG_FILE_SIZE: u64 = 5 * 1024 * 1024;
+/// Number of rotated log files to keep
+pub const MAX_LOG_FILES: usize = 5;
+/// Number of recent lines kept for the log viewer
+pub const LOG_BUFFER_LINES: usize = 1000;
+
+/// Recent log lines for the log viewer
+static LOG_BUFFER: Mutex<VecDeque<LogLine>> = Mutex::new(VecDeque::new());
+
+/// Single formatted log line
+#[derive(Debug, Clone)]
+pub struct LogLine {
+    /// Level the line was logged at
+    pub level: Level,
+    /// The formatted line
+    pub text: String,
+}
+
+/// Provides the per-user directory the client data is stored in
+pub fn data_dir() -> PathBuf {
+    let data_dir = if cfg!(target_os = "windows") {
+        env::var_os("LOCALAPPDATA").map(|path| PathBuf::from(path).join(DATA_DIR_NAME))
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|path| {
+            PathBuf::from(path)
+                .join("Library")
+                .join("Application Support")
+                .join(DATA_DIR_NAME)
+        })
+    } else {
+        env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|path| PathBuf::from(path).join(".local/share")))
+            .map(|path| path.join(DATA_DIR_NAME))
+    };
+
+    // Fallback to next to the executable
+    data_dir.unwrap_or_else(|| {
+        env::current_exe()
+            .ok()
+            .and_then(|path| path.parent().map(Path::to_path_buf))
+            .unwrap_or_default()
+    })
+}
+
+/// Provides the per-user directory log files are written to
+pub fn log_dir() -> PathBuf {
+    data_dir().join("logs")
+}
+
+/// Log file that is rotated once it grows too large
+struct RotatingFile {
+    /// Directory containing the log files
+    dir: PathBuf,
+    /// The open log file
+    file: File,
+    /// Current size of the log file
+    size: u64,
+}
+
+impl RotatingFile {
+    /// Opens the current log file in `dir` for appending
+    fn open(dir: PathBuf) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(LOG_FILE_NAME))?;
+        let size = file.metadata()?.len();
+        Ok(Self { dir, file, size })
+    }
+
+    /// Path to the rotated log file with the provided `index`
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        self.dir.join(format!("{}.{}", LOG_FILE_NAME, index))
+    }
+
+    /// Writes the `line` rotating the log files first if
+    /// the current file would become too large
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let length = line.len() as u64 + 1;
+        if self.size > 0 && self.size + length > MAX_LOG_FILE_SIZE {
+            self.rotate()?;
+        }
+
+        writeln!(self.file, "{}", line)?;
+        self.size += length;
+        Ok(())
+    }
+
+    /// Shifts each log file up an index, removing the oldest
+    /// and starting a new current log file
+    fn rotate(&mut self) -> io::Result<()> {
+        let _ = fs::remove_file(self.rotated_path(MAX_LOG_FILES));
+        for index in (1..MAX_LOG_FILES).rev() {
+            let path = self.rotated_path(index);
+            if path.exists() {
+                fs::rename(path, self.rotated_path(index + 1))?;
+            }
+        }
+
+        let current = self.dir.join(LOG_FILE_NAME);
+        fs::rename(&current, self.rotated_path(1))?;
+
+        self.file = OpenOptions::new().create(true).append(true).open(current)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+/// Logger writing to stderr, the log files and the log buffer
+struct ClientLogger {
+    /// Logger for stderr which also decides which records are enabled
+    stderr: env_logger::Logger,
+    /// The log file if it could be opened
+    file: Option<Mutex<RotatingFile>>,
+}
+
+impl Log for ClientLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.stderr.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.stderr.matches(record) {
+            return;
+        }
+
+        self.stderr.log(record);
+
+        let text = format!(
+            "[{} {} {}] {}",
+            format_timestamp(SystemTime::now()),
+            record.level(),
+            record.target(),
+            record.args()
+        );
+
+        if let Some(file) = &self.file {
+            if let Ok(file) = &mut file.lock() {
+                let _ = file.write_line(&text);
+            }
+        }
+
+        buffer_line(LogLine {
+            level: record.level(),
+            text,
+        });
+    }
+
+    fn flush(&self) {
+        self.stderr.flush();
+        if let Some(file) = &self.file {
+            if let Ok(file) = &mut file.lock() {
+                let _ = file.file.flush();
+            }
+        }
+    }
+}
+
+/// Adds the `line` to the log buffer, dropping the oldest
+/// line once the buffer is full
+fn buffer_line(line: LogLine) {
+    if let Ok(buffer) = &mut LOG_BUFFER.lock() {
+        if buffer.len() >= LOG_BUFFER_LINES {
+            buffer.pop_front();
+        }
+        buffer.push_back(line);
+    }
+}
+
+/// Initializes logging at the configured `level`, the `RUST_LOG`
+/// environment variable takes priority over the configured level
+///
+/// ## Arguments
+/// * `level` - The configured log level
+pub fn init_logging(level: LogLevel) {
+    let stderr = env_logger::Builder::new()
+        .filter_module("pocket_ark_client", level.to_filter())
+        .parse_env(env_logger::Env::default())
+        .build();
+
+    let max_level = stderr.filter();
+
+    let file = match RotatingFile::open(log_dir()) {
+        Ok(value) => Some(Mutex::new(value)),
+        Err(err) => {
+            eprintln!("Failed to open log file: {}", err);
+            None
+        }
+    };
+
+    if log::set_boxed_logger(Box::new(ClientLogger { stderr, file })).is_ok() {
+        log::set_max_level(max_level);
+    }
+}
+
+/// Obtains the recent log lines at or above the `level` containing
+/// the `search` text, ignoring case
+///
+/// ## Arguments
+/// * `level`  - The minimum level to include
+/// * `search` - Text the lines must contain
+pub fn recent_logs(level: Level, search: &str) -> Vec<LogLine> {
+    let search = search.to_lowercase();
+    let Ok(buffer) = LOG_BUFFER.lock() else {
+        return Vec::new();
+    };
+
+    buffer
+        .iter()
+        .filter(|line| line.level <= level)
+        .filter(|line| search.is_empty() || line.text.to_lowercase().contains(&search))
+        .cloned()
+        .collect()
+}
+
+/// Joins the provided log `lines` into text for copying
+pub fn logs_text(lines: &[LogLine]) -> String {
+    lines
+        .iter()
+        .map(|line| line.text.as_str())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Opens the log directory in the system file browser
+pub fn open_log_folder() -> io::Result<()> {
+    let dir = log_dir();
+    fs::create_dir_all(&dir)?;
+
+    let program = if cfg!(target_os = "windows") {
+        "explorer"
+    } else if cfg!(target_os = "macos") {
+        "open"
+    } else {
+        "xdg-open"
+    };
+
+    Command::new(program).arg(dir).spawn()?;
+    Ok(())
+}
+
+/// Formats the provided `time` as a UTC timestamp
+/// (e.g 2023-01-31 12:00:00)
+fn format_timestamp(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map(|value| value.as_secs())
+        .unwrap_or_default();
+
+    let days = (seconds / 86400) as i64;
+    let remainder = seconds % 86400;
+    let (hour, minute, second) = (remainder / 3600, (remainder % 3600) / 60, remainder % 60);
+
+    // Convert days since the epoch into a civil date
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year, month, day, hour, minute, second
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        buffer_line, logs_text, recent_logs, LogLine, RotatingFile, LOG_FILE_NAME, MAX_LOG_FILES,
+        MAX_LOG_FILE_SIZE,
+    };
+    use log::Level;
+    use std::{fs, path::PathBuf};
+
+    /// Temporary log directory removed when dropped
+    struct TestDir(PathBuf);
+
+    impl TestDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "pocket-ark-logging-test-{}-{}",
+                std::process::id(),
+                name
+            ));
+            let _ = fs::remove_dir_all(&path);
+            Self(path)
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_rotate_log_files() {
+        let dir = TestDir::new("rotate");
+        let mut file = RotatingFile::open(dir.0.clone()).unwrap();
+
+        // Lines over half the maximum size so each file only fits one
+        let padding = "x".repeat(MAX_LOG_FILE_SIZE as usize / 2 + 1);
+        let line = |index: usize| format!("{} {}", index, padding);
+
+        let lines = MAX_LOG_FILES + 2;
+        for index in 0..lines {
+            file.write_line(&line(index)).unwrap();
+        }
+
+        let read = |path: PathBuf| {
+            let contents = fs::read_to_string(path).unwrap();
+            contents.split(' ').next().unwrap().to_string()
+        };
+
+        // Current file has the newest line with older lines shifted up an index
+        assert_eq!(read(dir.0.join(LOG_FILE_NAME)), (lines - 1).to_string());
+        for index in 1..=MAX_LOG_FILES {
+            assert_eq!(
+                read(file.rotated_path(index)),
+                (lines - 1 - index).to_string()
+            );
+        }
+
+        // Oldest file was removed rather than shifted past the limit
+        assert!(!file.rotated_path(MAX_LOG_FILES + 1).exists());
+        assert_eq!(fs::read_dir(&dir.0).unwrap().count(), MAX_LOG_FILES + 1);
+    }
+
+    #[test]
+    fn test_reopen_appends() {
+        let dir = TestDir::new("reopen");
+
+        RotatingFile::open(dir.0.clone())
+            .unwrap()
+            .write_line("first")
+            .unwrap();
+        let mut file = RotatingFile::open(dir.0.clone()).unwrap();
+        assert_eq!(file.size, "first\n".len() as u64);
+        file.write_line("second").unwrap();
+
+        let contents = fs::read_to_string(dir.0.join(LOG_FILE_NAME)).unwrap();
+        assert_eq!(contents, "first\nsecond\n");
+        assert!(!file.rotated_path(1).exists());
+    }
+
+    #[test]
+    fn test_recent_logs_filter() {
+        for (level, text) in [
+            (Level::Error, "viewer-test Connection FAILED"),
+            (Level::Warn, "viewer-test Server unreachable"),
+            (Level::Info, "viewer-test Connected to server"),
+            (Level::Debug, "viewer-test Sent heartbeat"),
+        ] {
+            buffer_line(LogLine {
+                level,
+                text: text.to_string(),
+            });
+        }
+
+        let texts = |level: Level, search: &str| {
+            let lines = recent_logs(level, search);
+            logs_text(&lines)
+        };
+
+        // Level includes lines at or above it
+        assert_eq!(
+            texts(Level::Warn, "viewer-test"),
+            "viewer-test Connection FAILED\nviewer-test Server unreachable"
+        );
+        assert_eq!(recent_logs(Level::Trace, "viewer-test").len(), 4);
+
+        // Search ignores case and applies together with the level
+        assert_eq!(
+            texts(Level::Trace, "viewer-test connect"),
+            "viewer-test Connection FAILED\nviewer-test Connected to server"
+        );
+        assert_eq!(texts(Level::Error, "viewer-test connected"), "");
+    }
+}
@@ -4,37 +4,65 @@
 )]
 #![warn(unused_crate_dependencies)]
 
-use config::read_config_file;
+use args::Args;
+use config::{config_path, read_config_file, UpdateChannel};
 use core::{api::create_http_client, api::read_client_identity, reqwest};
 use hosts::HostEntryGuard;
-use log::error;
+use log::{error, info};
 use pocket_ark_client_shared as core;
-use std::path::Path;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 use ui::show_confirm;
+use update::{create_release_source, Updater};
 
 use crate::ui::show_error;
 
+pub mod account;
+pub mod args;
+pub mod capture;
+pub mod compat;
 pub mod config;
+pub mod controller;
+pub mod diagnostics;
+pub mod discovery;
+pub mod heartbeat;
+pub mod hex;
 pub mod hosts;
+pub mod launch;
+pub mod logging;
+#[cfg(test)]
+pub mod mock;
+pub mod nat;
 pub mod patch;
+pub mod portmap;
+pub mod proxy;
 pub mod servers;
+pub mod session;
+pub mod target;
 pub mod ui;
 pub mod update;
+pub mod validation;
 
 /// Application crate version string
 pub const APP_VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Name of the file diagnostics reports are saved to
+pub const DIAGNOSTICS_FILE_NAME: &str = "pocket-ark-diagnostics.txt";
 
 fn main() {
+    // Load the config file
+    let mut config: config::ClientConfig = read_config_file().unwrap_or_default();
+
     // Initialize logging
-    env_logger::builder()
-        .filter_module("pocket_ark_client", log::LevelFilter::Debug)
-        .init();
+    logging::init_logging(config.log_level);
 
-    // Attempt to apply the hosts file modification guard
-    let _host_guard: Option<HostEntryGuard> = HostEntryGuard::apply();
+    let args = Args::from_env();
 
-    // Load the config file
-    let config: Option<config::ClientConfig> = read_config_file();
+    // Managed installs can disable update checking for this launch
+    if args.no_update_check {
+        config.update_channel = UpdateChannel::Disabled;
+    }
 
     // Load the client identity
     let identity: Option<reqwest::Identity> = load_identity();
@@ -43,10 +71,128 @@ fn main() {
     let client: reqwest::Client =
         create_http_client(identity).expect("Failed to create HTTP client");
 
-    // Initialize the UI
-    ui::init(config, client);
+    // Run diagnostics without the UI when requested
+    if let Some(target) = args.diagnose {
+        run_diagnostics(client, target, config.game_path);
+        return;
+    }
+
+    // Replay a capture without the UI when requested
+    #[cfg(debug_assertions)]
+    if let Some(capture) = args.replay {
+        run_replay(client, &capture);
+        return;
+    }
+
+    // Attempt to apply the hosts file modification guard, only needed when
+    // the UI is shown so the command line modes leave the hosts file alone
+    let host_guard: Option<HostEntryGuard> = HostEntryGuard::apply();
+
+    // Create the updater, updating is unavailable if the executable can't be found
+    let updater = match Updater::new(create_release_source(&args, client.clone())) {
+        Ok(value) => Some(Arc::new(value)),
+        Err(err) => {
+            error!("Failed to create updater: {}", err);
+            None
+        }
+    };
+
+    // Initialize the UI, startup is reported to the previous version
+    // once the UI has been created
+    ui::init(config, client, updater, args.updated);
+
+    // The updated client applied the hosts entry again and still needs it,
+    // otherwise the entry is removed as the guard is dropped
+    if update::is_replaced() {
+        if let Some(host_guard) = host_guard {
+            host_guard.hand_over();
+        }
+    }
+
+    // Remove any port mappings left when the window was closed, after
+    // any removal started by disconnecting
+    portmap::start_removal();
+    portmap::wait_for_removal();
 }
 
+/// Runs the connection diagnostics for the `target` printing and logging
+/// the report and saving it to a file next to the executable
+fn run_diagnostics(client: reqwest::Client, target: String, game_path: Option<PathBuf>) {
+    attach_console();
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("Failed building tokio runtime");
+
+    let report = runtime
+        .block_on(diagnostics::run_diagnostics(client, target, game_path))
+        .to_string();
+
+    println!("{}", report);
+    info!("Diagnostics report:\n{}", report);
+
+    let report_path = config_path().with_file_name(DIAGNOSTICS_FILE_NAME);
+    if let Err(err) = std::fs::write(&report_path, report) {
+        error!("Failed to save diagnostics report: {}", err);
+    } else {
+        println!("Saved report to {}", report_path.display());
+        info!("Saved diagnostics report to {}", report_path.display());
+    }
+}
+
+/// Replays the `capture` through the local servers printing and logging
+/// the report, exits with a failure code if the replay didn't match the
+/// capture
+#[cfg(debug_assertions)]
+fn run_replay(client: reqwest::Client, capture: &Path) {
+    attach_console();
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("Failed building tokio runtime");
+
+    match runtime.block_on(capture::replay::run_replay(client, capture)) {
+        Ok(report) => {
+            println!("{}", report);
+            if report.passed() {
+                info!("Replay report:\n{}", report);
+            } else {
+                error!("Replay report:\n{}", report);
+                std::process::exit(1);
+            }
+        }
+        Err(err) => {
+            error!("Failed to replay capture: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Attaches to the console of the parent process so the output of the
+/// command line modes is visible. Release builds on Windows use the
+/// windows subsystem which doesn't have a console of its own
+#[cfg(windows)]
+fn attach_console() {
+    /// Process ID selecting the console of the parent process
+    const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+
+    extern "system" {
+        fn AttachConsole(process_id: u32) -> i32;
+    }
+
+    // SAFETY: AttachConsole has no preconditions, it fails if the parent
+    // has no console or this process already has one
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+/// Other platforms keep the console of the parent process
+#[cfg(not(windows))]
+fn attach_console() {}
+
 /// Attempts to load an identity file if one is present
 fn load_identity() -> Option<reqwest::Identity> {
     // Load the client identity
@@ -0,0 +1,226 @@
+//! Stand-in home router on loopback for testing port mapping
+//!
+//! Answers the UPnP device description and WAN connection SOAP requests
+//! over HTTP along with NAT-PMP requests over UDP, tracking the ports
+//! that are currently mapped
+
+use crate::{
+    core::reqwest::StatusCode,
+    portmap::upnp::find_tag,
+    proxy::{read_request, write_response, ProxyRequest},
+};
+use std::{
+    collections::BTreeSet,
+    io,
+    net::{Ipv4Addr, SocketAddr},
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    io::BufReader,
+    net::{TcpListener, TcpStream, UdpSocket},
+    task::JoinHandle,
+};
+
+/// Public IP the gateway reports (TEST-NET-3)
+pub const MOCK_EXTERNAL_IP: Ipv4Addr = Ipv4Addr::new(203, 0, 113, 1);
+
+/// Path of the device description
+const DESCRIPTION_PATH: &str = "/rootDesc.xml";
+/// Path of the WAN connection control endpoint
+const CONTROL_PATH: &str = "/ctl/IPConn";
+
+/// Handle to a running stand-in gateway, the gateway is stopped
+/// when the handle is dropped
+pub struct MockGateway {
+    /// Address of the UPnP HTTP server
+    http_addr: SocketAddr,
+    /// Address of the NAT-PMP socket
+    natpmp_addr: SocketAddr,
+    /// Ports currently mapped on the gateway
+    mappings: Arc<Mutex<BTreeSet<u16>>>,
+    /// Tasks answering UPnP and NAT-PMP requests
+    tasks: [JoinHandle<()>; 2],
+}
+
+impl MockGateway {
+    /// Starts a gateway on random loopback ports
+    pub async fn start() -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+        let http_addr = listener.local_addr()?;
+        let natpmp_addr = socket.local_addr()?;
+
+        let mappings = Arc::new(Mutex::new(BTreeSet::new()));
+
+        let upnp_mappings = mappings.clone();
+        let upnp_task = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let mappings = upnp_mappings.clone();
+                tokio::spawn(async move {
+                    let _ = handle_upnp(stream, mappings).await;
+                });
+            }
+        });
+
+        let natpmp_mappings = mappings.clone();
+        let natpmp_task = tokio::spawn(async move {
+            let _ = handle_natpmp(socket, natpmp_mappings).await;
+        });
+
+        Ok(Self {
+            http_addr,
+            natpmp_addr,
+            mappings,
+            tasks: [upnp_task, natpmp_task],
+        })
+    }
+
+    /// Location of the UPnP device description
+    pub fn location(&self) -> String {
+        format!("http://{}{}", self.http_addr, DESCRIPTION_PATH)
+    }
+
+    /// Address of the NAT-PMP socket
+    pub fn natpmp_addr(&self) -> SocketAddr {
+        self.natpmp_addr
+    }
+
+    /// Whether the `port` is currently mapped
+    pub fn is_mapped(&self, port: u16) -> bool {
+        self.mappings
+            .lock()
+            .map(|mappings| mappings.contains(&port))
+            .unwrap_or_default()
+    }
+}
+
+impl Drop for MockGateway {
+    fn drop(&mut self) {
+        self.tasks.iter().for_each(JoinHandle::abort);
+    }
+}
+
+/// Answers a single UPnP HTTP request
+async fn handle_upnp(stream: TcpStream, mappings: Arc<Mutex<BTreeSet<u16>>>) -> io::Result<()> {
+    let mut stream = BufReader::new(stream);
+    let request = read_request(&mut stream).await?;
+
+    let (status, body) = match (request.method.as_str(), request.path.as_str()) {
+        ("GET", DESCRIPTION_PATH) => (StatusCode::OK, device_description()),
+        ("POST", CONTROL_PATH) => soap_response(&request, &mappings),
+        _ => (StatusCode::NOT_FOUND, String::new()),
+    };
+
+    let headers = [("Content-Type".to_string(), "text/xml".to_string())];
+    write_response(stream.get_mut(), status, &headers, body.as_bytes()).await
+}
+
+fn device_description() -> String {
+    format!(
+        "<?xml version=\"1.0\"?>\
+        <root xmlns=\"urn:schemas-upnp-org:device-1-0\"><device>\
+        <deviceType>urn:schemas-upnp-org:device:InternetGatewayDevice:1</deviceType>\
+        <serviceList><service>\
+        <serviceType>urn:schemas-upnp-org:service:WANIPConnection:1</serviceType>\
+        <controlURL>{}</controlURL>\
+        </service></serviceList></device></root>",
+        CONTROL_PATH
+    )
+}
+
+/// Handles a SOAP action updating the `mappings`
+fn soap_response(request: &ProxyRequest, mappings: &Mutex<BTreeSet<u16>>) -> (StatusCode, String) {
+    let action = request
+        .headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("SOAPAction"))
+        .and_then(|(_, value)| value.trim_matches('"').rsplit('#').next())
+        .unwrap_or_default();
+    let body = String::from_utf8_lossy(&request.body);
+    let port = find_tag(&body, "NewExternalPort").and_then(|value| value.parse::<u16>().ok());
+
+    let Ok(mut mappings) = mappings.lock() else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, String::new());
+    };
+
+    let arguments = match (action, port) {
+        ("GetExternalIPAddress", _) => format!(
+            "<NewExternalIPAddress>{}</NewExternalIPAddress>",
+            MOCK_EXTERNAL_IP
+        ),
+        ("AddPortMapping", Some(port)) => {
+            mappings.insert(port);
+            String::new()
+        }
+        ("DeletePortMapping", Some(port)) if mappings.remove(&port) => String::new(),
+        ("DeletePortMapping", Some(_)) => return soap_fault(714, "NoSuchEntryInArray"),
+        _ => return soap_fault(401, "Invalid Action"),
+    };
+
+    (
+        StatusCode::OK,
+        format!(
+            "<?xml version=\"1.0\"?>\
+            <s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\"><s:Body>\
+            <u:{action}Response xmlns:u=\"urn:schemas-upnp-org:service:WANIPConnection:1\">\
+            {arguments}</u:{action}Response></s:Body></s:Envelope>"
+        ),
+    )
+}
+
+fn soap_fault(code: u16, description: &str) -> (StatusCode, String) {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!(
+            "<?xml version=\"1.0\"?>\
+            <s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\"><s:Body>\
+            <s:Fault><faultcode>s:Client</faultcode><faultstring>UPnPError</faultstring>\
+            <detail><UPnPError xmlns=\"urn:schemas-upnp-org:control-1-0\">\
+            <errorCode>{code}</errorCode><errorDescription>{description}</errorDescription>\
+            </UPnPError></detail></s:Fault></s:Body></s:Envelope>"
+        ),
+    )
+}
+
+/// Answers NAT-PMP requests until the socket fails
+async fn handle_natpmp(socket: UdpSocket, mappings: Arc<Mutex<BTreeSet<u16>>>) -> io::Result<()> {
+    let mut buffer = [0u8; 16];
+
+    loop {
+        let (count, from) = socket.recv_from(&mut buffer).await?;
+        if count < 2 || buffer[0] != 0 || buffer[1] >= 128 {
+            continue;
+        }
+
+        // Responses start with the version, opcode, result code and epoch
+        let opcode = buffer[1];
+        let mut response = vec![0, opcode + 128, 0, 0, 0, 0, 0, 1];
+
+        match opcode {
+            0 => response.extend_from_slice(&MOCK_EXTERNAL_IP.octets()),
+            1 if count >= 12 => {
+                let port = u16::from_be_bytes([buffer[4], buffer[5]]);
+                let lifetime = &buffer[8..12];
+
+                if let Ok(mut mappings) = mappings.lock() {
+                    if lifetime == [0, 0, 0, 0] {
+                        mappings.remove(&port);
+                    } else {
+                        mappings.insert(port);
+                    }
+                }
+
+                // Internal port, external port and the granted lifetime
+                response.extend_from_slice(&buffer[4..6]);
+                response.extend_from_slice(&buffer[4..6]);
+                response.extend_from_slice(lifetime);
+            }
+            _ => {
+                // Unsupported opcode result
+                response[3] = 5;
+            }
+        }
+
+        socket.send_to(&response, from).await?;
+    }
+}
@@ -0,0 +1,384 @@
+//! Fake Pocket Ark server running in-process on loopback for testing
+//! the client without a real server
+//!
+//! The server answers the lookup, authentication, account, Blaze upgrade,
+//! tunnel and HTTP endpoints used by the client and can be configured to
+//! fail in the ways a real server can, see [MockConfig]. Only built for tests
+
+use crate::{
+    capture::packet::{PacketDecoder, PacketType, HEADER_LENGTH},
+    core::{reqwest::StatusCode, Url},
+    heartbeat::TOKEN_HEADER,
+    proxy::{read_request, write_head, write_response, ProxyRequest},
+};
+use serde_json::{json, Value};
+use std::{
+    io,
+    net::Ipv4Addr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+    time::sleep,
+};
+
+pub mod gateway;
+mod scenario;
+
+/// Identifier the server reports to the client
+pub const MOCK_IDENT: &str = "POCKET_ARK_SERVER";
+/// Version the server reports by default
+pub const MOCK_VERSION: &str = "0.1.0";
+/// Token given out to authenticated clients
+pub const MOCK_TOKEN: &str = "mock-token";
+/// Header the tunnel identifies the client with
+pub const ASSOCIATION_HEADER: &str = "x-association";
+/// Length of the tunnel frame header, the one byte index of the peer
+/// socket followed by the big-endian length of the packet
+const TUNNEL_HEADER_LENGTH: usize = 3;
+
+/// Failures the mock server can be configured with
+#[derive(Debug, Clone)]
+pub struct MockConfig {
+    /// Version the server reports, set outside the supported
+    /// range to test incompatible servers
+    pub version: String,
+    /// Minimum client version the server reports requiring
+    pub min_client_version: Option<String>,
+    /// Email of the account that can login
+    pub email: String,
+    /// Password of the account that can login
+    pub password: String,
+    /// Status code login and create requests fail with
+    pub auth_failure: Option<StatusCode>,
+    /// Status code account requests fail with
+    pub account_failure: Option<StatusCode>,
+    /// Delay before every response is sent
+    pub delay: Duration,
+    /// Whether the server URL uses HTTPS while the server doesn't speak
+    /// TLS, causing the TLS handshake to fail
+    pub tls_failure: bool,
+    /// Association the server gives out for tunnelling, [None] when
+    /// the server doesn't support tunnelling
+    pub association: Option<String>,
+}
+
+impl Default for MockConfig {
+    fn default() -> Self {
+        Self {
+            version: MOCK_VERSION.to_string(),
+            min_client_version: None,
+            email: "test@example.com".to_string(),
+            password: "password123".to_string(),
+            auth_failure: None,
+            account_failure: None,
+            delay: Duration::ZERO,
+            tls_failure: false,
+            association: None,
+        }
+    }
+}
+
+/// Request received by the mock server
+#[derive(Debug, Clone)]
+pub struct MockRequest {
+    /// The request method
+    pub method: String,
+    /// The request path and query
+    pub path: String,
+}
+
+/// Handle to a running mock server, the server is stopped when
+/// the handle is dropped
+pub struct MockServer {
+    /// URL the client should connect to
+    url: Url,
+    /// Requests the server has received
+    requests: Arc<Mutex<Vec<MockRequest>>>,
+    /// Packets the server has received through the tunnel
+    tunnel_packets: Arc<Mutex<Vec<Vec<u8>>>>,
+    /// Task accepting connections
+    task: JoinHandle<()>,
+}
+
+impl MockServer {
+    /// Starts a mock server on a random loopback port
+    ///
+    /// ## Arguments
+    /// * `config` - The failures to configure the server with
+    pub async fn start(config: MockConfig) -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+        let addr = listener.local_addr()?;
+
+        let scheme = if config.tls_failure { "https" } else { "http" };
+        let url = Url::parse(&format!("{}://{}/", scheme, addr)).map_err(io::Error::other)?;
+
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let tunnel_packets = Arc::new(Mutex::new(Vec::new()));
+        let state = Arc::new(MockState {
+            config,
+            requests: requests.clone(),
+            tunnel_packets: tunnel_packets.clone(),
+        });
+
+        let task = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let state = state.clone();
+                tokio::spawn(async move {
+                    let _ = state.handle(stream).await;
+                });
+            }
+        });
+
+        Ok(Self {
+            url,
+            requests,
+            tunnel_packets,
+            task,
+        })
+    }
+
+    /// URL the client should connect to
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// Requests the server has received so far
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.requests
+            .lock()
+            .map(|requests| requests.clone())
+            .unwrap_or_default()
+    }
+
+    /// Whether the server has received a request for `path`
+    pub fn received(&self, path: &str) -> bool {
+        self.requests()
+            .iter()
+            .any(|request| request.path.trim_start_matches('/') == path)
+    }
+
+    /// Packets the server has received through the tunnel so far
+    pub fn tunnel_packets(&self) -> Vec<Vec<u8>> {
+        self.tunnel_packets
+            .lock()
+            .map(|packets| packets.clone())
+            .unwrap_or_default()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// State shared between the mock server connections
+struct MockState {
+    /// The configured failures
+    config: MockConfig,
+    /// Requests the server has received
+    requests: Arc<Mutex<Vec<MockRequest>>>,
+    /// Packets the server has received through the tunnel
+    tunnel_packets: Arc<Mutex<Vec<Vec<u8>>>>,
+}
+
+impl MockState {
+    /// Answers a single request
+    async fn handle(&self, stream: TcpStream) -> io::Result<()> {
+        let mut stream = BufReader::new(stream);
+        let request = read_request(&mut stream).await?;
+
+        if let Ok(requests) = &mut self.requests.lock() {
+            requests.push(MockRequest {
+                method: request.method.clone(),
+                path: request.path.clone(),
+            });
+        }
+
+        sleep(self.config.delay).await;
+
+        let path = request.path.split('?').next().unwrap_or_default();
+        let path = path.trim_start_matches('/');
+
+        let (status, body) = match (request.method.as_str(), path) {
+            ("GET", "api/server") => (
+                StatusCode::OK,
+                json!({
+                    "ident": MOCK_IDENT,
+                    "version": self.config.version,
+                    "association": self.config.association,
+                    "min_client_version": self.config.min_client_version,
+                }),
+            ),
+            ("POST", "api/auth/login") => self.login(&request),
+            ("POST", "api/auth/create") => self.create(&request),
+            ("GET", "api/users/self") | ("PUT", "api/users/self/details") => self.account(&request),
+            ("PUT", "api/users/self/password") => match self.account(&request) {
+                (StatusCode::OK, _) => (StatusCode::OK, Value::Null),
+                failure => failure,
+            },
+            ("GET", "api/server/upgrade") => {
+                return self.upgrade(stream, &request).await;
+            }
+            (_, "api/server/tunnel") => {
+                return self.tunnel(stream, &request).await;
+            }
+            // Game HTTP requests proxied by the local HTTP server
+            _ => (StatusCode::OK, json!({})),
+        };
+
+        let body = match body {
+            Value::Null => Vec::new(),
+            body => body.to_string().into_bytes(),
+        };
+        let headers = [("Content-Type".to_string(), "application/json".to_string())];
+
+        write_response(stream.get_mut(), status, &headers, &body).await
+    }
+
+    /// Whether the request has the token given out by the server
+    fn is_authenticated(request: &ProxyRequest) -> bool {
+        request
+            .headers
+            .iter()
+            .any(|(name, value)| name.eq_ignore_ascii_case(TOKEN_HEADER) && value == MOCK_TOKEN)
+    }
+
+    fn login(&self, request: &ProxyRequest) -> (StatusCode, Value) {
+        if let Some(status) = self.config.auth_failure {
+            return (status, json!("Authentication failed"));
+        }
+
+        let body: Value = serde_json::from_slice(&request.body).unwrap_or_default();
+        if body["email"] != self.config.email || body["password"] != self.config.password {
+            return (StatusCode::UNAUTHORIZED, json!("Invalid credentials"));
+        }
+
+        (StatusCode::OK, json!({ "token": MOCK_TOKEN }))
+    }
+
+    fn create(&self, request: &ProxyRequest) -> (StatusCode, Value) {
+        if let Some(status) = self.config.auth_failure {
+            return (status, json!("Authentication failed"));
+        }
+
+        let body: Value = serde_json::from_slice(&request.body).unwrap_or_default();
+        if body["email"] == self.config.email {
+            return (StatusCode::CONFLICT, json!("Email already in use"));
+        }
+
+        (StatusCode::CREATED, json!({ "token": MOCK_TOKEN }))
+    }
+
+    fn account(&self, request: &ProxyRequest) -> (StatusCode, Value) {
+        if !Self::is_authenticated(request) {
+            return (StatusCode::UNAUTHORIZED, json!("Invalid token"));
+        }
+
+        if let Some(status) = self.config.account_failure {
+            return (status, json!("Account request failed"));
+        }
+
+        (
+            StatusCode::OK,
+            json!({
+                "id": 1,
+                "email": self.config.email,
+                "username": "mock",
+            }),
+        )
+    }
+
+    /// Upgrades the connection for Blaze, requests are answered with
+    /// empty responses and pings with ping replies
+    async fn upgrade(
+        &self,
+        mut stream: BufReader<TcpStream>,
+        request: &ProxyRequest,
+    ) -> io::Result<()> {
+        if !Self::is_authenticated(request) {
+            return write_response(stream.get_mut(), StatusCode::UNAUTHORIZED, &[], &[]).await;
+        }
+
+        let headers = [
+            ("Connection".to_string(), "Upgrade".to_string()),
+            ("Upgrade".to_string(), "blaze".to_string()),
+        ];
+        write_head(stream.get_mut(), StatusCode::SWITCHING_PROTOCOLS, &headers).await?;
+
+        let mut decoder = PacketDecoder::default();
+        let mut buffer = [0u8; 8192];
+
+        loop {
+            let count = stream.read(&mut buffer).await?;
+            if count == 0 {
+                return Ok(());
+            }
+            decoder.push(&buffer[..count]);
+
+            while let Some(packet) = decoder.next_packet() {
+                let reply_type = match packet.header.ty {
+                    PacketType::Request => 1,
+                    PacketType::Ping => 5,
+                    _ => continue,
+                };
+
+                // Reply with the same header and an empty body
+                let mut reply = packet.raw[..HEADER_LENGTH].to_vec();
+                reply[0..4].copy_from_slice(&0u32.to_be_bytes());
+                reply[4..6].copy_from_slice(&0u16.to_be_bytes());
+                reply[13] = reply_type << 5;
+
+                sleep(self.config.delay).await;
+                stream.write_all(&reply).await?;
+            }
+        }
+    }
+
+    /// Upgrades the connection for the tunnel acting as the remote peer,
+    /// every packet is recorded and sent back to the socket it came from
+    async fn tunnel(
+        &self,
+        mut stream: BufReader<TcpStream>,
+        request: &ProxyRequest,
+    ) -> io::Result<()> {
+        let associated = request.headers.iter().any(|(name, value)| {
+            name.eq_ignore_ascii_case(ASSOCIATION_HEADER)
+                && self.config.association.as_ref() == Some(value)
+        });
+        if !associated {
+            return write_response(stream.get_mut(), StatusCode::UNAUTHORIZED, &[], &[]).await;
+        }
+
+        let headers = [
+            ("Connection".to_string(), "Upgrade".to_string()),
+            ("Upgrade".to_string(), "tunnel".to_string()),
+        ];
+        write_head(stream.get_mut(), StatusCode::SWITCHING_PROTOCOLS, &headers).await?;
+
+        let mut header = [0u8; TUNNEL_HEADER_LENGTH];
+        loop {
+            match stream.read_exact(&mut header).await {
+                Ok(_) => {}
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(err) => return Err(err),
+            }
+
+            let length = u16::from_be_bytes([header[1], header[2]]) as usize;
+            let mut packet = vec![0u8; length];
+            stream.read_exact(&mut packet).await?;
+
+            if let Ok(packets) = &mut self.tunnel_packets.lock() {
+                packets.push(packet.clone());
+            }
+
+            // Reply to the same peer socket with the same packet
+            stream.write_all(&header).await?;
+            stream.write_all(&packet).await?;
+        }
+    }
+}
@@ -0,0 +1,332 @@
+//! End-to-end scenarios driving the client against the [MockServer]
+//!
+//! Each scenario starts its own mock server and drives the same
+//! [Controller] and API used by the UI through lookup, login,
+//! [start_all_servers] and disconnect
+//!
+//! Scenarios that start the local servers need their fixed ports so
+//! they are ignored by default and run one at a time, run them with
+//! `cargo test -- --ignored`
+//!
+//! [start_all_servers]: crate::servers::start_all_servers
+
+use super::{MockConfig, MockServer};
+use crate::{
+    compat::Compatibility,
+    config::GAME_PEER_PORT,
+    controller::{
+        api::{HttpServerApi, LocalServers, ServerApi, ServerLauncher, SettingsStore},
+        ConnectionState, Controller, ControllerError,
+    },
+    core::{
+        reqwest,
+        servers::{BLAZE_PORT, TUNNEL_HOST_PORT},
+        Version,
+    },
+    servers::{tunnel_status, TunnelStatus},
+    session::SessionHandle,
+    APP_VERSION,
+};
+use std::{
+    net::Ipv4Addr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{
+    net::{TcpStream, UdpSocket},
+    sync::{Mutex, MutexGuard},
+    time::{sleep, timeout},
+};
+
+/// Delay used by the slow response scenario
+const SLOW_DELAY: Duration = Duration::from_secs(2);
+
+/// Time given to the local servers to start or stop
+const SERVER_DELAY: Duration = Duration::from_secs(1);
+
+/// Packet sent through the tunnel by the tunnel scenario
+const TUNNEL_PACKET: &[u8] = b"mock game packet";
+
+/// Held by scenarios using the local servers as they share fixed ports
+static LOCAL_SERVERS: Mutex<()> = Mutex::const_new(());
+
+/// Launcher for scenarios that don't need the local servers
+struct NoServers;
+
+impl ServerLauncher for NoServers {
+    fn start(&self, _session: SessionHandle) {}
+
+    fn stop(&self) {}
+}
+
+/// Settings store for scenarios, nothing is persisted
+struct NoSettings;
+
+impl SettingsStore for NoSettings {
+    fn save_connection_url(&self, _url: String) {}
+}
+
+/// Starts a mock server with the provided `config`
+async fn start_mock(config: MockConfig) -> MockServer {
+    MockServer::start(config)
+        .await
+        .expect("Failed to start mock server")
+}
+
+/// Creates a controller using the same API as the UI along with
+/// the provided `launcher`
+fn create_controller(launcher: Box<dyn ServerLauncher>) -> Controller {
+    let http_client = reqwest::Client::new();
+    Controller::new(
+        Arc::new(HttpServerApi::new(http_client.clone())),
+        launcher,
+        Box::new(NoSettings),
+        http_client,
+        false,
+    )
+}
+
+/// Creates a controller using the local servers, the returned guard
+/// must be held while the servers are running
+async fn create_local_controller() -> (Controller, MutexGuard<'static, ()>) {
+    let guard = LOCAL_SERVERS.lock().await;
+    let launcher = LocalServers::new(reqwest::Client::new());
+    (create_controller(Box::new(launcher)), guard)
+}
+
+/// Connects the `controller` to the `mock` server
+async fn connect(controller: &mut Controller, mock: &MockServer) {
+    let connect = controller.connect(mock.url().as_str()).unwrap();
+    controller.apply(connect.await).expect("Failed to connect");
+}
+
+/// Logs the `controller` in with the account from the `config`
+async fn login(controller: &mut Controller, config: &MockConfig) -> Result<(), ControllerError> {
+    let login = controller
+        .login(config.email.clone(), config.password.clone())
+        .unwrap();
+    controller.apply(login.await)
+}
+
+/// Looks up the server and checks its compatibility
+async fn lookup(config: MockConfig) -> Compatibility {
+    let mock = start_mock(config).await;
+    let api = HttpServerApi::new(reqwest::Client::new());
+
+    let lookup_data = api
+        .lookup(mock.url().to_string())
+        .await
+        .expect("Failed to lookup server");
+    api.check_compatibility(&lookup_data).await
+}
+
+/// Whether the local Blaze server is accepting connections
+async fn blaze_listening() -> bool {
+    TcpStream::connect((Ipv4Addr::LOCALHOST, BLAZE_PORT))
+        .await
+        .is_ok()
+}
+
+#[tokio::test]
+async fn test_lookup() {
+    assert!(matches!(
+        lookup(MockConfig::default()).await,
+        Compatibility::Compatible
+    ));
+}
+
+#[tokio::test]
+async fn test_wrong_version() {
+    let config = MockConfig {
+        version: "0.0.1".to_string(),
+        ..MockConfig::default()
+    };
+
+    assert!(matches!(
+        lookup(config).await,
+        Compatibility::Incompatible(_)
+    ));
+}
+
+#[tokio::test]
+async fn test_client_too_old() {
+    let current = Version::parse(APP_VERSION).unwrap();
+    let config = MockConfig {
+        min_client_version: Some(Version::new(current.major + 1, 0, 0).to_string()),
+        ..MockConfig::default()
+    };
+
+    assert!(matches!(
+        lookup(config).await,
+        Compatibility::Incompatible(_)
+    ));
+}
+
+#[tokio::test]
+async fn test_slow_responses() {
+    let config = MockConfig {
+        delay: SLOW_DELAY,
+        ..MockConfig::default()
+    };
+
+    let start = Instant::now();
+    assert!(matches!(lookup(config).await, Compatibility::Compatible));
+    assert!(start.elapsed() >= SLOW_DELAY);
+}
+
+#[tokio::test]
+async fn test_tls_error() {
+    let config = MockConfig {
+        tls_failure: true,
+        ..MockConfig::default()
+    };
+    let mock = start_mock(config).await;
+    let mut controller = create_controller(Box::new(NoServers));
+
+    let connect = controller.connect(mock.url().as_str()).unwrap();
+    let result = controller.apply(connect.await);
+
+    assert!(matches!(result, Err(ControllerError::Lookup(_))));
+    assert!(matches!(controller.state(), ConnectionState::Disconnected));
+}
+
+#[tokio::test]
+async fn test_invalid_credentials() {
+    let config = MockConfig::default();
+    let mock = start_mock(config.clone()).await;
+    let mut controller = create_controller(Box::new(NoServers));
+    connect(&mut controller, &mock).await;
+
+    let wrong = MockConfig {
+        password: "wrong-password1".to_string(),
+        ..config
+    };
+
+    assert!(matches!(
+        login(&mut controller, &wrong).await,
+        Err(ControllerError::Login(_))
+    ));
+    assert!(matches!(controller.state(), ConnectionState::Connected(_)));
+}
+
+#[tokio::test]
+async fn test_auth_error() {
+    let config = MockConfig {
+        auth_failure: Some(reqwest::StatusCode::INTERNAL_SERVER_ERROR),
+        ..MockConfig::default()
+    };
+    let mock = start_mock(config.clone()).await;
+    let mut controller = create_controller(Box::new(NoServers));
+    connect(&mut controller, &mock).await;
+
+    assert!(login(&mut controller, &config).await.is_err());
+
+    // Returned to the login screen
+    assert!(matches!(controller.state(), ConnectionState::Connected(_)));
+}
+
+#[tokio::test]
+async fn test_create_account() {
+    let mock = start_mock(MockConfig::default()).await;
+    let mut controller = create_controller(Box::new(NoServers));
+    connect(&mut controller, &mock).await;
+
+    let create = controller
+        .create(
+            "new@example.com".to_string(),
+            "newuser".to_string(),
+            "password123".to_string(),
+        )
+        .unwrap();
+    controller
+        .apply(create.await)
+        .expect("Failed to create account");
+
+    assert!(matches!(
+        controller.state(),
+        ConnectionState::Running { .. }
+    ));
+    assert!(mock.received("api/auth/create"));
+}
+
+#[tokio::test]
+#[ignore = "binds the fixed local server ports"]
+async fn test_login_and_disconnect() {
+    let config = MockConfig::default();
+    let mock = start_mock(config.clone()).await;
+    let (mut controller, _guard) = create_local_controller().await;
+    connect(&mut controller, &mock).await;
+
+    login(&mut controller, &config)
+        .await
+        .expect("Failed to login");
+    assert!(matches!(
+        controller.state(),
+        ConnectionState::Running { .. }
+    ));
+
+    sleep(SERVER_DELAY).await;
+
+    // Connecting to the Blaze server as the game should upgrade
+    // a connection to the server
+    let game = TcpStream::connect((Ipv4Addr::LOCALHOST, BLAZE_PORT))
+        .await
+        .expect("Blaze server not running");
+    sleep(SERVER_DELAY).await;
+    drop(game);
+
+    let upgraded = mock.received("api/server/upgrade");
+
+    controller.disconnect();
+    sleep(SERVER_DELAY).await;
+
+    assert!(upgraded, "Blaze server didn't upgrade a connection");
+    assert!(
+        !blaze_listening().await,
+        "Blaze server still running after disconnect"
+    );
+}
+
+#[tokio::test]
+#[ignore = "binds the fixed local server ports"]
+async fn test_tunnel() {
+    let config = MockConfig {
+        association: Some("mock-association".to_string()),
+        ..MockConfig::default()
+    };
+    let mock = start_mock(config.clone()).await;
+    let (mut controller, _guard) = create_local_controller().await;
+    connect(&mut controller, &mock).await;
+
+    login(&mut controller, &config)
+        .await
+        .expect("Failed to login");
+
+    sleep(SERVER_DELAY).await;
+    assert_eq!(tunnel_status(), TunnelStatus::Running);
+
+    // The game sends to the tunnel from its peer port, the mock server
+    // sends every packet back as the remote peer
+    let game = UdpSocket::bind((Ipv4Addr::LOCALHOST, GAME_PEER_PORT))
+        .await
+        .expect("Failed to bind game socket");
+    game.send_to(TUNNEL_PACKET, (Ipv4Addr::LOCALHOST, TUNNEL_HOST_PORT))
+        .await
+        .expect("Failed to send to tunnel");
+
+    let mut buffer = [0u8; 64];
+    let received = timeout(SERVER_DELAY, game.recv(&mut buffer)).await;
+
+    controller.disconnect();
+    sleep(SERVER_DELAY).await;
+
+    assert_eq!(
+        mock.tunnel_packets(),
+        vec![TUNNEL_PACKET.to_vec()],
+        "Packet didn't reach the server through the tunnel"
+    );
+    let count = received
+        .expect("Packet didn't return through the tunnel")
+        .expect("Failed to receive from tunnel");
+    assert_eq!(&buffer[..count], TUNNEL_PACKET);
+}
@@ -0,0 +1,404 @@
+//! Classification of the NAT the player is behind
+//!
+//! After connecting the client asks the QoS endpoint of the connected server
+//! which public address and port its UDP traffic appears from. Comparing the
+//! reflected address with the local address of the socket gives the NAT type,
+//! which together with UPnP availability decides whether other players can
+//! reach this client directly or whether the tunnel is needed.
+
+use crate::{
+    core::{servers::QOS_PORT, Url},
+    portmap::upnp::discover_location,
+    servers::TunnelStatus,
+};
+use log::{debug, warn};
+use std::{
+    fmt::{self, Display},
+    io,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, OnceLock,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    net::{lookup_host, UdpSocket},
+    sync::watch,
+    time::timeout,
+};
+
+/// Number of reflection requests sent before giving up, UDP requests
+/// can be dropped so more than one is sent
+pub const REFLECTION_ATTEMPTS: usize = 3;
+/// Time allowed for each reflection request
+pub const REFLECTION_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Length of the public address the QoS endpoint appends to the echoed
+/// request, the IPv4 address followed by the big-endian port
+const REFLECTED_ADDRESS_LENGTH: usize = 6;
+
+/// Types of NAT the client can be behind
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NatType {
+    /// Not behind a NAT or the NAT can be mapped through UPnP,
+    /// other players can connect directly
+    Open,
+    /// Behind a NAT that keeps the same public port for every destination,
+    /// most players can connect directly
+    Moderate,
+    /// Behind a NAT that changes the public port per destination or
+    /// UDP is blocked, direct connections will mostly fail
+    Strict,
+    /// The server couldn't be reached to reflect the public address
+    Unknown,
+}
+
+impl Display for NatType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            NatType::Open => "Open",
+            NatType::Moderate => "Moderate",
+            NatType::Strict => "Strict",
+            NatType::Unknown => "Unknown",
+        })
+    }
+}
+
+/// Result of classifying the NAT
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NatReport {
+    /// The classified NAT type
+    pub nat_type: NatType,
+    /// Public address reported by the server if any reflection responded
+    pub public_addr: Option<SocketAddr>,
+    /// Whether a UPnP gateway responded on the local network
+    pub upnp: bool,
+}
+
+impl NatReport {
+    /// Recommendation for the player on whether multiplayer traffic
+    /// will go through the tunnel
+    ///
+    /// ## Arguments
+    /// * `tunnel` - The current status of the tunnel server
+    pub fn recommendation(&self, tunnel: &TunnelStatus) -> &'static str {
+        match (self.nat_type, tunnel) {
+            (NatType::Strict, TunnelStatus::Running) => "Multiplayer traffic will use the tunnel",
+            (NatType::Strict, TunnelStatus::Disabled) => {
+                "Direct connections will likely fail, turn tunnelling on"
+            }
+            (NatType::Strict, _) => {
+                "Direct connections will likely fail and the tunnel isn't available"
+            }
+            (NatType::Unknown, TunnelStatus::Running) => {
+                "Couldn't check direct connections, the tunnel is used as a fallback"
+            }
+            (NatType::Unknown, _) => "Couldn't check whether direct connections will work",
+            (_, TunnelStatus::Running) => {
+                "Direct connections should work, the tunnel is used as a fallback"
+            }
+            (NatType::Moderate, _) => "Direct connections should work with most players",
+            (NatType::Open, _) => "Direct connections should work",
+        }
+    }
+}
+
+impl Display for NatReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} (UPnP {})",
+            self.nat_type,
+            if self.upnp {
+                "available"
+            } else {
+                "unavailable"
+            }
+        )
+    }
+}
+
+/// Status of the NAT classification
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NatStatus {
+    /// No classification has been run
+    Unknown,
+    /// Classification is in progress
+    Checking,
+    /// Classification finished
+    Classified(NatReport),
+}
+
+impl Display for NatStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NatStatus::Unknown => f.write_str("Unknown"),
+            NatStatus::Checking => f.write_str("Checking..."),
+            NatStatus::Classified(report) => report.fmt(f),
+        }
+    }
+}
+
+/// Sender publishing the current [NatStatus]
+fn nat_status_sender() -> &'static watch::Sender<NatStatus> {
+    static SENDER: OnceLock<watch::Sender<NatStatus>> = OnceLock::new();
+    SENDER.get_or_init(|| watch::channel(NatStatus::Unknown).0)
+}
+
+/// Obtains the current NAT classification status
+pub fn nat_status() -> NatStatus {
+    nat_status_sender().borrow().clone()
+}
+
+/// Subscribes to changes in the NAT classification status
+pub fn subscribe_nat_status() -> watch::Receiver<NatStatus> {
+    nat_status_sender().subscribe()
+}
+
+/// Classifies the NAT publishing the progress and result
+/// through [nat_status]
+///
+/// ## Arguments
+/// * `server` - URL of the connected server to reflect from
+pub async fn run_nat_check(server: Arc<Url>) {
+    nat_status_sender().send_replace(NatStatus::Checking);
+
+    let report = classify_nat(&server).await;
+    debug!("Classified NAT: {:?}", report);
+
+    nat_status_sender().send_replace(NatStatus::Classified(report));
+}
+
+/// Classifies the NAT by comparing the public address the QoS endpoint
+/// of the `server` reports with the local address of the socket
+///
+/// ## Arguments
+/// * `server` - URL of the connected server to reflect from
+pub async fn classify_nat(server: &Url) -> NatReport {
+    let upnp = discover_location().await.is_some();
+
+    let reflection = match server.host_str() {
+        Some(host) => match reflect_address(host, QOS_PORT).await {
+            Ok(value) => value,
+            Err(err) => {
+                warn!("Failed to reflect public address: {}", err);
+                None
+            }
+        },
+        None => None,
+    };
+
+    NatReport {
+        nat_type: classify(reflection, upnp),
+        public_addr: reflection.map(|(_, public_addr)| public_addr),
+        upnp,
+    }
+}
+
+/// Chooses the [NatType] from the local and public address of the
+/// reflected socket.
+///
+/// Only one reflection endpoint is available so mappings that change per
+/// destination can't be observed directly. NATs that keep the local port
+/// generally keep the same mapping for every destination, while NATs that
+/// replace the port are treated as changing it per destination
+///
+/// ## Arguments
+/// * `reflection` - The local and public address if the server responded
+/// * `upnp`       - Whether a UPnP gateway responded
+fn classify(reflection: Option<(SocketAddr, SocketAddr)>, upnp: bool) -> NatType {
+    let Some((local_addr, public_addr)) = reflection else {
+        // Without a response the NAT can't be told apart from the
+        // server being unreachable
+        return NatType::Unknown;
+    };
+
+    if public_addr.ip() == local_addr.ip() {
+        NatType::Open
+    } else if public_addr.port() != local_addr.port() {
+        // Public port was replaced
+        NatType::Strict
+    } else if upnp {
+        NatType::Open
+    } else {
+        NatType::Moderate
+    }
+}
+
+/// Sends reflection requests to the QoS endpoint on `port` of the `host`,
+/// returning the local address of the socket and the public address the
+/// server reported, [None] when the server didn't respond
+///
+/// ## Arguments
+/// * `host` - The host of the connected server
+/// * `port` - The QoS port of the server
+async fn reflect_address(host: &str, port: u16) -> io::Result<Option<(SocketAddr, SocketAddr)>> {
+    let Some(addr) = lookup_host((host, port)).await?.find(SocketAddr::is_ipv4) else {
+        return Ok(None);
+    };
+
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    let local_addr = SocketAddr::new(local_ip_for(addr).await?, socket.local_addr()?.port());
+
+    for attempt in 1..=REFLECTION_ATTEMPTS {
+        match timeout(REFLECTION_TIMEOUT, qos_reflect(&socket, addr)).await {
+            Ok(Ok(public_addr)) => return Ok(Some((local_addr, public_addr))),
+            Ok(Err(err)) => debug!("Reflection from {} failed: {}", addr, err),
+            Err(_) => debug!("Reflection from {} timed out (attempt {})", addr, attempt),
+        }
+    }
+
+    Ok(None)
+}
+
+/// Finds the local IP the system routes traffic to `addr` from
+pub(crate) async fn local_ip_for(addr: SocketAddr) -> io::Result<IpAddr> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    socket.connect(addr).await?;
+    Ok(socket.local_addr()?.ip())
+}
+
+/// Sends a QoS request to `addr` returning the public address the
+/// server reflected in its response
+async fn qos_reflect(socket: &UdpSocket, addr: SocketAddr) -> io::Result<SocketAddr> {
+    let request = request_id();
+    socket.send_to(&request, addr).await?;
+
+    let mut buffer = [0u8; 512];
+    loop {
+        let (count, from) = socket.recv_from(&mut buffer).await?;
+        if from != addr {
+            continue;
+        }
+
+        if let Some(public_addr) = parse_qos_response(&buffer[..count], &request) {
+            return Ok(public_addr);
+        }
+    }
+}
+
+/// Parses the reflected address from a QoS response, the server echoes
+/// the `request` followed by the public IPv4 address and port the request
+/// came from. Anything after the address is ignored
+fn parse_qos_response(bytes: &[u8], request: &[u8]) -> Option<SocketAddr> {
+    let address = bytes
+        .strip_prefix(request)?
+        .get(..REFLECTED_ADDRESS_LENGTH)?;
+
+    let ip = Ipv4Addr::new(address[0], address[1], address[2], address[3]);
+    let port = u16::from_be_bytes([address[4], address[5]]);
+    Some(SocketAddr::new(IpAddr::V4(ip), port))
+}
+
+/// Creates the payload for a QoS request, responses are matched to the
+/// request by the echoed payload so it only needs to be unique between
+/// the requests the client makes
+fn request_id() -> [u8; 12] {
+    static COUNTER: AtomicU32 = AtomicU32::new(0);
+
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|value| value.as_nanos() as u64)
+        .unwrap_or_default();
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+
+    let mut id = [0u8; 12];
+    id[..8].copy_from_slice(&nanos.to_be_bytes());
+    id[8..].copy_from_slice(&count.to_be_bytes());
+    id
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{classify, parse_qos_response, reflect_address, NatType};
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+    use tokio::{net::UdpSocket, task::JoinHandle};
+
+    const LOCAL_IP: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 20));
+    const PUBLIC_IP: IpAddr = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 5));
+
+    /// Starts a loopback QoS endpoint reflecting the address of each request
+    /// with `port_offset` added to the port, sending an unrelated response
+    /// before each reflection
+    async fn start_reflector(port_offset: u16) -> (u16, JoinHandle<()>) {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let port = socket.local_addr().unwrap().port();
+
+        let task = tokio::spawn(async move {
+            let mut buffer = [0u8; 512];
+            while let Ok((count, from)) = socket.recv_from(&mut buffer).await {
+                let IpAddr::V4(ip) = from.ip() else {
+                    continue;
+                };
+
+                let _ = socket.send_to(b"stale response", from).await;
+
+                let mut response = buffer[..count].to_vec();
+                response.extend_from_slice(&ip.octets());
+                response.extend_from_slice(&(from.port() + port_offset).to_be_bytes());
+                // Trailing timestamp the server includes
+                response.extend_from_slice(&0u32.to_be_bytes());
+                let _ = socket.send_to(&response, from).await;
+            }
+        });
+
+        (port, task)
+    }
+
+    #[tokio::test]
+    async fn test_reflect_address() {
+        let (port, task) = start_reflector(0).await;
+        let (local_addr, public_addr) = reflect_address("127.0.0.1", port).await.unwrap().unwrap();
+        assert_eq!(local_addr, public_addr);
+        assert_eq!(
+            classify(Some((local_addr, public_addr)), false),
+            NatType::Open
+        );
+        task.abort();
+
+        // Replaced public port
+        let (port, task) = start_reflector(1).await;
+        let (local_addr, public_addr) = reflect_address("127.0.0.1", port).await.unwrap().unwrap();
+        assert_eq!(public_addr.port(), local_addr.port() + 1);
+        task.abort();
+
+        // Nothing answering the requests
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let port = socket.local_addr().unwrap().port();
+        assert_eq!(reflect_address("127.0.0.1", port).await.unwrap(), None);
+    }
+
+    #[test]
+    fn test_parse_qos_response() {
+        let request = [1u8, 2, 3, 4];
+        let response = [1u8, 2, 3, 4, 203, 0, 113, 5, 0x0E, 0x4B, 0, 0, 0, 1];
+        assert_eq!(
+            parse_qos_response(&response, &request),
+            Some(SocketAddr::new(PUBLIC_IP, 3659))
+        );
+
+        // Response for a different request or missing the address
+        assert_eq!(parse_qos_response(&response, &[1, 2, 3, 5]), None);
+        assert_eq!(parse_qos_response(&response[..8], &request), None);
+    }
+
+    #[test]
+    fn test_classify() {
+        let local = SocketAddr::new(LOCAL_IP, 3659);
+        let public = |port| Some((local, SocketAddr::new(PUBLIC_IP, port)));
+
+        // Nothing responded
+        assert_eq!(classify(None, true), NatType::Unknown);
+
+        // Not behind a NAT
+        assert_eq!(classify(Some((local, local)), false), NatType::Open);
+
+        // Port kept by the NAT
+        assert_eq!(classify(public(3659), false), NatType::Moderate);
+        assert_eq!(classify(public(3659), true), NatType::Open);
+
+        // Port replaced by the NAT
+        assert_eq!(classify(public(40000), true), NatType::Strict);
+    }
+}
@@ -1,9 +1,10 @@
 use std::{
-    fs::{copy, remove_file, write},
+    fs::{copy, read, remove_file, write},
     io,
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
+use crate::config::update_config_file;
 use native_dialog::FileDialog;
 use thiserror::Error;
 
@@ -89,5 +90,36 @@ pub fn try_patch_game() -> Result<bool, PatchError> {
 
     write(ansel_bak, ANSEL_SDK64_BAK).map_err(PatchError::FailedWritingPatchFiles)?;
     write(ansel, ANSEL_SDK64_DLL).map_err(PatchError::FailedWritingPatchFiles)?;
+
+    // Remember the game path for diagnostics
+    update_config_file(|config| config.game_path = Some(path));
+
     Ok(true)
 }
+
+/// Patch state of a game installation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PatchStatus {
+    /// The game executable couldn't be found
+    MissingGame,
+    /// The patch is applied
+    Patched,
+    /// The patch is not applied
+    Unpatched,
+    /// The AnselSDK64.dll doesn't match the patched or original file
+    Unknown,
+}
+
+/// Determines the patch state of the game executable at `path`
+pub fn get_patch_status(path: &Path) -> PatchStatus {
+    let Some(parent) = path.parent().filter(|_| path.exists()) else {
+        return PatchStatus::MissingGame;
+    };
+
+    match read(parent.join("AnselSDK64.dll")) {
+        Ok(bytes) if bytes == ANSEL_SDK64_DLL => PatchStatus::Patched,
+        Ok(bytes) if bytes == ANSEL_SDK64_BAK => PatchStatus::Unpatched,
+        Ok(_) => PatchStatus::Unknown,
+        Err(_) => PatchStatus::Unpatched,
+    }
+}
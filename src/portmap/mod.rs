@@ -0,0 +1,413 @@
+//! Optional port mapping on the home router so other players can reach
+//! the game when hosting matches
+//!
+//! When enabled in the config the game peer ports are mapped through
+//! [upnp] or, when no UPnP gateway responds, through [natpmp]. Mappings
+//! are renewed at half their lease while the servers run and removed on
+//! disconnect or shutdown through [start_removal]
+
+use crate::{
+    config::PortMappingConfig,
+    core::{reqwest, Url},
+};
+use log::{debug, error, info, warn};
+use natpmp::NatPmpGateway;
+use std::{
+    fmt::{self, Display},
+    io,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::{Mutex, OnceLock},
+    thread::JoinHandle,
+    time::Duration,
+};
+use thiserror::Error;
+use tokio::{sync::watch, time::sleep};
+use upnp::UpnpGateway;
+
+pub mod natpmp;
+pub mod route;
+pub mod upnp;
+
+/// Description given to the mappings on the gateway
+pub const MAPPING_DESCRIPTION: &str = "Pocket Ark Client";
+/// Lease requested for each mapping
+pub const MAPPING_LEASE: Duration = Duration::from_secs(60 * 60);
+
+/// Errors that can occur while mapping ports
+#[derive(Debug, Error)]
+pub enum PortMapError {
+    /// No gateway responded
+    #[error("No gateway found")]
+    NoGateway,
+    /// The gateway doesn't provide a WAN connection service
+    #[error("Gateway doesn't support port mapping")]
+    MissingService,
+    /// The gateway sent a response that couldn't be understood
+    #[error("Invalid response from gateway")]
+    InvalidResponse,
+    /// Socket error while talking to the gateway
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// HTTP error while talking to a UPnP gateway
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+    /// UPnP gateway rejected a request
+    #[error("{action} failed ({status}): {message}")]
+    Soap {
+        /// The action that was requested
+        action: &'static str,
+        /// The response status code
+        status: reqwest::StatusCode,
+        /// The error description from the gateway
+        message: String,
+    },
+    /// NAT-PMP gateway rejected a request with a result code
+    #[error("Gateway rejected the request (result code {0})")]
+    NatPmp(u16),
+}
+
+/// Gateway mappings are added through
+#[derive(Debug, Clone)]
+pub enum Gateway {
+    /// UPnP Internet Gateway Device
+    Upnp(UpnpGateway),
+    /// NAT-PMP gateway
+    NatPmp(NatPmpGateway),
+}
+
+impl Gateway {
+    /// Finds a gateway on the local network, UPnP gateways are preferred
+    ///
+    /// ## Arguments
+    /// * `http_client` - The HTTP client to use for UPnP requests
+    /// * `config`      - The port mapping settings
+    pub async fn discover(
+        http_client: reqwest::Client,
+        config: &PortMappingConfig,
+    ) -> Result<Self, PortMapError> {
+        let location = upnp::discover_location().await;
+        if let Some(location) = &location {
+            match UpnpGateway::from_location(http_client, location).await {
+                Ok(gateway) => return Ok(Self::Upnp(gateway)),
+                Err(err) => debug!("UPnP gateway at {} unusable: {}", location, err),
+            }
+        }
+
+        // Routers commonly serve NAT-PMP on the same address as UPnP
+        let addr = match config.gateway {
+            Some(addr) => addr,
+            None => match route::default_gateway().await {
+                Some(addr) => addr,
+                None => location
+                    .as_deref()
+                    .and_then(location_ip)
+                    .ok_or(PortMapError::NoGateway)?,
+            },
+        };
+        let gateway = NatPmpGateway::new(SocketAddr::new(IpAddr::V4(addr), natpmp::NATPMP_PORT));
+
+        // Check the gateway actually speaks NAT-PMP
+        gateway.external_ip().await?;
+        Ok(Self::NatPmp(gateway))
+    }
+
+    /// Name of the protocol used by the gateway
+    pub fn protocol(&self) -> &'static str {
+        match self {
+            Gateway::Upnp(_) => "UPnP",
+            Gateway::NatPmp(_) => "NAT-PMP",
+        }
+    }
+
+    /// Requests the public IP of the gateway
+    pub async fn external_ip(&self) -> Result<IpAddr, PortMapError> {
+        match self {
+            Gateway::Upnp(gateway) => gateway.external_ip().await,
+            Gateway::NatPmp(gateway) => gateway.external_ip().await,
+        }
+    }
+
+    /// Maps the UDP `port` returning the lease granted
+    ///
+    /// ## Arguments
+    /// * `port`  - The port to map
+    /// * `lease` - How long the mapping should last
+    pub async fn add_mapping(&self, port: u16, lease: Duration) -> Result<Duration, PortMapError> {
+        match self {
+            Gateway::Upnp(gateway) => gateway.add_mapping(port, lease).await.map(|_| lease),
+            Gateway::NatPmp(gateway) => gateway.add_mapping(port, lease).await,
+        }
+    }
+
+    /// Removes the mapping for the UDP `port`
+    pub async fn remove_mapping(&self, port: u16) -> Result<(), PortMapError> {
+        match self {
+            Gateway::Upnp(gateway) => gateway.remove_mapping(port).await,
+            Gateway::NatPmp(gateway) => gateway.remove_mapping(port).await,
+        }
+    }
+}
+
+/// Status of the port mappings
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PortMappingStatus {
+    /// Port mapping is turned off or the mappings were removed
+    Inactive,
+    /// Looking for a gateway and requesting the mappings
+    Requesting,
+    /// The ports are mapped on the gateway
+    Mapped {
+        /// Name of the protocol the ports were mapped with
+        protocol: &'static str,
+        /// Public IP of the gateway if it could be found
+        external_ip: Option<IpAddr>,
+        /// The mapped ports
+        ports: Vec<u16>,
+    },
+    /// Mapping the ports failed
+    Failed(String),
+}
+
+impl Display for PortMappingStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PortMappingStatus::Inactive => f.write_str("Inactive"),
+            PortMappingStatus::Requesting => f.write_str("Requesting..."),
+            PortMappingStatus::Mapped {
+                protocol,
+                external_ip,
+                ports,
+            } => {
+                let ports = ports
+                    .iter()
+                    .map(|port| port.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(f, "Mapped UDP {} via {}", ports, protocol)?;
+                if let Some(ip) = external_ip {
+                    write!(f, " on {}", ip)?;
+                }
+                Ok(())
+            }
+            PortMappingStatus::Failed(err) => write!(f, "Failed: {}", err),
+        }
+    }
+}
+
+/// Mappings currently added on a gateway
+struct ActiveMappings {
+    /// The gateway the mappings were added on
+    gateway: Gateway,
+    /// The mapped ports
+    ports: Vec<u16>,
+}
+
+/// The mappings that need removing on disconnect
+static ACTIVE_MAPPINGS: Mutex<Option<ActiveMappings>> = Mutex::new(None);
+
+/// Thread removing the mappings started by [start_removal]
+static REMOVAL_THREAD: Mutex<Option<JoinHandle<()>>> = Mutex::new(None);
+
+/// Reads the IP address from the `location` of a UPnP device description
+fn location_ip(location: &str) -> Option<Ipv4Addr> {
+    Url::parse(location).ok()?.host_str()?.parse().ok()
+}
+
+/// Sender publishing the current [PortMappingStatus]
+fn status_sender() -> &'static watch::Sender<PortMappingStatus> {
+    static SENDER: OnceLock<watch::Sender<PortMappingStatus>> = OnceLock::new();
+    SENDER.get_or_init(|| watch::channel(PortMappingStatus::Inactive).0)
+}
+
+/// Obtains the current port mapping status
+pub fn port_mapping_status() -> PortMappingStatus {
+    status_sender().borrow().clone()
+}
+
+/// Subscribes to changes in the port mapping status
+pub fn subscribe_port_mapping_status() -> watch::Receiver<PortMappingStatus> {
+    status_sender().subscribe()
+}
+
+fn set_status(status: PortMappingStatus) {
+    status_sender().send_replace(status);
+}
+
+/// Maps the configured ports and keeps renewing them, runs until the
+/// task is stopped or the mappings fail
+///
+/// ## Arguments
+/// * `http_client` - The HTTP client to use for UPnP requests
+/// * `config`      - The port mapping settings
+pub async fn run_port_mapping(http_client: reqwest::Client, config: PortMappingConfig) {
+    if !config.enabled || config.ports.is_empty() {
+        set_status(PortMappingStatus::Inactive);
+        return;
+    }
+
+    set_status(PortMappingStatus::Requesting);
+
+    let gateway = match Gateway::discover(http_client, &config).await {
+        Ok(value) => value,
+        Err(err) => {
+            warn!("Failed to find gateway for port mapping: {}", err);
+            set_status(PortMappingStatus::Failed(err.to_string()));
+            return;
+        }
+    };
+
+    if let Ok(mut active) = ACTIVE_MAPPINGS.lock() {
+        *active = Some(ActiveMappings {
+            gateway: gateway.clone(),
+            ports: config.ports.clone(),
+        });
+    }
+
+    let external_ip = gateway.external_ip().await.ok();
+
+    loop {
+        let mut renew = MAPPING_LEASE;
+
+        for port in &config.ports {
+            match gateway.add_mapping(*port, MAPPING_LEASE).await {
+                Ok(lease) => renew = renew.min(lease),
+                Err(err) => {
+                    error!("Failed to map port {}: {}", port, err);
+                    set_status(PortMappingStatus::Failed(err.to_string()));
+                    return;
+                }
+            }
+        }
+
+        if port_mapping_status() == PortMappingStatus::Requesting {
+            info!("Mapped ports {:?} via {}", config.ports, gateway.protocol());
+        }
+
+        set_status(PortMappingStatus::Mapped {
+            protocol: gateway.protocol(),
+            external_ip,
+            ports: config.ports.clone(),
+        });
+
+        // Renew the mappings before the lease runs out
+        sleep((renew / 2).max(Duration::from_secs(30))).await;
+    }
+}
+
+/// Starts removing any mappings added by [run_port_mapping] on a thread
+/// with its own runtime, so stopping the runtime of the caller doesn't
+/// cancel the removal. Any removal already running finishes first
+pub fn start_removal() {
+    let Ok(mut removal) = REMOVAL_THREAD.lock() else {
+        return;
+    };
+    let previous = removal.take();
+
+    *removal = Some(std::thread::spawn(move || {
+        if let Some(previous) = previous {
+            let _ = previous.join();
+        }
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("Failed building tokio runtime");
+        runtime.block_on(remove_port_mappings());
+    }));
+}
+
+/// Waits for the removal started by [start_removal] to finish
+pub fn wait_for_removal() {
+    let removal = REMOVAL_THREAD
+        .lock()
+        .ok()
+        .and_then(|mut value| value.take());
+    if let Some(removal) = removal {
+        let _ = removal.join();
+    }
+}
+
+/// Removes any mappings added by [run_port_mapping], the mappings are
+/// only forgotten once removed so an interrupted removal can be retried
+pub async fn remove_port_mappings() {
+    let active = ACTIVE_MAPPINGS.lock().ok().and_then(|active| {
+        active
+            .as_ref()
+            .map(|active| (active.gateway.clone(), active.ports.clone()))
+    });
+
+    if let Some((gateway, ports)) = active {
+        for port in ports {
+            if let Err(err) = gateway.remove_mapping(port).await {
+                warn!("Failed to remove mapping for port {}: {}", port, err);
+            }
+        }
+        debug!("Removed port mappings");
+
+        if let Ok(mut active) = ACTIVE_MAPPINGS.lock() {
+            *active = None;
+        }
+    }
+
+    set_status(PortMappingStatus::Inactive);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{location_ip, natpmp::NatPmpGateway, upnp::UpnpGateway, Gateway, MAPPING_LEASE};
+    use crate::{
+        config::GAME_PEER_PORT,
+        core::reqwest,
+        mock::gateway::{MockGateway, MOCK_EXTERNAL_IP},
+    };
+    use std::net::Ipv4Addr;
+
+    /// Adds and removes a mapping for the game port through the `gateway`
+    /// checking the stand-in `mock` gateway is updated
+    async fn check_mapping(gateway: Gateway, mock: &MockGateway) {
+        let external_ip = gateway.external_ip().await.unwrap();
+        assert_eq!(external_ip, MOCK_EXTERNAL_IP);
+
+        gateway
+            .add_mapping(GAME_PEER_PORT, MAPPING_LEASE)
+            .await
+            .expect("Failed to add mapping");
+        assert!(
+            mock.is_mapped(GAME_PEER_PORT),
+            "Gateway didn't receive the mapping"
+        );
+
+        gateway
+            .remove_mapping(GAME_PEER_PORT)
+            .await
+            .expect("Failed to remove mapping");
+        assert!(!mock.is_mapped(GAME_PEER_PORT), "Mapping wasn't removed");
+    }
+
+    #[tokio::test]
+    async fn test_upnp_mapping() {
+        let mock = MockGateway::start().await.unwrap();
+        let gateway = UpnpGateway::from_location(reqwest::Client::new(), &mock.location())
+            .await
+            .expect("Failed to read gateway");
+
+        check_mapping(Gateway::Upnp(gateway), &mock).await;
+    }
+
+    #[tokio::test]
+    async fn test_natpmp_mapping() {
+        let mock = MockGateway::start().await.unwrap();
+        let gateway = NatPmpGateway::new(mock.natpmp_addr());
+
+        check_mapping(Gateway::NatPmp(gateway), &mock).await;
+    }
+
+    #[test]
+    fn test_location_ip() {
+        assert_eq!(
+            location_ip("http://192.168.1.1:5000/rootDesc.xml"),
+            Some(Ipv4Addr::new(192, 168, 1, 1))
+        );
+        assert_eq!(location_ip("http://router.local/rootDesc.xml"), None);
+    }
+}
@@ -0,0 +1,108 @@
+//! NAT-PMP (RFC 6886) client for adding port mappings on gateways
+//! that don't support UPnP
+
+use super::PortMapError;
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::Duration,
+};
+use tokio::{net::UdpSocket, time::timeout};
+
+/// Port NAT-PMP gateways listen on
+pub const NATPMP_PORT: u16 = 5351;
+
+/// Time to wait for the first response, doubled for each retry
+const INITIAL_TIMEOUT: Duration = Duration::from_millis(250);
+/// Number of times a request is sent before giving up
+const MAX_ATTEMPTS: usize = 4;
+
+/// Opcode for requesting the external address
+const OP_EXTERNAL_ADDRESS: u8 = 0;
+/// Opcode for mapping a UDP port
+const OP_MAP_UDP: u8 = 1;
+/// Added to the opcode of a request for its response
+const OP_RESPONSE: u8 = 128;
+
+/// Gateway reachable through NAT-PMP
+#[derive(Debug, Clone)]
+pub struct NatPmpGateway {
+    /// Address of the gateway NAT-PMP port
+    addr: SocketAddr,
+}
+
+impl NatPmpGateway {
+    /// Creates a gateway for the NAT-PMP server at `addr`
+    pub fn new(addr: SocketAddr) -> Self {
+        Self { addr }
+    }
+
+    /// Requests the public IP of the gateway
+    pub async fn external_ip(&self) -> Result<IpAddr, PortMapError> {
+        let response = self.request(&[0, OP_EXTERNAL_ADDRESS], 12).await?;
+        let ip = Ipv4Addr::new(response[8], response[9], response[10], response[11]);
+        Ok(IpAddr::V4(ip))
+    }
+
+    /// Maps the UDP `port` on the gateway to the same port on this
+    /// machine, returning the lease the gateway granted
+    ///
+    /// ## Arguments
+    /// * `port`  - The port to map
+    /// * `lease` - How long the mapping should last
+    pub async fn add_mapping(&self, port: u16, lease: Duration) -> Result<Duration, PortMapError> {
+        let lifetime = lease.as_secs().min(u32::MAX as u64) as u32;
+        let response = self.map(port, port, lifetime).await?;
+
+        let granted = u32::from_be_bytes([response[12], response[13], response[14], response[15]]);
+        Ok(Duration::from_secs(granted as u64))
+    }
+
+    /// Removes the mapping for the UDP `port` from the gateway
+    pub async fn remove_mapping(&self, port: u16) -> Result<(), PortMapError> {
+        // Mappings are removed by requesting a zero lifetime
+        self.map(port, 0, 0).await?;
+        Ok(())
+    }
+
+    async fn map(&self, port: u16, external: u16, lifetime: u32) -> Result<Vec<u8>, PortMapError> {
+        let mut request = vec![0, OP_MAP_UDP, 0, 0];
+        request.extend_from_slice(&port.to_be_bytes());
+        request.extend_from_slice(&external.to_be_bytes());
+        request.extend_from_slice(&lifetime.to_be_bytes());
+
+        self.request(&request, 16).await
+    }
+
+    /// Sends the `request` to the gateway retrying with increasing
+    /// timeouts until a response of `length` bytes arrives
+    async fn request(&self, request: &[u8], length: usize) -> Result<Vec<u8>, PortMapError> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+        socket.connect(self.addr).await?;
+
+        let opcode = request[1] + OP_RESPONSE;
+        let mut wait = INITIAL_TIMEOUT;
+        let mut buffer = [0u8; 16];
+
+        for _ in 0..MAX_ATTEMPTS {
+            socket.send(request).await?;
+
+            if let Ok(result) = timeout(wait, socket.recv(&mut buffer)).await {
+                let count = result?;
+                if count < length || buffer[0] != 0 || buffer[1] != opcode {
+                    return Err(PortMapError::InvalidResponse);
+                }
+
+                let code = u16::from_be_bytes([buffer[2], buffer[3]]);
+                if code != 0 {
+                    return Err(PortMapError::NatPmp(code));
+                }
+
+                return Ok(buffer[..count].to_vec());
+            }
+
+            wait *= 2;
+        }
+
+        Err(PortMapError::NoGateway)
+    }
+}
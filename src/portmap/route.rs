@@ -0,0 +1,131 @@
+//! Lookup of the default gateway from the routing table of the system,
+//! used to find the NAT-PMP gateway when it isn't set in the config
+
+use std::net::Ipv4Addr;
+
+/// Finds the gateway of the default IPv4 route
+#[cfg(target_os = "linux")]
+pub async fn default_gateway() -> Option<Ipv4Addr> {
+    let table = tokio::fs::read_to_string("/proc/net/route").await.ok()?;
+    parse_proc_route(&table)
+}
+
+/// Finds the gateway of the default IPv4 route
+#[cfg(target_os = "macos")]
+pub async fn default_gateway() -> Option<Ipv4Addr> {
+    let output = tokio::process::Command::new("route")
+        .args(["-n", "get", "default"])
+        .output()
+        .await
+        .ok()?;
+    parse_route_get(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Finds the gateway of the default IPv4 route
+#[cfg(windows)]
+pub async fn default_gateway() -> Option<Ipv4Addr> {
+    /// IPv4 routing table entry (MIB_IPFORWARDROW), only the next hop
+    /// is read from the entry the system fills in
+    #[repr(C)]
+    #[derive(Default)]
+    #[allow(dead_code)]
+    struct IpForwardRow {
+        destination: u32,
+        mask: u32,
+        policy: u32,
+        next_hop: u32,
+        interface: u32,
+        ty: u32,
+        protocol: u32,
+        age: u32,
+        next_hop_as: u32,
+        metrics: [u32; 5],
+    }
+
+    #[link(name = "iphlpapi")]
+    extern "system" {
+        fn GetBestRoute(destination: u32, source: u32, route: *mut IpForwardRow) -> u32;
+    }
+
+    // Any public address takes the default route
+    let destination = u32::from_ne_bytes([1, 1, 1, 1]);
+    let mut route = IpForwardRow::default();
+
+    // SAFETY: The route is a valid MIB_IPFORWARDROW for the call to write to
+    let result = unsafe { GetBestRoute(destination, 0, &mut route) };
+    if result != 0 {
+        return None;
+    }
+
+    // Addresses are in network byte order
+    let gateway = Ipv4Addr::from(route.next_hop.to_ne_bytes());
+    (!gateway.is_unspecified()).then_some(gateway)
+}
+
+/// Other platforms rely on the UPnP gateway address
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+pub async fn default_gateway() -> Option<Ipv4Addr> {
+    None
+}
+
+/// Parses the gateway of the default route from the contents of
+/// `/proc/net/route`, addresses are hex in network byte order
+#[cfg(target_os = "linux")]
+fn parse_proc_route(table: &str) -> Option<Ipv4Addr> {
+    /// Flag set on routes that go through a gateway
+    const RTF_GATEWAY: u16 = 0x2;
+
+    // Skip the column names
+    table.lines().skip(1).find_map(|line| {
+        let mut columns = line.split_whitespace().skip(1);
+        let destination = columns.next()?;
+        let gateway = u32::from_str_radix(columns.next()?, 16).ok()?;
+        let flags = u16::from_str_radix(columns.next()?, 16).ok()?;
+
+        (destination == "00000000" && flags & RTF_GATEWAY != 0)
+            .then(|| Ipv4Addr::from(gateway.to_le_bytes()))
+    })
+}
+
+/// Parses the gateway from the output of `route -n get default`
+#[cfg(target_os = "macos")]
+fn parse_route_get(output: &str) -> Option<Ipv4Addr> {
+    output.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        if name.trim() != "gateway" {
+            return None;
+        }
+        value.trim().parse().ok()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_parse_proc_route() {
+        use super::parse_proc_route;
+        use std::net::Ipv4Addr;
+
+        let table =
+            "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT\n\
+            eth0\t0001A8C0\t00000000\t0001\t0\t0\t0\t00FFFFFF\t0\t0\t0\n\
+            eth0\t00000000\t0101A8C0\t0003\t0\t0\t0\t00000000\t0\t0\t0\n";
+        assert_eq!(parse_proc_route(table), Some(Ipv4Addr::new(192, 168, 1, 1)));
+
+        // Only the local subnet route
+        let table = "Iface\tDestination\tGateway \tFlags\n\
+            eth0\t0001A8C0\t00000000\t0001\n";
+        assert_eq!(parse_proc_route(table), None);
+    }
+
+    #[test]
+    #[cfg(target_os = "macos")]
+    fn test_parse_route_get() {
+        use super::parse_route_get;
+        use std::net::Ipv4Addr;
+
+        let output = "   route to: default\ndestination: default\n       mask: default\n    gateway: 192.168.1.1\n  interface: en0\n";
+        assert_eq!(parse_route_get(output), Some(Ipv4Addr::new(192, 168, 1, 1)));
+    }
+}
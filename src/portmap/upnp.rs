@@ -0,0 +1,260 @@
+//! UPnP Internet Gateway Device client for adding port mappings
+//!
+//! Gateways are found with an SSDP search, their device description is
+//! read to find the WAN connection service and mappings are then managed
+//! through SOAP requests to the service control URL
+
+use super::{PortMapError, MAPPING_DESCRIPTION};
+use crate::{
+    core::{reqwest, Url},
+    nat::local_ip_for,
+};
+use log::debug;
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::Duration,
+};
+use tokio::{
+    net::{lookup_host, UdpSocket},
+    time::{timeout_at, Instant},
+};
+
+/// Multicast address UPnP gateways listen for searches on
+pub const SSDP_ADDR: SocketAddr =
+    SocketAddr::new(IpAddr::V4(Ipv4Addr::new(239, 255, 255, 250)), 1900);
+/// How long to wait for a UPnP gateway to respond
+pub const SSDP_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// WAN connection services that port mappings can be added through
+const WAN_SERVICES: &[&str] = &[
+    "urn:schemas-upnp-org:service:WANIPConnection:2",
+    "urn:schemas-upnp-org:service:WANIPConnection:1",
+    "urn:schemas-upnp-org:service:WANPPPConnection:1",
+];
+
+/// Searches the local network for a UPnP internet gateway, providing the
+/// location of its device description if one responds
+pub async fn discover_location() -> Option<String> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await.ok()?;
+
+    let search = format!(
+        "M-SEARCH * HTTP/1.1\r\n\
+        HOST: {}\r\n\
+        MAN: \"ssdp:discover\"\r\n\
+        MX: 2\r\n\
+        ST: urn:schemas-upnp-org:device:InternetGatewayDevice:1\r\n\r\n",
+        SSDP_ADDR
+    );
+    if let Err(err) = socket.send_to(search.as_bytes(), SSDP_ADDR).await {
+        debug!("Failed to send UPnP search: {}", err);
+        return None;
+    }
+
+    let deadline = Instant::now() + SSDP_TIMEOUT;
+    let mut buffer = [0u8; 2048];
+
+    while let Ok(Ok((count, _))) = timeout_at(deadline, socket.recv_from(&mut buffer)).await {
+        let response = String::from_utf8_lossy(&buffer[..count]);
+        let location = response.lines().find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.trim()
+                .eq_ignore_ascii_case("location")
+                .then(|| value.trim().to_string())
+        });
+
+        if location.is_some() {
+            return location;
+        }
+    }
+
+    None
+}
+
+/// Gateway reachable through UPnP
+#[derive(Debug, Clone)]
+pub struct UpnpGateway {
+    /// The HTTP client to send requests with
+    http_client: reqwest::Client,
+    /// URL of the WAN connection service control endpoint
+    control_url: Url,
+    /// Type of the WAN connection service
+    service_type: String,
+    /// Local IP the gateway reaches this machine at
+    local_ip: IpAddr,
+}
+
+impl UpnpGateway {
+    /// Reads the device description at `location` to find the
+    /// WAN connection service of the gateway
+    ///
+    /// ## Arguments
+    /// * `http_client` - The HTTP client to send requests with
+    /// * `location`    - URL of the gateway device description
+    pub async fn from_location(
+        http_client: reqwest::Client,
+        location: &str,
+    ) -> Result<Self, PortMapError> {
+        let location = Url::parse(location).map_err(|_| PortMapError::InvalidResponse)?;
+
+        let description = http_client
+            .get(location.clone())
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+
+        let (service_type, control_path) =
+            find_service(&description).ok_or(PortMapError::MissingService)?;
+        let control_url = location
+            .join(&control_path)
+            .map_err(|_| PortMapError::MissingService)?;
+
+        // The local IP is the one used to reach the gateway
+        let host = location.host_str().ok_or(PortMapError::InvalidResponse)?;
+        let port = location.port_or_known_default().unwrap_or(80);
+        let gateway_addr = lookup_host((host, port))
+            .await?
+            .next()
+            .ok_or(PortMapError::NoGateway)?;
+        let local_ip = local_ip_for(gateway_addr).await?;
+
+        Ok(Self {
+            http_client,
+            control_url,
+            service_type,
+            local_ip,
+        })
+    }
+
+    /// Maps the UDP `port` on the gateway to the same port on this machine
+    ///
+    /// ## Arguments
+    /// * `port`  - The port to map
+    /// * `lease` - How long the mapping should last
+    pub async fn add_mapping(&self, port: u16, lease: Duration) -> Result<(), PortMapError> {
+        let arguments = format!(
+            "<NewRemoteHost></NewRemoteHost>\
+            <NewExternalPort>{port}</NewExternalPort>\
+            <NewProtocol>UDP</NewProtocol>\
+            <NewInternalPort>{port}</NewInternalPort>\
+            <NewInternalClient>{}</NewInternalClient>\
+            <NewEnabled>1</NewEnabled>\
+            <NewPortMappingDescription>{}</NewPortMappingDescription>\
+            <NewLeaseDuration>{}</NewLeaseDuration>",
+            self.local_ip,
+            MAPPING_DESCRIPTION,
+            lease.as_secs()
+        );
+
+        self.request("AddPortMapping", &arguments).await?;
+        Ok(())
+    }
+
+    /// Removes the mapping for the UDP `port` from the gateway
+    pub async fn remove_mapping(&self, port: u16) -> Result<(), PortMapError> {
+        let arguments = format!(
+            "<NewRemoteHost></NewRemoteHost>\
+            <NewExternalPort>{port}</NewExternalPort>\
+            <NewProtocol>UDP</NewProtocol>"
+        );
+
+        self.request("DeletePortMapping", &arguments).await?;
+        Ok(())
+    }
+
+    /// Requests the public IP of the gateway
+    pub async fn external_ip(&self) -> Result<IpAddr, PortMapError> {
+        let response = self.request("GetExternalIPAddress", "").await?;
+
+        find_tag(&response, "NewExternalIPAddress")
+            .and_then(|value| value.parse().ok())
+            .ok_or(PortMapError::InvalidResponse)
+    }
+
+    /// Sends a SOAP request for the `action` on the WAN connection
+    /// service returning the response body
+    ///
+    /// ## Arguments
+    /// * `action`    - The name of the action
+    /// * `arguments` - The XML arguments for the action
+    async fn request(&self, action: &'static str, arguments: &str) -> Result<String, PortMapError> {
+        let body = format!(
+            "<?xml version=\"1.0\"?>\
+            <s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\" \
+            s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\">\
+            <s:Body><u:{action} xmlns:u=\"{}\">{arguments}</u:{action}></s:Body>\
+            </s:Envelope>",
+            self.service_type
+        );
+
+        let response = self
+            .http_client
+            .post(self.control_url.clone())
+            .header("Content-Type", "text/xml; charset=\"utf-8\"")
+            .header(
+                "SOAPAction",
+                format!("\"{}#{}\"", self.service_type, action),
+            )
+            .body(body)
+            .send()
+            .await?;
+
+        let status = response.status();
+        let text = response.text().await?;
+
+        if !status.is_success() {
+            return Err(PortMapError::Soap {
+                action,
+                status,
+                message: find_tag(&text, "errorDescription").unwrap_or_else(|| status.to_string()),
+            });
+        }
+
+        Ok(text)
+    }
+}
+
+/// Finds the first supported WAN connection service in a device
+/// description providing its type and control URL
+fn find_service(description: &str) -> Option<(String, String)> {
+    let services: Vec<(String, String)> = description
+        .split("<service>")
+        .skip(1)
+        .filter_map(|service| {
+            let service_type = find_tag(service, "serviceType")?;
+            let control_url = find_tag(service, "controlURL")?;
+            Some((service_type, control_url))
+        })
+        .collect();
+
+    WAN_SERVICES.iter().find_map(|wanted| {
+        services
+            .iter()
+            .find(|(service_type, _)| service_type == wanted)
+            .cloned()
+    })
+}
+
+/// Finds the text content of the first `tag` element, namespace
+/// prefixes on the tag are ignored
+pub(crate) fn find_tag(xml: &str, tag: &str) -> Option<String> {
+    let mut rest = xml;
+
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        let end = rest.find('>')?;
+        let name = rest[..end].split_whitespace().next().unwrap_or_default();
+        rest = &rest[end + 1..];
+
+        let local_name = name.rsplit(':').next().unwrap_or(name);
+        if local_name != tag {
+            continue;
+        }
+
+        let close = rest.find("</")?;
+        return Some(rest[..close].trim().to_string());
+    }
+
+    None
+}
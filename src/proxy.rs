@@ -0,0 +1,801 @@
+//! Local HTTP proxy sitting between the local servers and the connected
+//! server, allowing the session to change without restarting the servers
+//!
+//! The local servers are started once pointed at the proxy. Each request
+//! is forwarded to the base URL of the current session with the current
+//! authentication token, so a refreshed token is used from the next request
+//! on. Connections from the local servers are kept alive between requests
+//! and request bodies may be sent with chunked transfer encoding. When the
+//! connected server upgrades a connection for Blaze the upgraded streams
+//! are piped through, Blaze packets don't carry the token so upgraded
+//! connections stay authenticated with the token they were upgraded with.
+//!
+//! While capturing the proxy also records the traffic passing through it,
+//! see [crate::capture]
+
+use crate::{
+    capture::{packet::PacketDecoder, Direction, Recorder},
+    core::{
+        reqwest::{self, Method, StatusCode},
+        Url,
+    },
+    heartbeat::TOKEN_HEADER,
+    session::SessionHandle,
+};
+use log::debug;
+use std::{
+    io,
+    net::{Ipv4Addr, TcpListener as StdTcpListener},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+
+/// Largest request head accepted from the local servers
+const MAX_HEAD_LENGTH: usize = 64 * 1024;
+/// Largest request body accepted from the local servers
+const MAX_BODY_LENGTH: usize = 16 * 1024 * 1024;
+
+/// Headers that are set by the proxy itself and not forwarded
+const SKIPPED_HEADERS: &[&str] = &["host", "content-length", "transfer-encoding", "connection"];
+
+/// Session proxy bound to a local port
+pub struct SessionProxy {
+    /// The bound listener
+    listener: StdTcpListener,
+    /// URL the local servers should use instead of the server URL
+    url: Url,
+}
+
+/// State shared between the proxied connections
+struct ProxyState {
+    /// HTTP client for forwarding requests
+    http_client: reqwest::Client,
+    /// The current session to forward requests to
+    session: SessionHandle,
+    /// Recorder for the traffic while capturing
+    recorder: Option<Arc<Recorder>>,
+    /// ID for the next connection
+    next_id: AtomicU32,
+}
+
+/// HTTP request read from one of the local servers
+pub(crate) struct ProxyRequest {
+    pub method: String,
+    pub path: String,
+    pub version: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl ProxyRequest {
+    /// Finds the value of the header with the provided `name`
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Whether the connection should be kept open after the response,
+    /// HTTP/1.1 connections are persistent unless closed by the client
+    fn keep_alive(&self) -> bool {
+        let connection = self.header("connection").unwrap_or_default();
+        let has_option = |option: &str| {
+            connection
+                .split(',')
+                .any(|value| value.trim().eq_ignore_ascii_case(option))
+        };
+
+        if self.version.eq_ignore_ascii_case("HTTP/1.0") {
+            has_option("keep-alive")
+        } else {
+            !has_option("close")
+        }
+    }
+}
+
+impl SessionProxy {
+    /// Binds the proxy to a random local port
+    pub fn bind() -> io::Result<Self> {
+        let listener = StdTcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+        listener.set_nonblocking(true)?;
+
+        let port = listener.local_addr()?.port();
+        let url = Url::parse(&format!("http://{}:{}/", Ipv4Addr::LOCALHOST, port))
+            .map_err(io::Error::other)?;
+
+        Ok(Self { listener, url })
+    }
+
+    /// URL the local servers should connect to
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// Accepts and proxies connections until the task is stopped
+    ///
+    /// ## Arguments
+    /// * `http_client` - The HTTP client to forward requests with
+    /// * `session`     - Handle to the current session details
+    /// * `recorder`    - Recorder for the traffic when capturing
+    pub async fn run(
+        self,
+        http_client: reqwest::Client,
+        session: SessionHandle,
+        recorder: Option<Arc<Recorder>>,
+    ) -> io::Result<()> {
+        let listener = TcpListener::from_std(self.listener)?;
+        let state = Arc::new(ProxyState {
+            http_client,
+            session,
+            recorder,
+            next_id: AtomicU32::new(1),
+        });
+
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let state = state.clone();
+            let id = state.next_id.fetch_add(1, Ordering::Relaxed);
+
+            tokio::spawn(async move {
+                let error = handle_connection(stream, id, &state)
+                    .await
+                    .err()
+                    .map(|err| err.to_string());
+                if let Some(error) = &error {
+                    debug!("Proxy connection {} failed: {}", id, error);
+                }
+                if let Some(recorder) = &state.recorder {
+                    recorder.record_closed(id, error);
+                }
+            });
+        }
+    }
+}
+
+/// Proxies the requests from one of the local servers, the connection is
+/// kept open between requests until it is closed or upgraded
+async fn handle_connection(stream: TcpStream, id: u32, state: &ProxyState) -> io::Result<()> {
+    let mut stream = BufReader::new(stream);
+
+    loop {
+        // The local server closed the connection between requests
+        if stream.fill_buf().await?.is_empty() {
+            return Ok(());
+        }
+
+        let request = read_request(&mut stream).await?;
+        let keep_alive = request.keep_alive();
+
+        match forward_request(request, id, state).await? {
+            Forwarded::Response(status, headers, body) => {
+                write_full_response(stream.get_mut(), status, &headers, &body, keep_alive).await?;
+                if !keep_alive {
+                    return Ok(());
+                }
+            }
+            Forwarded::Upgrade(status, headers, response) => {
+                return pipe_upgrade(stream, id, state, status, &headers, response).await;
+            }
+        }
+    }
+}
+
+/// Outcome of forwarding a request to the connected server
+enum Forwarded {
+    /// Complete response to write back to the local server
+    Response(StatusCode, Vec<(String, String)>, Vec<u8>),
+    /// The server is upgrading the connection
+    Upgrade(StatusCode, Vec<(String, String)>, reqwest::Response),
+}
+
+/// Forwards a `request` from one of the local servers to the connected
+/// server using the current session
+async fn forward_request(
+    request: ProxyRequest,
+    id: u32,
+    state: &ProxyState,
+) -> io::Result<Forwarded> {
+    let recorder = state.recorder.as_deref();
+    if let Some(recorder) = recorder {
+        recorder.record_request(id, &request);
+    }
+
+    // Read the session for each request so the current token is used
+    let session = state.session.current();
+    let url = session
+        .base_url
+        .join(request.path.trim_start_matches('/'))
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    let method = Method::from_bytes(request.method.as_bytes())
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+
+    let upgrade = request.header("upgrade").is_some();
+
+    let mut builder = state.http_client.request(method, url);
+    for (name, value) in &request.headers {
+        let skipped = name.eq_ignore_ascii_case(TOKEN_HEADER)
+            || SKIPPED_HEADERS
+                .iter()
+                .any(|header| name.eq_ignore_ascii_case(header));
+        if !skipped {
+            builder = builder.header(name.as_str(), value.as_str());
+        }
+    }
+    builder = builder.header(TOKEN_HEADER, session.token.to_string());
+    if upgrade {
+        builder = builder.header("Connection", "Upgrade");
+    }
+
+    let response = match builder.body(request.body).send().await {
+        Ok(value) => value,
+        Err(err) => {
+            let body = err.to_string().into_bytes();
+            if let Some(recorder) = recorder {
+                recorder.record_response(id, StatusCode::BAD_GATEWAY, &[], &body);
+            }
+            return Ok(Forwarded::Response(
+                StatusCode::BAD_GATEWAY,
+                Vec::new(),
+                body,
+            ));
+        }
+    };
+
+    let status = response.status();
+    let headers: Vec<(String, String)> = response
+        .headers()
+        .iter()
+        .map(|(name, value)| {
+            (
+                name.to_string(),
+                String::from_utf8_lossy(value.as_bytes()).into_owned(),
+            )
+        })
+        .collect();
+
+    if status == StatusCode::SWITCHING_PROTOCOLS {
+        if let Some(recorder) = recorder {
+            recorder.record_response(id, status, &headers, &[]);
+        }
+        return Ok(Forwarded::Upgrade(status, headers, response));
+    }
+
+    let body = response.bytes().await.map_err(io::Error::other)?;
+    if let Some(recorder) = recorder {
+        recorder.record_response(id, status, &headers, &body);
+    }
+    Ok(Forwarded::Response(status, headers, body.to_vec()))
+}
+
+/// Completes an upgrade from the connected server and pipes the upgraded
+/// streams between the local server and the connected server
+async fn pipe_upgrade(
+    mut stream: BufReader<TcpStream>,
+    id: u32,
+    state: &ProxyState,
+    status: StatusCode,
+    headers: &[(String, String)],
+    response: reqwest::Response,
+) -> io::Result<()> {
+    write_head(stream.get_mut(), status, headers).await?;
+
+    let mut upgraded = response.upgrade().await.map_err(io::Error::other)?;
+
+    // Forward anything the local server sent before the upgrade completed
+    let buffered = stream.buffer().to_vec();
+    if !buffered.is_empty() {
+        upgraded.write_all(&buffered).await?;
+    }
+
+    let recorder = state.recorder.as_deref();
+    let (client_read, client_write) = tokio::io::split(stream.into_inner());
+    let (server_read, server_write) = tokio::io::split(upgraded);
+
+    tokio::select! {
+        result = pump(client_read, server_write, Direction::ToServer, id, recorder) => result,
+        result = pump(server_read, client_write, Direction::ToClient, id, recorder) => result,
+    }
+}
+
+/// Copies bytes from `read` to `write`, each complete Blaze packet
+/// that passes through is recorded when capturing
+async fn pump<R, W>(
+    mut read: R,
+    mut write: W,
+    direction: Direction,
+    id: u32,
+    recorder: Option<&Recorder>,
+) -> io::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut decoder = PacketDecoder::default();
+    let mut buffer = [0u8; 8192];
+
+    loop {
+        let count = read.read(&mut buffer).await?;
+        if count == 0 {
+            write.shutdown().await?;
+            return Ok(());
+        }
+
+        let bytes = &buffer[..count];
+        write.write_all(bytes).await?;
+
+        let Some(recorder) = recorder else {
+            continue;
+        };
+
+        decoder.push(bytes);
+        while let Some(packet) = decoder.next_packet() {
+            recorder.record_packet(id, direction, &packet);
+        }
+    }
+}
+
+/// Creates an error for malformed request data
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Reads an HTTP/1.1 request head and body, the body is read using
+/// either the content length or chunked transfer encoding
+pub(crate) async fn read_request(stream: &mut BufReader<TcpStream>) -> io::Result<ProxyRequest> {
+    let mut line = String::new();
+    stream.read_line(&mut line).await?;
+
+    let mut parts = line.split_whitespace();
+    let method = parts.next().ok_or_else(|| invalid_data("Missing method"))?;
+    let path = parts.next().ok_or_else(|| invalid_data("Missing path"))?;
+    let version = parts.next().unwrap_or("HTTP/1.1");
+    let (method, path, version) = (method.to_string(), path.to_string(), version.to_string());
+
+    let mut headers = Vec::new();
+    let mut head_length = line.len();
+    loop {
+        line.clear();
+        head_length += stream.read_line(&mut line).await?;
+        if head_length > MAX_HEAD_LENGTH {
+            return Err(invalid_data("Request head too large"));
+        }
+
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+
+        let (name, value) = header
+            .split_once(':')
+            .ok_or_else(|| invalid_data("Malformed header"))?;
+        headers.push((name.trim().to_string(), value.trim().to_string()));
+    }
+
+    let mut request = ProxyRequest {
+        method,
+        path,
+        version,
+        headers,
+        body: Vec::new(),
+    };
+
+    // Chunked encoding takes priority over the content length
+    let chunked = request
+        .header("transfer-encoding")
+        .is_some_and(|value| value.to_ascii_lowercase().contains("chunked"));
+
+    if chunked {
+        request.body = read_chunked_body(stream).await?;
+    } else {
+        let content_length = request
+            .header("content-length")
+            .and_then(|value| value.parse::<usize>().ok())
+            .unwrap_or_default();
+        if content_length > MAX_BODY_LENGTH {
+            return Err(invalid_data("Request body too large"));
+        }
+
+        request.body = vec![0u8; content_length];
+        stream.read_exact(&mut request.body).await?;
+    }
+
+    Ok(request)
+}
+
+/// Reads a request body sent with chunked transfer encoding, chunk
+/// extensions and trailers are discarded
+async fn read_chunked_body(stream: &mut BufReader<TcpStream>) -> io::Result<Vec<u8>> {
+    let mut body = Vec::new();
+    let mut line = String::new();
+
+    loop {
+        line.clear();
+        stream.read_line(&mut line).await?;
+
+        let size = line.split(';').next().unwrap_or_default().trim();
+        let size =
+            usize::from_str_radix(size, 16).map_err(|_| invalid_data("Malformed chunk size"))?;
+        if size == 0 {
+            break;
+        }
+
+        if body.len() + size > MAX_BODY_LENGTH {
+            return Err(invalid_data("Request body too large"));
+        }
+
+        let start = body.len();
+        body.resize(start + size, 0);
+        stream.read_exact(&mut body[start..]).await?;
+
+        // Each chunk ends with a line break
+        line.clear();
+        stream.read_line(&mut line).await?;
+        if !line.trim_end().is_empty() {
+            return Err(invalid_data("Malformed chunk"));
+        }
+    }
+
+    // Trailers end with an empty line
+    let mut trailer_length = 0;
+    loop {
+        line.clear();
+        let count = stream.read_line(&mut line).await?;
+        trailer_length += count;
+        if trailer_length > MAX_HEAD_LENGTH {
+            return Err(invalid_data("Request trailers too large"));
+        }
+        if count == 0 || line.trim_end().is_empty() {
+            break;
+        }
+    }
+
+    Ok(body)
+}
+
+/// Writes an HTTP/1.1 response head with the provided headers
+pub(crate) async fn write_head(
+    stream: &mut TcpStream,
+    status: StatusCode,
+    headers: &[(String, String)],
+) -> io::Result<()> {
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\n",
+        status.as_u16(),
+        status.canonical_reason().unwrap_or_default()
+    );
+    for (name, value) in headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+
+    stream.write_all(head.as_bytes()).await
+}
+
+/// Writes a complete HTTP/1.1 response, the connection is closed after
+/// the response unless `keep_alive` is set
+async fn write_full_response(
+    stream: &mut TcpStream,
+    status: StatusCode,
+    headers: &[(String, String)],
+    body: &[u8],
+    keep_alive: bool,
+) -> io::Result<()> {
+    // The body length and connection are replaced with our own
+    let mut headers: Vec<(String, String)> = headers
+        .iter()
+        .filter(|(name, _)| {
+            !SKIPPED_HEADERS
+                .iter()
+                .any(|header| name.eq_ignore_ascii_case(header))
+        })
+        .cloned()
+        .collect();
+    headers.push(("Content-Length".to_string(), body.len().to_string()));
+    let connection = if keep_alive { "keep-alive" } else { "close" };
+    headers.push(("Connection".to_string(), connection.to_string()));
+
+    write_head(stream, status, &headers).await?;
+    stream.write_all(body).await?;
+
+    if keep_alive {
+        stream.flush().await
+    } else {
+        stream.shutdown().await
+    }
+}
+
+/// Writes a complete HTTP/1.1 response and closes the connection, used
+/// by servers that answer a single request per connection
+pub(crate) async fn write_response(
+    stream: &mut TcpStream,
+    status: StatusCode,
+    headers: &[(String, String)],
+    body: &[u8],
+) -> io::Result<()> {
+    write_full_response(stream, status, headers, body, false).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_request, write_response, SessionProxy};
+    use crate::{
+        capture::packet::HEADER_LENGTH,
+        core::{
+            api::AuthToken,
+            reqwest::{self, StatusCode},
+            Url,
+        },
+        heartbeat::TOKEN_HEADER,
+        mock::{MockConfig, MockServer, MOCK_TOKEN},
+        session::{Session, SessionHandle},
+    };
+    use std::{net::Ipv4Addr, sync::Arc};
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+        net::{TcpListener, TcpStream},
+        task::JoinHandle,
+    };
+
+    /// Response read from the proxy
+    struct TestResponse {
+        status: u16,
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+    }
+
+    impl TestResponse {
+        fn header(&self, name: &str) -> Option<&str> {
+            self.headers
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.as_str())
+        }
+    }
+
+    /// Starts a proxy forwarding to `base_url` with the mock token
+    fn start_proxy(base_url: &Url) -> (Url, JoinHandle<std::io::Result<()>>) {
+        let session = SessionHandle::new(Session {
+            base_url: Arc::new(base_url.clone()),
+            association: Arc::new(None),
+            token: AuthToken::from(MOCK_TOKEN),
+        });
+
+        let proxy = SessionProxy::bind().unwrap();
+        let url = proxy.url().clone();
+        let task = tokio::spawn(proxy.run(reqwest::Client::new(), session, None));
+        (url, task)
+    }
+
+    /// Starts a server that echoes the method, path, token and body
+    /// of each request back in the response
+    async fn start_echo_server() -> (Url, JoinHandle<()>) {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+
+        let task = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut stream = BufReader::new(stream);
+                    let request = read_request(&mut stream).await?;
+                    let token = request.header(TOKEN_HEADER).unwrap_or_default().to_string();
+                    let headers = [
+                        ("X-Method".to_string(), request.method),
+                        ("X-Path".to_string(), request.path),
+                        ("X-Echo-Token".to_string(), token),
+                    ];
+                    write_response(stream.get_mut(), StatusCode::OK, &headers, &request.body).await
+                });
+            }
+        });
+        (url, task)
+    }
+
+    /// Connects to the proxy at `url`
+    async fn connect(url: &Url) -> BufReader<TcpStream> {
+        let address = (url.host_str().unwrap(), url.port().unwrap());
+        BufReader::new(TcpStream::connect(address).await.unwrap())
+    }
+
+    /// Reads a response with a content length from the proxy
+    async fn read_response(stream: &mut BufReader<TcpStream>) -> TestResponse {
+        let mut line = String::new();
+        stream.read_line(&mut line).await.unwrap();
+        let status = line.split_whitespace().nth(1).unwrap().parse().unwrap();
+
+        let mut headers = Vec::new();
+        loop {
+            line.clear();
+            stream.read_line(&mut line).await.unwrap();
+            let Some((name, value)) = line.trim_end().split_once(':') else {
+                break;
+            };
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+
+        let mut response = TestResponse {
+            status,
+            headers,
+            body: Vec::new(),
+        };
+        let length = response
+            .header("content-length")
+            .map(|value| value.parse().unwrap())
+            .unwrap_or_default();
+        response.body = vec![0; length];
+        stream.read_exact(&mut response.body).await.unwrap();
+        response
+    }
+
+    /// Asserts the proxy closed the `stream`
+    async fn assert_closed(stream: &mut BufReader<TcpStream>) {
+        let mut buffer = Vec::new();
+        assert_eq!(stream.read_to_end(&mut buffer).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_chunked_request_body() {
+        let (server_url, server) = start_echo_server().await;
+        let (url, proxy) = start_proxy(&server_url);
+        let mut stream = connect(&url).await;
+
+        // Chunk extensions and trailers are dropped
+        stream
+            .write_all(
+                b"POST /api/echo HTTP/1.1\r\n\
+                Host: localhost\r\n\
+                Transfer-Encoding: chunked\r\n\
+                X-Token: local-token\r\n\
+                \r\n\
+                4;name=value\r\nPock\r\n\
+                9\r\net Relay!\r\n\
+                0\r\n\
+                X-Trailer: value\r\n\
+                \r\n",
+            )
+            .await
+            .unwrap();
+
+        let response = read_response(&mut stream).await;
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, b"Pocket Relay!");
+        assert_eq!(response.header("x-method"), Some("POST"));
+        assert_eq!(response.header("x-echo-token"), Some(MOCK_TOKEN));
+
+        // Malformed chunks close the connection
+        stream
+            .write_all(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n")
+            .await
+            .unwrap();
+        assert_closed(&mut stream).await;
+
+        proxy.abort();
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn test_keep_alive() {
+        let (server_url, server) = start_echo_server().await;
+        let (url, proxy) = start_proxy(&server_url);
+        let mut stream = connect(&url).await;
+
+        // Requests can be pipelined on the same connection
+        stream
+            .write_all(
+                b"GET /first HTTP/1.1\r\n\r\n\
+                POST /second HTTP/1.1\r\nContent-Length: 4\r\n\r\nbody",
+            )
+            .await
+            .unwrap();
+
+        for (path, body) in [("/first", &b""[..]), ("/second", &b"body"[..])] {
+            let response = read_response(&mut stream).await;
+            assert_eq!(response.header("x-path"), Some(path));
+            assert_eq!(response.header("connection"), Some("keep-alive"));
+            assert_eq!(response.body, body);
+        }
+
+        // The connection is closed when the client asks
+        stream
+            .write_all(b"GET /third HTTP/1.1\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let response = read_response(&mut stream).await;
+        assert_eq!(response.header("x-path"), Some("/third"));
+        assert_eq!(response.header("connection"), Some("close"));
+        assert_closed(&mut stream).await;
+
+        // HTTP/1.0 connections close unless asked to stay open
+        let mut stream = connect(&url).await;
+        stream
+            .write_all(b"GET /old HTTP/1.0\r\nConnection: keep-alive\r\n\r\n")
+            .await
+            .unwrap();
+        assert_eq!(
+            read_response(&mut stream).await.header("connection"),
+            Some("keep-alive")
+        );
+        stream
+            .write_all(b"GET /old HTTP/1.0\r\n\r\n")
+            .await
+            .unwrap();
+        assert_eq!(
+            read_response(&mut stream).await.header("connection"),
+            Some("close")
+        );
+        assert_closed(&mut stream).await;
+
+        proxy.abort();
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn test_upgrade_after_request() {
+        let server = MockServer::start(MockConfig::default()).await.unwrap();
+        let (url, proxy) = start_proxy(server.url());
+        let mut stream = connect(&url).await;
+
+        stream
+            .write_all(
+                b"GET /api/server HTTP/1.1\r\n\r\n\
+                GET /api/server/upgrade HTTP/1.1\r\n\
+                Connection: Upgrade\r\n\
+                Upgrade: blaze\r\n\
+                \r\n",
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(read_response(&mut stream).await.status, 200);
+        assert_eq!(read_response(&mut stream).await.status, 101);
+
+        // Blaze request with an empty body is answered with a response
+        let mut packet = [0u8; HEADER_LENGTH];
+        packet[6..8].copy_from_slice(&1u16.to_be_bytes());
+        packet[8..10].copy_from_slice(&2u16.to_be_bytes());
+        stream.write_all(&packet).await.unwrap();
+
+        let mut reply = [0u8; HEADER_LENGTH];
+        stream.read_exact(&mut reply).await.unwrap();
+        assert_eq!(reply[6..10], packet[6..10]);
+        assert_eq!(reply[13] >> 5, 1);
+
+        proxy.abort();
+    }
+
+    #[tokio::test]
+    async fn test_refreshed_token_used_without_restart() {
+        let server = MockServer::start(MockConfig::default()).await.unwrap();
+        let session = SessionHandle::new(Session {
+            base_url: Arc::new(server.url().clone()),
+            association: Arc::new(None),
+            token: AuthToken::from("expired-token"),
+        });
+
+        let proxy = SessionProxy::bind().unwrap();
+        let url = proxy.url().join("api/users/self").unwrap();
+        let http_client = crate::core::reqwest::Client::new();
+        let task = tokio::spawn(proxy.run(http_client.clone(), session.clone(), None));
+
+        // The token sent by the local server is replaced with the session token
+        let send = || {
+            http_client
+                .get(url.clone())
+                .header(TOKEN_HEADER, MOCK_TOKEN)
+                .send()
+        };
+
+        assert_eq!(send().await.unwrap().status(), StatusCode::UNAUTHORIZED);
+
+        session.set_token(AuthToken::from(MOCK_TOKEN));
+        assert_eq!(send().await.unwrap().status(), StatusCode::OK);
+
+        task.abort();
+    }
+}
@@ -1,79 +1,326 @@
 use crate::{
-    core::{api::AuthToken, reqwest, servers::*, ssl::create_ssl_context, Url},
+    capture::{CaptureWriter, Recorder, Redactor},
+    config::{read_config_file, CaptureConfig, TunnelMode},
+    core::{reqwest, servers::*, ssl::create_ssl_context},
+    nat::run_nat_check,
+    portmap::{run_port_mapping, start_removal},
+    proxy::SessionProxy,
+    session::SessionHandle,
     ui::show_error,
 };
-use log::error;
-use std::sync::Arc;
+use log::{error, info, warn};
+use std::{
+    fmt::{self, Display},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, OnceLock,
+    },
+};
+use tokio::sync::watch;
+
+/// Status of the tunnel server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TunnelStatus {
+    /// Tunnelling is turned off in the config
+    Disabled,
+    /// The server didn't provide an association to tunnel with
+    Unavailable,
+    /// The tunnel server is running
+    Running,
+    /// The tunnel server couldn't start or stopped with an error
+    Failed(String),
+}
+
+impl Display for TunnelStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TunnelStatus::Disabled => f.write_str("Disabled"),
+            TunnelStatus::Unavailable => f.write_str("Not supported by server"),
+            TunnelStatus::Running => f.write_str("Running"),
+            TunnelStatus::Failed(err) => write!(f, "Failed: {}", err),
+        }
+    }
+}
+
+/// Sender publishing the current [TunnelStatus]
+fn tunnel_status_sender() -> &'static watch::Sender<TunnelStatus> {
+    static SENDER: OnceLock<watch::Sender<TunnelStatus>> = OnceLock::new();
+    SENDER.get_or_init(|| watch::channel(TunnelStatus::Disabled).0)
+}
+
+/// Obtains the current status of the tunnel server
+pub fn tunnel_status() -> TunnelStatus {
+    tunnel_status_sender().borrow().clone()
+}
+
+/// Subscribes to changes in the status of the tunnel server
+pub fn subscribe_tunnel_status() -> watch::Receiver<TunnelStatus> {
+    tunnel_status_sender().subscribe()
+}
+
+fn set_tunnel_status(status: TunnelStatus) {
+    tunnel_status_sender().send_replace(status);
+}
+
+/// Failure of one of the server tasks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerFailure {
+    /// Name of the server that failed
+    pub server: &'static str,
+    /// The error the server failed with
+    pub error: String,
+}
 
-/// Starts all the servers in their own tasks
+impl Display for ServerFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Failed to start {} server: {}", self.server, self.error)
+    }
+}
+
+/// Sender publishing the last [ServerFailure]
+fn server_failure_sender() -> &'static watch::Sender<Option<ServerFailure>> {
+    static SENDER: OnceLock<watch::Sender<Option<ServerFailure>>> = OnceLock::new();
+    SENDER.get_or_init(|| watch::channel(None).0)
+}
+
+/// Subscribes to failures of the server tasks
+pub fn subscribe_server_failures() -> watch::Receiver<Option<ServerFailure>> {
+    server_failure_sender().subscribe()
+}
+
+/// Whether server failures are shown in an error dialog, turned off while
+/// the front-end shows them as notifications instead
+static FAILURE_DIALOGS: AtomicBool = AtomicBool::new(true);
+
+/// Sets whether server failures are shown in an error dialog, the native
+/// front-end turns these off while minimised to the tray so failures only
+/// raise a notification
+pub fn set_failure_dialogs(enabled: bool) {
+    FAILURE_DIALOGS.store(enabled, Ordering::Relaxed);
+}
+
+/// Reports that the `server` failed to the user and any
+/// failure subscribers
+///
+/// ## Arguments
+/// * `server` - Name of the server that failed
+/// * `err`    - The error the server failed with
+fn report_server_failure(server: &'static str, err: &dyn Display) {
+    let failure = ServerFailure {
+        server,
+        error: err.to_string(),
+    };
+
+    error!("{}", failure);
+    if FAILURE_DIALOGS.load(Ordering::Relaxed) {
+        show_error(
+            &format!("Failed to start {} server", server),
+            &failure.error,
+        );
+    }
+    server_failure_sender().send_replace(Some(failure));
+}
+
+/// Starts all the servers in their own tasks. The Blaze and HTTP servers
+/// reach the connected server through the session proxy so changes to the
+/// `session` apply without restarting them
 ///
 /// ## Arguments
 /// * `http_client` - The HTTP client to use on the servers
-/// * `base_url`    - The base URL of the connected server
-/// * `association` - Optional association token if supported
-pub fn start_all_servers(
-    http_client: reqwest::Client,
-    base_url: Arc<Url>,
-    association: Arc<Option<String>>,
-    token: AuthToken,
-) {
+/// * `session`     - Handle to the current session details
+pub fn start_all_servers(http_client: reqwest::Client, session: SessionHandle) {
     // Stop existing servers and tasks if they are running
     stop_server_tasks();
 
     let ssl_context = create_ssl_context().expect("Failed to create ssl context");
 
+    let config = read_config_file().unwrap_or_default();
+
+    let proxy = match SessionProxy::bind() {
+        Ok(value) => value,
+        Err(err) => {
+            report_server_failure("proxy", &err);
+            return;
+        }
+    };
+    let proxy_url = Arc::new(proxy.url().clone());
+
+    // Spawn the session proxy, recording the traffic when capturing
+    {
+        let recorder = start_capture(&config.capture);
+        let (a, b) = (http_client.clone(), session.clone());
+
+        spawn_server_task(async move {
+            if let Err(err) = proxy.run(a, b, recorder).await {
+                report_server_failure("proxy", &err);
+            }
+        });
+    }
+
+    // The proxy replaces the token on each request, the servers are given
+    // the current details for anything they use before connecting
+    let current = session.current();
+
     let a = ssl_context.clone();
 
     // Spawn the Redirector server
     spawn_server_task(async move {
         if let Err(err) = redirector::start_redirector_server(a).await {
-            show_error("Failed to start redirector server", &err.to_string());
-            error!("Failed to start redirector server: {}", err);
+            report_server_failure("redirector", &err);
         }
     });
 
-    // Need to copy the client and base_url so it can be moved into the task
+    // Need to copy the client so it can be moved into the task
     let (a, b, c, d) = (
         http_client.clone(),
-        base_url.clone(),
-        association.clone(),
-        token.clone(),
+        proxy_url.clone(),
+        current.association.clone(),
+        current.token.clone(),
     );
 
     // Spawn the Blaze server
     spawn_server_task(async move {
         if let Err(err) = blaze::start_blaze_server(a, b, c, d).await {
-            show_error("Failed to start blaze server", &err.to_string());
-            error!("Failed to start blaze server: {}", err);
+            report_server_failure("blaze", &err);
         }
     });
 
-    // Need to copy the client and base_url so it can be moved into the task
-    let (a, b) = (http_client.clone(), base_url.clone());
+    // Need to copy the client so it can be moved into the task
+    let (a, b, c) = (http_client.clone(), proxy_url, current.token.clone());
 
     // Spawn the HTTP server
     spawn_server_task(async move {
-        if let Err(err) = http::start_http_server(a, b, ssl_context, token).await {
-            show_error("Failed to start http server", &err.to_string());
-            error!("Failed to start http server: {}", err);
+        if let Err(err) = http::start_http_server(a, b, ssl_context, c).await {
+            report_server_failure("http", &err);
         }
     });
 
-    // Need to copy the client and base_url so it can be moved into the task
-    // let (a, b) = (http_client.clone(), base_url.clone());
-    // Spawn the tunneling server (Not supported yet)
-    // spawn_server_task(async move {
-    //     if let Err(err) = tunnel::start_tunnel_server(a, b, association).await {
-    //         show_error("Failed to start tunnel server", &err.to_string());
-    //         error!("Failed to start tunnel server: {}", err);
-    //     }
-    // });
+    // The tunnel needs the association from the lookup to identify this
+    // client to the server, the connected server and association stay the
+    // same for as long as the servers are running
+    let status = select_tunnel_status(config.tunnel, current.association.is_some());
+    set_tunnel_status(status.clone());
+
+    if let TunnelStatus::Failed(err) = &status {
+        report_server_failure("tunnel", err);
+    }
+
+    if status == TunnelStatus::Running {
+        // Need to copy the client so it can be moved into the task
+        let (a, b, c) = (
+            http_client.clone(),
+            current.base_url.clone(),
+            current.association.clone(),
+        );
+
+        // Spawn the tunneling server
+        spawn_server_task(async move {
+            if let Err(err) = tunnel::start_tunnel_server(a, b, c).await {
+                set_tunnel_status(TunnelStatus::Failed(err.to_string()));
+                report_server_failure("tunnel", &err);
+            }
+        });
+    }
 
     // Spawn the QoS server
     spawn_server_task(async move {
         if let Err(err) = qos::start_qos_server().await {
-            show_error("Failed to start qos server", &err.to_string());
-            error!("Failed to start qos server: {}", err);
+            report_server_failure("qos", &err);
         }
     });
+
+    // Classify the NAT so the player knows whether direct
+    // connections will work
+    spawn_server_task(run_nat_check(current.base_url.clone()));
+
+    // Map the game ports on the router when enabled
+    spawn_server_task(run_port_mapping(http_client, config.port_mapping));
+}
+
+/// Chooses the [TunnelStatus] the tunnel starts with, forcing the tunnel
+/// on fails when the server didn't provide an association
+///
+/// ## Arguments
+/// * `mode`        - The configured tunnel mode
+/// * `association` - Whether the server provided an association
+fn select_tunnel_status(mode: TunnelMode, association: bool) -> TunnelStatus {
+    match mode {
+        TunnelMode::Off => TunnelStatus::Disabled,
+        TunnelMode::Auto if !association => TunnelStatus::Unavailable,
+        TunnelMode::On if !association => {
+            TunnelStatus::Failed("Server didn't provide an association to tunnel with".to_string())
+        }
+        TunnelMode::Auto | TunnelMode::On => TunnelStatus::Running,
+    }
+}
+
+/// Stops all the servers and starts removing any port mappings they
+/// added, shutdown waits for the removal through [wait_for_removal]
+///
+/// [wait_for_removal]: crate::portmap::wait_for_removal
+pub fn stop_all_servers() {
+    stop_server_tasks();
+    start_removal();
+}
+
+/// Creates the recorder for the session proxy if capturing
+/// is enabled in the config
+///
+/// ## Arguments
+/// * `config` - The capture settings
+fn start_capture(config: &CaptureConfig) -> Option<Arc<Recorder>> {
+    if !config.enabled {
+        return None;
+    }
+
+    let writer = match CaptureWriter::create() {
+        Ok(value) => value,
+        Err(err) => {
+            show_error("Failed to start capture", &err.to_string());
+            error!("Failed to start capture: {}", err);
+            return None;
+        }
+    };
+
+    info!("Capturing traffic to {}", writer.path().display());
+
+    // Unredacted captures contain the account credentials so they are
+    // only allowed while debugging
+    let redact = config.redact_tokens || !cfg!(debug_assertions);
+    if redact != config.redact_tokens {
+        warn!("Unredacted captures are only available in debug builds, redacting capture");
+    }
+
+    let redactor = Redactor::new(redact);
+    Some(Arc::new(Recorder::new(writer, redactor)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{select_tunnel_status, TunnelStatus};
+    use crate::config::TunnelMode;
+
+    #[test]
+    fn test_select_tunnel_status() {
+        assert_eq!(
+            select_tunnel_status(TunnelMode::Off, true),
+            TunnelStatus::Disabled
+        );
+        assert_eq!(
+            select_tunnel_status(TunnelMode::Auto, false),
+            TunnelStatus::Unavailable
+        );
+        assert_eq!(
+            select_tunnel_status(TunnelMode::Auto, true),
+            TunnelStatus::Running
+        );
+        assert_eq!(
+            select_tunnel_status(TunnelMode::On, true),
+            TunnelStatus::Running
+        );
+        assert!(matches!(
+            select_tunnel_status(TunnelMode::On, false),
+            TunnelStatus::Failed(_)
+        ));
+    }
 }
@@ -0,0 +1,76 @@
+//! Shared session details for the running servers, allowing the
+//! authentication token to be swapped while the servers are running.
+//! The session is read by the session [proxy](crate::proxy) for each
+//! request so the servers never need restarting
+
+use crate::core::{api::AuthToken, Url};
+use std::sync::Arc;
+use tokio::sync::watch;
+
+/// Details of the current session used by the servers
+#[derive(Debug, Clone)]
+pub struct Session {
+    /// The base URL of the connected server
+    pub base_url: Arc<Url>,
+    /// Optional association token if supported
+    pub association: Arc<Option<String>>,
+    /// The current authentication token
+    pub token: AuthToken,
+}
+
+/// Swappable handle to the current [Session], cloning the handle
+/// shares the same underlying session
+#[derive(Clone)]
+pub struct SessionHandle {
+    /// Sender for publishing the current session
+    sender: Arc<watch::Sender<Arc<Session>>>,
+}
+
+impl SessionHandle {
+    /// Creates a new handle starting with the provided `session`
+    ///
+    /// ## Arguments
+    /// * `session` - The initial session
+    pub fn new(session: Session) -> Self {
+        let (sender, _) = watch::channel(Arc::new(session));
+        Self {
+            sender: Arc::new(sender),
+        }
+    }
+
+    /// Obtains the current session
+    pub fn current(&self) -> Arc<Session> {
+        self.sender.borrow().clone()
+    }
+
+    /// Replaces the entire session, used when logging in as a different
+    /// user on the same server. The tunnel keeps the base URL and
+    /// association it was started with so moving to a different server
+    /// requires restarting the servers
+    ///
+    /// ## Arguments
+    /// * `session` - The new session
+    pub fn replace(&self, session: Session) {
+        self.sender.send_replace(Arc::new(session));
+    }
+
+    /// Replaces just the authentication token of the current session,
+    /// used when the token is refreshed
+    ///
+    /// ## Arguments
+    /// * `token` - The new authentication token
+    pub fn set_token(&self, token: AuthToken) {
+        self.sender.send_modify(|session| {
+            *session = Arc::new(Session {
+                base_url: session.base_url.clone(),
+                association: session.association.clone(),
+                token,
+            });
+        });
+    }
+
+    /// Creates a receiver which is notified when the session changes
+    pub fn subscribe(&self) -> watch::Receiver<Arc<Session>> {
+        self.sender.subscribe()
+    }
+}
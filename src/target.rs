@@ -0,0 +1,207 @@
+//! Parsing and normalisation of user provided server connection URLs
+
+use crate::core::Url;
+use std::net::Ipv6Addr;
+use thiserror::Error;
+
+/// Errors that can occur while parsing a connection URL
+#[derive(Debug, Clone, Error)]
+pub enum TargetError {
+    /// Nothing was provided
+    #[error("Enter the server Connection URL")]
+    Empty,
+    /// The URL contained whitespace
+    #[error("Connection URL must not contain spaces")]
+    Whitespace,
+    /// The URL scheme wasn't HTTP or HTTPS
+    #[error("Unsupported scheme \"{0}\", use http or https")]
+    UnsupportedScheme(String),
+    /// The URL included a username or password
+    #[error("Connection URL must not include a username or password")]
+    Credentials,
+    /// The URL didn't include a host
+    #[error("Connection URL is missing the server address")]
+    MissingHost,
+    /// The URL port wasn't valid
+    #[error("Port \"{0}\" must be a number between 1 and 65535")]
+    InvalidPort(String),
+    /// The URL was otherwise invalid
+    #[error("Invalid Connection URL: {0}")]
+    Invalid(String),
+}
+
+/// Parses the user provided connection URL `input` into a normalised URL.
+///
+/// The scheme defaults to HTTP and is case insensitive, IPv6 addresses
+/// may be provided without brackets when no port is given, international
+/// domain names are converted to punycode, the query and fragment are
+/// removed and the path always ends with a slash.
+pub fn parse_target(input: &str) -> Result<Url, TargetError> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Err(TargetError::Empty);
+    }
+
+    if input.contains(char::is_whitespace) {
+        return Err(TargetError::Whitespace);
+    }
+
+    // Split the scheme from the rest of the URL
+    let (scheme, rest) = match input.split_once("://") {
+        Some((scheme, rest)) => (scheme.to_lowercase(), rest),
+        None => ("http".to_string(), input),
+    };
+
+    if scheme != "http" && scheme != "https" {
+        return Err(TargetError::UnsupportedScheme(scheme));
+    }
+
+    // Split the host and port from the path
+    let authority_end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    let (authority, path) = rest.split_at(authority_end);
+
+    // Credentials are not supported
+    if authority.contains('@') {
+        return Err(TargetError::Credentials);
+    }
+
+    if authority.is_empty() {
+        return Err(TargetError::MissingHost);
+    }
+
+    let (host, port) = split_host_port(authority)?;
+    if host.is_empty() {
+        return Err(TargetError::MissingHost);
+    }
+
+    let url = match port {
+        Some(port) => format!("{}://{}:{}{}", scheme, host, port, path),
+        None => format!("{}://{}{}", scheme, host, path),
+    };
+
+    let mut url = Url::parse(&url).map_err(|err| TargetError::Invalid(err.to_string()))?;
+
+    url.set_query(None);
+    url.set_fragment(None);
+
+    if !url.path().ends_with('/') {
+        let path = format!("{}/", url.path());
+        url.set_path(&path);
+    }
+
+    Ok(url)
+}
+
+/// Splits the `authority` into its host and optional port. Bare IPv6
+/// addresses are wrapped in brackets
+fn split_host_port(authority: &str) -> Result<(String, Option<u16>), TargetError> {
+    // Bracketed IPv6 address with an optional port
+    if let Some(rest) = authority.strip_prefix('[') {
+        let (address, rest) = rest
+            .split_once(']')
+            .ok_or_else(|| TargetError::Invalid("Unclosed IPv6 address bracket".to_string()))?;
+
+        address
+            .parse::<Ipv6Addr>()
+            .map_err(|_| TargetError::Invalid(format!("Invalid IPv6 address \"{}\"", address)))?;
+
+        let port = match rest.strip_prefix(':') {
+            Some(port) => Some(parse_port(port)?),
+            None if rest.is_empty() => None,
+            None => return Err(TargetError::Invalid(format!("Unexpected \"{}\"", rest))),
+        };
+
+        return Ok((format!("[{}]", address), port));
+    }
+
+    // Bare IPv6 address without a port
+    if authority.parse::<Ipv6Addr>().is_ok() {
+        return Ok((format!("[{}]", authority), None));
+    }
+
+    match authority.rsplit_once(':') {
+        Some((host, port)) => Ok((host.to_string(), Some(parse_port(port)?))),
+        None => Ok((authority.to_string(), None)),
+    }
+}
+
+/// Parses a non-zero port number
+fn parse_port(port: &str) -> Result<u16, TargetError> {
+    match port.parse::<u16>() {
+        Ok(value) if value != 0 => Ok(value),
+        _ => Err(TargetError::InvalidPort(port.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_target, TargetError};
+
+    #[test]
+    fn test_parse_target() {
+        for (input, expected) in [
+            // Scheme defaulting and case
+            ("localhost", "http://localhost/"),
+            ("HTTPS://Example.COM", "https://example.com/"),
+            ("  example.com/path  ", "http://example.com/path/"),
+            // Port normalisation
+            ("example.com:80", "http://example.com/"),
+            ("https://example.com:443/", "https://example.com/"),
+            ("https://example.com:80", "https://example.com:80/"),
+            ("example.com:08080", "http://example.com:8080/"),
+            // IPv4 and IPv6 literals
+            ("127.0.0.1:8080", "http://127.0.0.1:8080/"),
+            ("::1", "http://[::1]/"),
+            ("[::1]:8080", "http://[::1]:8080/"),
+            ("https://[2001:DB8::1]", "https://[2001:db8::1]/"),
+            ("fe80::1:2", "http://[fe80::1:2]/"),
+            // International domain names
+            ("bücher.example", "http://xn--bcher-kva.example/"),
+            (
+                "https://ÉXAMPLE.com:8443",
+                "https://xn--xample-9ua.com:8443/",
+            ),
+            // Query and fragment removal
+            (
+                "example.com/api?query=1#fragment",
+                "http://example.com/api/",
+            ),
+            ("example.com?query", "http://example.com/"),
+        ] {
+            let url = parse_target(input).unwrap_or_else(|err| panic!("{}: {}", input, err));
+            assert_eq!(url.as_str(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_parse_invalid_target() {
+        for (input, expected) in [
+            ("", "empty"),
+            ("   ", "empty"),
+            ("example .com", "whitespace"),
+            ("ftp://example.com", "scheme"),
+            ("http://", "host"),
+            ("http://:8080", "host"),
+            ("user:pass@example.com", "credentials"),
+            ("https://user@example.com:8443", "credentials"),
+            ("example.com:0", "port"),
+            ("example.com:65536", "port"),
+            ("example.com:http", "port"),
+            ("[::1", "invalid"),
+            ("[::1]x", "invalid"),
+            ("[not-ipv6]:80", "invalid"),
+        ] {
+            let result = match parse_target(input) {
+                Ok(url) => panic!("{} parsed as {}", input, url),
+                Err(TargetError::Empty) => "empty",
+                Err(TargetError::Whitespace) => "whitespace",
+                Err(TargetError::UnsupportedScheme(_)) => "scheme",
+                Err(TargetError::MissingHost) => "host",
+                Err(TargetError::Credentials) => "credentials",
+                Err(TargetError::InvalidPort(_)) => "port",
+                Err(TargetError::Invalid(_)) => "invalid",
+            };
+            assert_eq!(result, expected, "{}", input);
+        }
+    }
+}
@@ -1,32 +1,64 @@
-use super::{ICON_BYTES, WINDOW_TITLE};
+use super::{
+    confirm_compatibility, report_error,
+    tray::{self, Tray, TrayAction},
+    ICON_BYTES, WINDOW_TITLE,
+};
 use crate::{
-    config::{write_config_file, ClientConfig},
+    account::UpdateAccountRequest,
+    config::{read_config_file, ClientConfig},
+    controller::{ConnectionState, Controller, Transition},
+    diagnostics::run_diagnostics,
+    discovery::{discover_servers, DiscoveredServer, DISCOVERY_ENABLED},
+    heartbeat::{ConnectionStatus, HeartbeatEvent},
+    launch::{game_status, launch_game, subscribe_game_status, GameStatus},
+    logging::{logs_text, open_log_folder, recent_logs, LogLine},
+    nat::{nat_status, subscribe_nat_status, NatStatus, NatType},
     patch::{try_patch_game, try_remove_patch},
-    servers::start_all_servers,
-    update,
+    portmap::{port_mapping_status, subscribe_port_mapping_status, PortMappingStatus},
+    servers::{subscribe_tunnel_status, tunnel_status, TunnelStatus},
+    target::{parse_target, TargetError},
+    update::{self, DownloadProgress, UpdateInfo, Updater},
+    validation::{server_error_field, validate_create, validate_login, FormField, ValidationError},
 };
 use iced::{
     executor,
+    futures::SinkExt,
+    subscription,
     theme::Palette,
     widget::{
-        button, column, container, row, text, text_input, Button, Column, Row, Text, TextInput,
+        button, checkbox, column, container, progress_bar, row, scrollable, text, text_input,
+        Button, Column, Row, Text, TextInput,
     },
     window::{self, icon},
-    Application, Color, Command, Length, Settings, Theme,
+    Application, Color, Command, Length, Settings, Subscription, Theme,
 };
-use log::debug;
-use pocket_ark_client_shared::{
-    api::{
-        create_user, login_user, lookup_server, AuthToken, CreateUserRequest, LoginUserRequest,
-        LookupData, LookupError, ServerAuthError,
+use log::{error, Level};
+use pocket_ark_client_shared::{reqwest, Url};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
     },
-    reqwest,
+    time::Duration,
 };
+use tokio::sync::watch;
 
 /// The window size
-pub const WINDOW_SIZE: (u32, u32) = (500, 300);
+pub const WINDOW_SIZE: (u32, u32) = (500, 400);
 
-pub fn init(config: Option<ClientConfig>, client: reqwest::Client) {
+/// Initializes the user interface
+///
+/// ## Arguments
+/// * `config`  - The client config to use
+/// * `client`  - The HTTP client to use
+/// * `updater` - The updater to use, [None] if updating is unavailable
+/// * `updated` - Whether the client was launched after being updated
+pub fn init(
+    config: ClientConfig,
+    client: reqwest::Client,
+    updater: Option<Arc<Updater>>,
+    updated: bool,
+) {
     App::run(Settings {
         window: window::Settings {
             icon: icon::from_file_data(ICON_BYTES, None).ok(),
@@ -35,7 +67,7 @@ pub fn init(config: Option<ClientConfig>, client: reqwest::Client) {
 
             ..window::Settings::default()
         },
-        flags: (config, client),
+        flags: (config, client, updater, updated),
 
         ..Settings::default()
     })
@@ -43,18 +75,61 @@ pub fn init(config: Option<ClientConfig>, client: reqwest::Client) {
 }
 
 struct App {
-    /// Result of a connection lookup
-    lookup_result: LookupState,
-    /// Whether to remember the connection URL
-    remember: bool,
+    /// Controller for the connection state
+    controller: Controller,
     /// The current connection URL
     target: String,
+    /// The normalised `target` URL or the reason its invalid
+    target_url: Result<Url, TargetError>,
+    /// State of LAN server discovery
+    discovery: DiscoveryState,
+    /// State of the connection diagnostics
+    diagnostics: DiagnosticsState,
+    /// Account panel form while the panel is open
+    account_form: Option<AccountForm>,
+    /// Error from the server for a specific form field
+    field_error: Option<(FormField, String)>,
+    /// Log viewer state while the viewer is open
+    logs: Option<LogsState>,
+    /// Current status of the tunnel server
+    tunnel_status: TunnelStatus,
+    /// Current status of the NAT classification
+    nat_status: NatStatus,
+    /// Current status of the port mappings
+    port_mapping_status: PortMappingStatus,
+    /// Current status of the game launched from the client
+    game_status: GameStatus,
     /// Http client for sending requests
     http_client: reqwest::Client,
     /// Current authentication state
     auth_state: AuthState,
     /// App state
     state: AppState,
+    /// Current state of the client update
+    update_state: UpdateState,
+    /// Updater for checking and installing updates
+    updater: Option<Arc<Updater>>,
+    /// Tray icon the window can be hidden to, [None] if it couldn't be created
+    tray: Option<Tray>,
+}
+
+/// State of the client update process
+#[derive(Debug, Default, Clone)]
+enum UpdateState {
+    /// No update is available or the update was dismissed
+    #[default]
+    None,
+    /// An update is available and the user is being prompted
+    Available(Arc<UpdateInfo>),
+    /// The update is being downloaded
+    Downloading {
+        /// The update being downloaded
+        info: Arc<UpdateInfo>,
+        /// Current download progress
+        progress: DownloadProgress,
+        /// Flag for cancelling the download
+        cancel: Arc<AtomicBool>,
+    },
 }
 
 #[derive(Debug, Default, Clone)]
@@ -68,6 +143,34 @@ pub struct CreateState {
     pub email: String,
     pub username: String,
     pub password: String,
+    pub confirm_password: String,
+}
+
+/// Form state for the account panel
+#[derive(Debug, Default, Clone)]
+pub struct AccountForm {
+    pub username: String,
+    pub email: String,
+    pub current_password: String,
+    pub new_password: String,
+}
+
+/// State of the log viewer
+#[derive(Debug, Clone)]
+pub struct LogsState {
+    /// Minimum level of lines to show
+    pub level: Level,
+    /// Text the shown lines must contain
+    pub search: String,
+    /// The lines matching the filters
+    pub lines: Vec<LogLine>,
+}
+
+impl LogsState {
+    /// Reloads the lines matching the filters
+    fn refresh(&mut self) {
+        self.lines = recent_logs(self.level, &self.search);
+    }
 }
 
 #[derive(Debug, Default, Clone)]
@@ -79,8 +182,6 @@ enum AppState {
     Login(LoginState),
     /// User is on create account page
     Create(CreateState),
-    /// User is logged in and running
-    Running(AuthToken),
 }
 
 /// Messages used for updating the game state
@@ -94,16 +195,16 @@ enum AppMessage {
     EmailChanged(String),
     /// Password field changed
     PasswordChanged(String),
+    /// Confirm password field changed
+    ConfirmPasswordChanged(String),
     /// The redirector target should be updated
     UpdateTarget,
     /// Display the patch game dialog asking the player to patch
     PatchGame,
     /// Remove the patch from the game
     RemovePatch,
-    /// Message for setting the current lookup result state
-    LookupState(LookupState),
-    /// Message for setting the current lookup result state
-    AuthState(AuthState),
+    /// A controller future completed
+    Transition(Transition),
     /// Login should be attempted
     AttemptLogin,
     /// Account creation should be attempted
@@ -112,19 +213,106 @@ enum AppMessage {
     SetState(AppState),
     /// Server should disconnect
     Disconnect,
+    /// The user should be logged out
+    Logout,
+    /// Whether to remember the connection URL changed
+    RememberChanged(bool),
+    /// The account panel should be opened
+    OpenAccount,
+    /// The account panel should be closed
+    CloseAccount,
+    /// Account panel username field changed
+    AccountUsernameChanged(String),
+    /// Account panel email field changed
+    AccountEmailChanged(String),
+    /// Account panel current password field changed
+    CurrentPasswordChanged(String),
+    /// Account panel new password field changed
+    NewPasswordChanged(String),
+    /// The changed account details should be saved
+    SaveAccount,
+    /// The log viewer should be opened
+    OpenLogs,
+    /// The log viewer should be closed
+    CloseLogs,
+    /// The log viewer lines should be reloaded
+    RefreshLogs,
+    /// The log viewer level filter changed
+    LogLevelChanged(Level),
+    /// The log viewer search text changed
+    LogSearchChanged(String),
+    /// The shown log lines should be copied to the clipboard
+    CopyLogs,
+    /// The log folder should be opened
+    OpenLogFolder,
+    /// The account password should be changed
+    ChangePassword,
+    /// LAN servers should be discovered
+    DiscoverServers,
+    /// LAN discovery completed
+    DiscoveryComplete(Vec<DiscoveredServer>),
+    /// Connection diagnostics should be run
+    RunDiagnostics,
+    /// Connection diagnostics completed with the report
+    DiagnosticsComplete(String),
+    /// The diagnostics report should be copied to the clipboard
+    CopyDiagnostics,
+    /// The diagnostics report should be closed
+    CloseDiagnostics,
+    /// Event from the heartbeat while running
+    Heartbeat(HeartbeatEvent),
+    /// The tunnel server status changed
+    TunnelStatusChanged(TunnelStatus),
+    /// The NAT classification status changed
+    NatStatusChanged(NatStatus),
+    /// The port mapping status changed
+    PortMappingStatusChanged(PortMappingStatus),
+    /// The game should be launched
+    LaunchGame,
+    /// The window was created after launching from an update
+    Started,
+    /// The launched game status changed
+    GameStatusChanged(GameStatus),
+    /// The update check completed
+    UpdateChecked(Option<Arc<UpdateInfo>>),
+    /// The user accepted the update
+    StartUpdate,
+    /// The user chose to skip the update version
+    SkipUpdate,
+    /// The user dismissed the update
+    DismissUpdate,
+    /// The user cancelled the update download
+    CancelUpdate,
+    /// Progress was made on the update download
+    UpdateProgress(DownloadProgress),
+    /// The update download completed
+    UpdateFinished(Result<(), String>),
+    /// The window should be hidden leaving the tray icon
+    HideToTray,
+    /// An action was chosen from the tray icon
+    Tray(TrayAction),
 }
 
-/// Different states that lookup process can be in
+/// Different states that LAN discovery can be in
 #[derive(Debug, Clone)]
-enum LookupState {
-    /// Lookup not yet done
+enum DiscoveryState {
+    /// Discovery not yet done
     None,
-    /// Looking up value
-    Loading,
-    /// Lookup complete success
-    Success(LookupData),
-    /// Lookup failed error
-    Error,
+    /// Searching for servers
+    Searching,
+    /// Discovery complete with the servers found
+    Complete(Vec<DiscoveredServer>),
+}
+
+/// Different states that connection diagnostics can be in
+#[derive(Debug, Clone)]
+enum DiagnosticsState {
+    /// Diagnostics not being shown
+    None,
+    /// Running the diagnostics
+    Running,
+    /// Diagnostics complete with the report
+    Complete(String),
 }
 
 #[derive(Debug, Clone)]
@@ -137,28 +325,69 @@ enum AuthState {
 impl Application for App {
     type Message = AppMessage;
     type Executor = executor::Default;
-    type Flags = (Option<ClientConfig>, reqwest::Client);
+    type Flags = (ClientConfig, reqwest::Client, Option<Arc<Updater>>, bool);
     type Theme = Theme;
 
     fn new(flags: Self::Flags) -> (Self, Command<Self::Message>) {
-        let (config, http_client) = flags;
+        let (config, http_client, updater, updated) = flags;
+
+        // Check for updates in the background
+        let update_check = match updater.clone() {
+            Some(updater) => {
+                let (channel, skipped_version) = (config.update_channel, config.skipped_version);
+
+                Command::perform(
+                    async move { updater.check(channel, skipped_version).await },
+                    |result| {
+                        let info = match result {
+                            Ok(value) => value.map(Arc::new),
+                            Err(err) => {
+                                error!("Failed to check for updates: {}", err);
+                                None
+                            }
+                        };
+                        AppMessage::UpdateChecked(info)
+                    },
+                )
+            }
+            None => Command::none(),
+        };
+
+        // Commands run once the window is created, startup is reported
+        // to the previous version from there
+        let startup = if updated {
+            Command::perform(async {}, |_| AppMessage::Started)
+        } else {
+            Command::none()
+        };
+
         let (target, remember) = config
-            .map(|value| (value.connection_url, true))
+            .connection_url
+            .map(|value| (value, true))
             .unwrap_or_default();
 
-        // Spawn the update checking task
-        tokio::spawn(update::update(http_client.clone()));
-
         (
             App {
-                lookup_result: LookupState::None,
+                controller: Controller::from_client(http_client.clone(), remember),
                 auth_state: AuthState::None,
                 state: AppState::Default,
+                update_state: UpdateState::None,
+                updater,
+                target_url: parse_target(&target),
+                discovery: DiscoveryState::None,
+                diagnostics: DiagnosticsState::None,
+                account_form: None,
+                field_error: None,
+                logs: None,
+                tunnel_status: tunnel_status(),
+                nat_status: nat_status(),
+                port_mapping_status: port_mapping_status(),
+                game_status: game_status(),
                 target,
-                remember,
                 http_client,
+                tray: Tray::create(),
             },
-            Command::none(),
+            Command::batch([update_check, startup]),
         )
     }
 
@@ -167,38 +396,147 @@ impl Application for App {
     }
 
     fn update(&mut self, message: Self::Message) -> Command<Self::Message> {
+        let command = self.handle_message(message);
+        self.update_tray();
+        command
+    }
+
+    fn subscription(&self) -> Subscription<Self::Message> {
+        // Keep the log viewer up to date while its open
+        let logs_subscription = match &self.logs {
+            Some(_) => iced::time::every(Duration::from_secs(1)).map(|_| AppMessage::RefreshLogs),
+            None => Subscription::none(),
+        };
+
+        Subscription::batch([
+            self.update_subscription(),
+            self.heartbeat_subscription(),
+            self.watch_subscription(
+                "tunnel",
+                subscribe_tunnel_status,
+                AppMessage::TunnelStatusChanged,
+            ),
+            self.watch_subscription("nat", subscribe_nat_status, AppMessage::NatStatusChanged),
+            self.watch_subscription(
+                "port_mapping",
+                subscribe_port_mapping_status,
+                AppMessage::PortMappingStatusChanged,
+            ),
+            self.watch_subscription("game", subscribe_game_status, AppMessage::GameStatusChanged),
+            self.tray_subscription(),
+            logs_subscription,
+        ])
+    }
+
+    fn view(&self) -> iced::Element<'_, Self::Message> {
+        // Updates take priority over the other screens
+        match &self.update_state {
+            UpdateState::None => {}
+            UpdateState::Available(info) => return self.update_view(info, None),
+            UpdateState::Downloading { info, progress, .. } => {
+                return self.update_view(info, Some(progress))
+            }
+        }
+
+        match &self.diagnostics {
+            DiagnosticsState::None => {}
+            DiagnosticsState::Running => return self.diagnostics_view(None),
+            DiagnosticsState::Complete(report) => return self.diagnostics_view(Some(report)),
+        }
+
+        match (self.controller.state(), &self.state) {
+            (ConnectionState::Running { .. }, _) => match (&self.account_form, &self.logs) {
+                (Some(form), _) => self.account_view(form),
+                (None, Some(logs)) => self.logs_view(logs),
+                (None, None) => self.running_view(),
+            },
+            (
+                ConnectionState::Connected(_) | ConnectionState::Authenticating(_),
+                AppState::Login(state),
+            ) => self.login_view(state),
+            (
+                ConnectionState::Connected(_) | ConnectionState::Authenticating(_),
+                AppState::Create(state),
+            ) => self.create_view(state),
+            _ => self.base_view(),
+        }
+    }
+
+    fn theme(&self) -> iced::Theme {
+        iced::Theme::Dark
+    }
+}
+
+const DARK_TEXT: Color = Color::from_rgb(0.4, 0.4, 0.4);
+const RED_TEXT: Color = Color::from_rgb(0.8, 0.4, 0.4);
+const YELLOW_TEXT: Color = Color::from_rgb(0.8, 0.8, 0.4);
+const ORANGE_TEXT: Color = Color::from_rgb(0.8, 0.6, 0.4);
+const SPACING: u16 = 10;
+
+impl App {
+    /// Updates the app state for the provided `message`
+    fn handle_message(&mut self, message: AppMessage) -> Command<AppMessage> {
         match message {
             // Update the stored target
-            AppMessage::TargetChanged(value) => self.target = value,
+            AppMessage::TargetChanged(value) => {
+                self.target_url = parse_target(&value);
+                self.target = value;
+            }
             // Handle new target being set
             AppMessage::UpdateTarget => {
-                // Don't try to lookup if already looking up
-                if let LookupState::Loading = self.lookup_result {
+                // Invalid URLs are shown inline and repeated lookups are ignored
+                let Ok(connect) = self.controller.connect(&self.target) else {
                     return Command::none();
-                }
+                };
 
-                self.lookup_result = LookupState::Loading;
+                return Command::perform(connect, AppMessage::Transition);
+            }
+            AppMessage::DiscoverServers => {
+                // Don't search if already searching
+                if let DiscoveryState::Searching = self.discovery {
+                    return Command::none();
+                }
 
-                let target = self.target.clone();
+                self.discovery = DiscoveryState::Searching;
 
-                // Handling for once the async lookup is complete
-                let post_lookup = |result: Result<LookupData, LookupError>| {
-                    let result = match result {
-                        Ok(value) => LookupState::Success(value),
+                return Command::perform(discover_servers(), |result| {
+                    let servers = match result {
+                        Ok(value) => value,
                         Err(err) => {
-                            show_error("Failed to connect", &err.to_string());
-                            LookupState::Error
+                            error!("Failed to discover LAN servers: {}", err);
+                            Vec::new()
                         }
                     };
-                    AppMessage::LookupState(result)
-                };
+                    AppMessage::DiscoveryComplete(servers)
+                });
+            }
+            AppMessage::DiscoveryComplete(servers) => {
+                self.discovery = DiscoveryState::Complete(servers);
+            }
+            AppMessage::RunDiagnostics => {
+                // Don't run if already running
+                if let DiagnosticsState::Running = self.diagnostics {
+                    return Command::none();
+                }
+
+                self.diagnostics = DiagnosticsState::Running;
+
+                let game_path = read_config_file().and_then(|config| config.game_path);
 
-                // Perform the async lookup with the callback
                 return Command::perform(
-                    lookup_server(self.http_client.clone(), target),
-                    post_lookup,
+                    run_diagnostics(self.http_client.clone(), self.target.clone(), game_path),
+                    |report| AppMessage::DiagnosticsComplete(report.to_string()),
                 );
             }
+            AppMessage::DiagnosticsComplete(report) => {
+                self.diagnostics = DiagnosticsState::Complete(report);
+            }
+            AppMessage::CopyDiagnostics => {
+                if let DiagnosticsState::Complete(report) = &self.diagnostics {
+                    return iced::clipboard::write(report.clone());
+                }
+            }
+            AppMessage::CloseDiagnostics => self.diagnostics = DiagnosticsState::None,
             // Patching
             AppMessage::PatchGame => match try_patch_game() {
                 // Game was patched
@@ -217,137 +555,505 @@ impl Application for App {
                 // Error occurred
                 Err(err) => show_error("Failed to remove patch", &err.to_string()),
             },
-            // Lookup result changed
-            AppMessage::LookupState(value) => {
-                if let LookupState::Success(_) = &value {
-                    self.state = AppState::Login(LoginState::default());
-                }
-                self.lookup_result = value
-            }
-            AppMessage::SetState(state) => {
-                if let (AppState::Running(token), LookupState::Success(value)) =
-                    (&state, &self.lookup_result)
-                {
-                    debug!("Starting servers");
-                    // Start all the servers
-                    start_all_servers(
-                        self.http_client.clone(),
-                        value.url.clone(),
-                        value.association.clone(),
-                        token.clone(),
-                    );
+            // A controller future completed
+            AppMessage::Transition(transition) => {
+                let connected = matches!(transition, Transition::Connected(_));
+                let authenticated = matches!(transition, Transition::Authenticated(_));
+                let success_message = transition.success_message();
 
-                    // Save the connection URL
-                    if self.remember {
-                        let connection_url = value.url.to_string();
+                match self.controller.apply(transition) {
+                    Ok(()) => {
+                        // Declining a compatibility warning disconnects again
+                        if connected && confirm_compatibility(&mut self.controller) {
+                            self.state = AppState::Login(LoginState::default());
+                        }
+
+                        // Launch the game straight away when configured to
+                        if authenticated
+                            && matches!(self.controller.state(), ConnectionState::Running { .. })
+                            && !self.game_status.is_active()
+                            && read_config_file().is_some_and(|config| config.launch.on_login)
+                        {
+                            self.launch_game();
+                        }
 
-                        write_config_file(ClientConfig { connection_url });
+                        // Fill the account panel with the loaded details
+                        if let (Some(form), Some(account)) =
+                            (&mut self.account_form, self.controller.account())
+                        {
+                            form.username = account.username.clone();
+                            form.email = account.email.clone();
+                            form.current_password.clear();
+                            form.new_password.clear();
+                        }
+
+                        if let Some((title, text)) = success_message {
+                            show_info(title, text);
+                        }
+                        self.auth_state = AuthState::None;
                     }
-                }
+                    Err(err) => {
+                        // Field errors from the server are shown next to the input
+                        match server_error_field(&err) {
+                            Some(field) if authenticated => {
+                                self.field_error = Some((field, err.to_string()));
+                            }
+                            _ => report_error(&err),
+                        }
 
+                        if authenticated {
+                            self.auth_state = AuthState::Error;
+                        }
+                    }
+                }
+            }
+            AppMessage::SetState(state) => {
+                self.auth_state = AuthState::None;
+                self.field_error = None;
                 self.state = state;
             }
             AppMessage::UsernameChanged(username) => {
+                self.clear_field_error(FormField::Username);
                 if let AppState::Create(state) = &mut self.state {
                     state.username = username
                 }
             }
-            AppMessage::PasswordChanged(password) => match &mut self.state {
-                AppState::Login(state) => state.password = password,
-                AppState::Create(state) => state.password = password,
-                _ => {}
-            },
-            AppMessage::EmailChanged(email) => match &mut self.state {
-                AppState::Login(state) => state.email = email,
-                AppState::Create(state) => state.email = email,
-                _ => {}
-            },
+            AppMessage::PasswordChanged(password) => {
+                self.clear_field_error(FormField::Password);
+                match &mut self.state {
+                    AppState::Login(state) => state.password = password,
+                    AppState::Create(state) => state.password = password,
+                    _ => {}
+                }
+            }
+            AppMessage::ConfirmPasswordChanged(password) => {
+                if let AppState::Create(state) = &mut self.state {
+                    state.confirm_password = password
+                }
+            }
+            AppMessage::EmailChanged(email) => {
+                self.clear_field_error(FormField::Email);
+                match &mut self.state {
+                    AppState::Login(state) => state.email = email,
+                    AppState::Create(state) => state.email = email,
+                    _ => {}
+                }
+            }
             AppMessage::AttemptLogin => {
-                self.auth_state = AuthState::Loading;
-                // Handling for once the async lookup is complete
-                let post_login = |result: Result<AuthToken, ServerAuthError>| match result {
-                    Ok(token) => AppMessage::SetState(AppState::Running(token)),
-                    Err(err) => {
-                        show_error("Failed to login", &err.to_string());
-                        AppMessage::AuthState(AuthState::Error)
-                    }
+                let AppState::Login(state) = &self.state else {
+                    return Command::none();
                 };
 
-                let (state, data) = match (&self.state, &self.lookup_result) {
-                    (AppState::Login(value), LookupState::Success(data)) => (value, data),
-                    _ => return Command::none(),
-                };
+                // Invalid fields are shown inline
+                if !validate_login(&state.email, &state.password).is_empty() {
+                    return Command::none();
+                }
+                self.field_error = None;
 
-                return Command::perform(
-                    login_user(
-                        self.http_client.clone(),
-                        data.url.as_ref().clone(),
-                        LoginUserRequest {
-                            email: state.email.clone(),
-                            password: state.password.clone(),
-                        },
-                    ),
-                    post_login,
-                );
+                let login = self
+                    .controller
+                    .login(state.email.clone(), state.password.clone());
+                if let Ok(login) = login {
+                    self.auth_state = AuthState::Loading;
+                    return Command::perform(login, AppMessage::Transition);
+                }
             }
             AppMessage::AttemptCreate => {
-                self.auth_state = AuthState::Loading;
-
-                // Handling for once the async lookup is complete
-                let post_login = |result: Result<AuthToken, ServerAuthError>| match result {
-                    Ok(token) => AppMessage::SetState(AppState::Running(token)),
-                    Err(err) => {
-                        show_error("Failed to create account", &err.to_string());
-                        AppMessage::AuthState(AuthState::Error)
-                    }
-                };
-                let (state, data) = match (&self.state, &self.lookup_result) {
-                    (AppState::Create(value), LookupState::Success(data)) => (value, data),
-                    _ => return Command::none(),
+                let AppState::Create(state) = &self.state else {
+                    return Command::none();
                 };
-                return Command::perform(
-                    create_user(
-                        self.http_client.clone(),
-                        data.url.as_ref().clone(),
-                        CreateUserRequest {
-                            email: state.email.clone(),
-                            username: state.username.clone(),
-                            password: state.password.clone(),
-                        },
-                    ),
-                    post_login,
+
+                // Invalid fields are shown inline
+                let errors = validate_create(
+                    &state.email,
+                    &state.username,
+                    &state.password,
+                    &state.confirm_password,
                 );
+                if !errors.is_empty() {
+                    return Command::none();
+                }
+                self.field_error = None;
+
+                let create = self.controller.create(
+                    state.email.clone(),
+                    state.username.clone(),
+                    state.password.clone(),
+                );
+                if let Ok(create) = create {
+                    self.auth_state = AuthState::Loading;
+                    return Command::perform(create, AppMessage::Transition);
+                }
             }
             AppMessage::Disconnect => {
+                self.controller.disconnect();
                 self.state = AppState::Default;
-                self.lookup_result = LookupState::None;
+                self.auth_state = AuthState::None;
+                self.account_form = None;
+                self.logs = None;
+            }
+            AppMessage::Logout => {
+                self.controller.logout();
+                self.state = AppState::Login(LoginState::default());
+                self.auth_state = AuthState::None;
+                self.account_form = None;
+                self.logs = None;
             }
-            AppMessage::AuthState(state) => {
-                self.auth_state = state;
+            AppMessage::RememberChanged(value) => self.controller.set_remember(value),
+            AppMessage::OpenAccount => {
+                self.account_form = Some(AccountForm::default());
+
+                if let Ok(load) = self.controller.load_account() {
+                    return Command::perform(load, AppMessage::Transition);
+                }
+            }
+            AppMessage::CloseAccount => self.account_form = None,
+            AppMessage::AccountUsernameChanged(value) => {
+                if let Some(form) = &mut self.account_form {
+                    form.username = value;
+                }
+            }
+            AppMessage::AccountEmailChanged(value) => {
+                if let Some(form) = &mut self.account_form {
+                    form.email = value;
+                }
+            }
+            AppMessage::CurrentPasswordChanged(value) => {
+                if let Some(form) = &mut self.account_form {
+                    form.current_password = value;
+                }
+            }
+            AppMessage::NewPasswordChanged(value) => {
+                if let Some(form) = &mut self.account_form {
+                    form.new_password = value;
+                }
+            }
+            AppMessage::OpenLogs => {
+                let mut logs = LogsState {
+                    level: Level::Info,
+                    search: String::new(),
+                    lines: Vec::new(),
+                };
+                logs.refresh();
+                self.logs = Some(logs);
+            }
+            AppMessage::CloseLogs => self.logs = None,
+            AppMessage::TunnelStatusChanged(status) => self.tunnel_status = status,
+            AppMessage::NatStatusChanged(status) => self.nat_status = status,
+            AppMessage::PortMappingStatusChanged(status) => self.port_mapping_status = status,
+            AppMessage::LaunchGame => self.launch_game(),
+            AppMessage::GameStatusChanged(status) => {
+                let exited =
+                    self.game_status == GameStatus::Running && status == GameStatus::Exited;
+                self.game_status = status;
+
+                // Close the client once the game exits when configured to, the hosts
+                // entry and port mappings are removed as the client shuts down
+                if exited && read_config_file().is_some_and(|config| config.launch.close_on_exit) {
+                    self.controller.disconnect();
+                    return window::close();
+                }
+            }
+            AppMessage::RefreshLogs => {
+                if let Some(logs) = &mut self.logs {
+                    logs.refresh();
+                }
+            }
+            AppMessage::LogLevelChanged(level) => {
+                if let Some(logs) = &mut self.logs {
+                    logs.level = level;
+                    logs.refresh();
+                }
+            }
+            AppMessage::LogSearchChanged(search) => {
+                if let Some(logs) = &mut self.logs {
+                    logs.search = search;
+                    logs.refresh();
+                }
+            }
+            AppMessage::CopyLogs => {
+                if let Some(logs) = &self.logs {
+                    return iced::clipboard::write(logs_text(&logs.lines));
+                }
+            }
+            AppMessage::OpenLogFolder => {
+                if let Err(err) = open_log_folder() {
+                    show_error("Failed to open log folder", &err.to_string());
+                }
             }
+            AppMessage::SaveAccount => {
+                let (Some(form), Some(account)) = (&self.account_form, self.controller.account())
+                else {
+                    return Command::none();
+                };
+
+                // Only send the details that changed
+                let request = UpdateAccountRequest {
+                    username: (form.username != account.username).then(|| form.username.clone()),
+                    email: (form.email != account.email).then(|| form.email.clone()),
+                };
+                if request.username.is_none() && request.email.is_none() {
+                    return Command::none();
+                }
+
+                if let Ok(update) = self.controller.update_account(request) {
+                    return Command::perform(update, AppMessage::Transition);
+                }
+            }
+            AppMessage::ChangePassword => {
+                let Some(form) = &self.account_form else {
+                    return Command::none();
+                };
+
+                let change = self
+                    .controller
+                    .change_password(form.current_password.clone(), form.new_password.clone());
+                if let Ok(change) = change {
+                    return Command::perform(change, AppMessage::Transition);
+                }
+            }
+            AppMessage::Heartbeat(event) => {
+                // Prompt the user to login again once the session expires
+                if let Some(email) = self.controller.handle_heartbeat(event) {
+                    show_warning(
+                        "Session expired",
+                        "Your session has expired and couldn't be renewed, please login again. \
+                        The local servers have been stopped until you login",
+                    );
+
+                    self.auth_state = AuthState::None;
+                    self.account_form = None;
+                    self.state = AppState::Login(LoginState {
+                        email,
+                        password: String::new(),
+                    });
+                }
+            }
+            AppMessage::UpdateChecked(info) => {
+                if let Some(info) = info {
+                    self.update_state = UpdateState::Available(info);
+                }
+            }
+            AppMessage::StartUpdate => {
+                if let UpdateState::Available(info) = &self.update_state {
+                    self.update_state = UpdateState::Downloading {
+                        info: info.clone(),
+                        progress: DownloadProgress::default(),
+                        cancel: Arc::new(AtomicBool::new(false)),
+                    };
+                }
+            }
+            AppMessage::SkipUpdate => {
+                if let UpdateState::Available(info) = &self.update_state {
+                    update::skip_version(&info.version);
+                }
+                self.update_state = UpdateState::None;
+            }
+            AppMessage::Started => update::report_startup(),
+            AppMessage::DismissUpdate => self.update_state = UpdateState::None,
+            AppMessage::CancelUpdate => {
+                if let UpdateState::Downloading { cancel, .. } = &self.update_state {
+                    cancel.store(true, Ordering::Release);
+                }
+            }
+            AppMessage::UpdateProgress(value) => {
+                if let UpdateState::Downloading { progress, .. } = &mut self.update_state {
+                    *progress = value;
+                }
+            }
+            AppMessage::UpdateFinished(result) => {
+                self.update_state = UpdateState::None;
+
+                match result {
+                    // The updated client has started and taken over, closing
+                    // returns from init so the client shuts down normally
+                    Ok(()) => return window::close(),
+                    Err(err) => show_error("Failed to update", &err),
+                }
+            }
+            AppMessage::HideToTray => return window::change_mode(window::Mode::Hidden),
+            AppMessage::Tray(action) => return self.handle_tray_action(action),
         }
         Command::none()
     }
 
-    fn view(&self) -> iced::Element<'_, Self::Message> {
-        match &self.state {
-            AppState::Default => self.base_view(),
-            AppState::Login(state) => self.login_view(state),
-            AppState::Create(state) => self.create_view(state),
-            AppState::Running(_) => self.running_view(),
+    /// Handles an `action` chosen from the tray icon
+    fn handle_tray_action(&mut self, action: TrayAction) -> Command<AppMessage> {
+        let running = matches!(self.controller.state(), ConnectionState::Running { .. });
+
+        match action {
+            TrayAction::Show => {}
+            // The log viewer is only available while running
+            TrayAction::OpenLogs if running => {
+                let _ = self.handle_message(AppMessage::OpenLogs);
+            }
+            TrayAction::OpenLogs => return self.handle_message(AppMessage::OpenLogFolder),
+            TrayAction::Reconnect => {
+                if let Err(err) = self.controller.restart_servers() {
+                    show_error("Failed to reconnect", &err.to_string());
+                }
+                return Command::none();
+            }
+            TrayAction::Disconnect if running => {
+                return self.handle_message(AppMessage::Disconnect);
+            }
+            TrayAction::Disconnect => return Command::none(),
+            // Servers and port mappings are cleaned up once init returns
+            TrayAction::Quit => return window::close(),
         }
+
+        Command::batch([
+            window::change_mode(window::Mode::Windowed),
+            window::gain_focus(),
+        ])
     }
 
-    fn theme(&self) -> iced::Theme {
-        iced::Theme::Dark
+    /// Shows the connection status on the tray icon
+    fn update_tray(&mut self) {
+        let status = match self.controller.state() {
+            ConnectionState::Disconnected => "Not connected".to_string(),
+            ConnectionState::Connecting => "Connecting...".to_string(),
+            ConnectionState::Connected(lookup_data)
+            | ConnectionState::Authenticating(lookup_data) => {
+                format!("Not logged in to {}", lookup_data.url.authority())
+            }
+            ConnectionState::Running {
+                lookup_data,
+                status,
+                ..
+            } => format!(
+                "Connected to {}\nStatus: {}\nTunnel: {}",
+                lookup_data.url.authority(),
+                status,
+                self.tunnel_status
+            ),
+        };
+
+        if let Some(tray) = &mut self.tray {
+            tray.set_status(status);
+        }
     }
-}
 
-const DARK_TEXT: Color = Color::from_rgb(0.4, 0.4, 0.4);
-const RED_TEXT: Color = Color::from_rgb(0.8, 0.4, 0.4);
-const YELLOW_TEXT: Color = Color::from_rgb(0.8, 0.8, 0.4);
-const ORANGE_TEXT: Color = Color::from_rgb(0.8, 0.6, 0.4);
-const SPACING: u16 = 10;
+    /// Subscription sending the actions chosen from the tray icon
+    fn tray_subscription(&self) -> Subscription<AppMessage> {
+        if self.tray.is_none() {
+            return Subscription::none();
+        }
+
+        subscription::channel("tray", 10, |output| async move {
+            tray::set_action_handler(move |action| {
+                let _ = output.clone().try_send(AppMessage::Tray(action));
+            });
+
+            std::future::pending().await
+        })
+    }
+
+    /// Subscription that downloads the update while in the downloading state
+    fn update_subscription(&self) -> Subscription<AppMessage> {
+        let (UpdateState::Downloading { info, cancel, .. }, Some(updater)) =
+            (&self.update_state, &self.updater)
+        else {
+            return Subscription::none();
+        };
+
+        let updater = updater.clone();
+        let (info, cancel) = (info.clone(), cancel.clone());
+
+        subscription::channel(
+            info.version.to_string(),
+            100,
+            move |mut output| async move {
+                let result = updater
+                    .install(
+                        &info,
+                        |progress| {
+                            // Progress is only informational so dropping updates is fine
+                            let _ = output.try_send(AppMessage::UpdateProgress(progress));
+                        },
+                        &cancel,
+                    )
+                    .await
+                    .map_err(|err| err.to_string());
+
+                let _ = output.send(AppMessage::UpdateFinished(result)).await;
+
+                // Subscription is dropped once the state changes
+                std::future::pending().await
+            },
+        )
+    }
+
+    /// Subscription running the heartbeat while in the running state, restarted
+    /// whenever the token changes
+    fn heartbeat_subscription(&self) -> Subscription<AppMessage> {
+        let (ConnectionState::Running { token, .. }, Some(heartbeat)) =
+            (self.controller.state(), self.controller.heartbeat())
+        else {
+            return Subscription::none();
+        };
+
+        subscription::channel(
+            ("heartbeat", token.to_string()),
+            10,
+            move |mut output| async move {
+                heartbeat
+                    .run(|event| {
+                        let _ = output.try_send(AppMessage::Heartbeat(event));
+                    })
+                    .await;
+
+                // Subscription is dropped once the state changes
+                std::future::pending().await
+            },
+        )
+    }
+
+    /// Launches the game using the saved config, showing any
+    /// errors to the user
+    fn launch_game(&mut self) {
+        let config = read_config_file().unwrap_or_default();
+
+        match launch_game(&config) {
+            Ok(()) => self.game_status = GameStatus::Launching,
+            Err(err) => show_error("Failed to launch game", &err.to_string()),
+        }
+    }
+
+    /// Subscription sending a message each time the value of a watch
+    /// channel changes, only active while running
+    ///
+    /// ## Arguments
+    /// * `id`        - Unique ID for the subscription
+    /// * `subscribe` - Function subscribing to the watch channel
+    /// * `message`   - Function creating the message for a value
+    fn watch_subscription<T>(
+        &self,
+        id: &'static str,
+        subscribe: fn() -> watch::Receiver<T>,
+        message: fn(T) -> AppMessage,
+    ) -> Subscription<AppMessage>
+    where
+        T: Clone + Send + Sync + 'static,
+    {
+        if !matches!(self.controller.state(), ConnectionState::Running { .. }) {
+            return Subscription::none();
+        }
+
+        subscription::channel(id, 10, move |mut output| async move {
+            let mut receiver = subscribe();
+
+            loop {
+                let value = receiver.borrow_and_update().clone();
+                let _ = output.send(message(value)).await;
+
+                if receiver.changed().await.is_err() {
+                    std::future::pending::<()>().await;
+                }
+            }
+        })
+    }
+}
 
 impl App
 where
@@ -361,10 +1067,62 @@ where
 
         let target_text: Text =
             text("Please put the server Connection URL below and press 'Set'").style(DARK_TEXT);
-        let target_button: Button<_> = button("Set").on_press(AppMessage::UpdateTarget).padding(10);
+        let mut target_button: Button<_> = button("Set").padding(10);
+        if self.target_url.is_ok() {
+            target_button = target_button.on_press(AppMessage::UpdateTarget);
+        }
 
         let target_row: Row<_> = row![target_input, target_button].spacing(SPACING);
 
+        // The URL that will actually be used or why its invalid
+        let target_status: Text = match &self.target_url {
+            Ok(url) => text(format!("Will connect to {}", url)).style(DARK_TEXT),
+            // No need to show an error before anything is entered
+            Err(TargetError::Empty) => text(""),
+            Err(err) => text(err.to_string()).style(RED_TEXT),
+        };
+
+        let remember_checkbox = checkbox(
+            "Remember connection URL",
+            self.controller.remember(),
+            AppMessage::RememberChanged,
+        );
+
+        // LAN discovery
+        let mut discover_button: Button<_> = button("Find LAN servers").padding(5);
+        if !matches!(self.discovery, DiscoveryState::Searching) {
+            discover_button = discover_button.on_press(AppMessage::DiscoverServers);
+        }
+
+        let discovery_status: Text = match &self.discovery {
+            DiscoveryState::None => text(""),
+            DiscoveryState::Searching => text("Searching...").style(YELLOW_TEXT),
+            DiscoveryState::Complete(servers) if servers.is_empty() => {
+                text("No LAN servers found").style(ORANGE_TEXT)
+            }
+            DiscoveryState::Complete(servers) => {
+                text(format!("Found {} LAN server(s)", servers.len())).style(DARK_TEXT)
+            }
+        };
+
+        // Selecting a server fills in its connection URL
+        let discovered: Column<_> = match &self.discovery {
+            DiscoveryState::Complete(servers) => {
+                servers.iter().fold(column![].spacing(5), |column, server| {
+                    column.push(
+                        button(text(server.to_string()))
+                            .on_press(AppMessage::TargetChanged(server.url.to_string()))
+                            .padding(5)
+                            .width(Length::Fill),
+                    )
+                })
+            }
+            _ => column![],
+        };
+
+        let discovery_row: Row<_> = row![discover_button, discovery_status].spacing(SPACING);
+        let discovered = scrollable(discovered).height(Length::Shrink);
+
         // Keep running notice
         let notice = text(
             "You must keep this program running while playing. \
@@ -387,12 +1145,21 @@ where
         )
         .style(DARK_TEXT);
 
-        let actions_row: Row<_> = row![patch_button, unpatch_button]
+        let diagnostics_button: Button<_> = button("Diagnostics")
+            .on_press(AppMessage::RunDiagnostics)
+            .padding(5);
+
+        let actions_row: Row<_> = row![patch_button, unpatch_button, diagnostics_button]
             .spacing(SPACING)
             .width(Length::Fill);
 
-        let content: Column<_> =
-            column![target_text, target_row, notice, patch_notice, actions_row].spacing(10);
+        let mut content: Column<_> =
+            column![target_text, target_row, target_status, remember_checkbox].spacing(10);
+        // LAN discovery is hidden until servers answer discovery queries
+        if DISCOVERY_ENABLED {
+            content = content.push(discovery_row).push(discovered);
+        }
+        let content = content.push(notice).push(patch_notice).push(actions_row);
 
         container(content)
             .width(Length::Fill)
@@ -410,6 +1177,8 @@ where
             AuthState::Error => text("Failed to login").style(Palette::DARK.danger),
         };
 
+        let errors = validate_login(&state.email, &state.password);
+
         let email_input: TextInput<_> = text_input("Email", &state.email)
             .padding(10)
             .on_input(AppMessage::EmailChanged);
@@ -418,28 +1187,25 @@ where
             .password()
             .on_input(AppMessage::PasswordChanged);
 
-        let submit_button: Button<_> = button("Login")
-            .on_press(AppMessage::AttemptLogin)
-            .padding(10)
-            .width(Length::Fill);
+        // Prevent submitting invalid details or submitting twice
+        let mut submit_button: Button<_> = button("Login").padding(10).width(Length::Fill);
+        if errors.is_empty() && !matches!(self.auth_state, AuthState::Loading) {
+            submit_button = submit_button.on_press(AppMessage::AttemptLogin);
+        }
+
         let switch_button: Button<_> = button("Don't have an account? Create")
             .on_press(AppMessage::SetState(AppState::Create(CreateState {
                 email: state.email.clone(),
-                username: String::new(),
-                password: String::new(),
+                ..CreateState::default()
             })))
             .padding(10)
             .width(Length::Fill);
 
-        let content: Column<_> = column![
-            title,
-            status_text,
-            email_input,
-            password_input,
-            submit_button,
-            switch_button
-        ]
-        .spacing(10);
+        let mut content: Column<_> = column![title, status_text, email_input].spacing(10);
+        content = self.push_field_error(content, FormField::Email, &state.email, &errors);
+        content = content.push(password_input);
+        content = self.push_field_error(content, FormField::Password, &state.password, &errors);
+        content = content.push(submit_button).push(switch_button);
 
         container(content)
             .width(Length::Fill)
@@ -462,21 +1228,35 @@ where
             AuthState::Error => text("Failed to create account").style(Palette::DARK.danger),
         };
 
+        let errors = validate_create(
+            &state.email,
+            &state.username,
+            &state.password,
+            &state.confirm_password,
+        );
+
         let email_input: TextInput<_> = text_input("Email", &state.email)
-            .padding(10)
+            .padding(5)
             .on_input(AppMessage::EmailChanged);
         let username_input: TextInput<_> = text_input("Username", &state.username)
-            .padding(10)
+            .padding(5)
             .on_input(AppMessage::UsernameChanged);
         let password_input: TextInput<_> = text_input("Password", &state.password)
-            .padding(10)
+            .padding(5)
             .password()
             .on_input(AppMessage::PasswordChanged);
+        let confirm_password_input: TextInput<_> =
+            text_input("Confirm Password", &state.confirm_password)
+                .padding(5)
+                .password()
+                .on_input(AppMessage::ConfirmPasswordChanged);
+
+        // Prevent submitting invalid details or submitting twice
+        let mut submit_button: Button<_> = button("Create").padding(10).width(Length::Fill);
+        if errors.is_empty() && !matches!(self.auth_state, AuthState::Loading) {
+            submit_button = submit_button.on_press(AppMessage::AttemptCreate);
+        }
 
-        let submit_button: Button<_> = button("Create")
-            .on_press(AppMessage::AttemptCreate)
-            .padding(10)
-            .width(Length::Fill);
         let switch_button: Button<_> = button("Already have an account? Login")
             .on_press(AppMessage::SetState(AppState::Login(LoginState {
                 email: state.email.clone(),
@@ -485,16 +1265,271 @@ where
             .padding(10)
             .width(Length::Fill);
 
+        let mut content: Column<_> = column![title, status_text, email_input].spacing(5);
+        content = self.push_field_error(content, FormField::Email, &state.email, &errors);
+        content = content.push(username_input);
+        content = self.push_field_error(content, FormField::Username, &state.username, &errors);
+        content = content.push(password_input);
+        content = self.push_field_error(content, FormField::Password, &state.password, &errors);
+        content = content.push(confirm_password_input);
+        content = self.push_field_error(
+            content,
+            FormField::ConfirmPassword,
+            &state.confirm_password,
+            &errors,
+        );
+        content = content.push(submit_button).push(switch_button);
+
+        container(content)
+            .width(Length::Fill)
+            .height(Length::Fill)
+            .padding(SPACING)
+            .into()
+    }
+
+    /// Pushes the error for a form `field` onto the `column` if there is
+    /// one. Errors from the server take priority, validation errors are
+    /// only shown once something has been entered
+    fn push_field_error<'a>(
+        &self,
+        column: Column<'a, AppMessage>,
+        field: FormField,
+        value: &str,
+        errors: &[(FormField, ValidationError)],
+    ) -> Column<'a, AppMessage> {
+        let message = match &self.field_error {
+            Some((error_field, message)) if *error_field == field => Some(message.clone()),
+            _ if value.is_empty() => None,
+            _ => errors
+                .iter()
+                .find(|(error_field, _)| *error_field == field)
+                .map(|(_, err)| err.to_string()),
+        };
+
+        match message {
+            Some(message) => column.push(text(message).size(14).style(RED_TEXT)),
+            None => column,
+        }
+    }
+
+    /// Clears the server error for `field` once its value changes
+    fn clear_field_error(&mut self, field: FormField) {
+        if matches!(&self.field_error, Some((error_field, _)) if *error_field == field) {
+            self.field_error = None;
+        }
+    }
+
+    fn account_view(
+        &self,
+        form: &AccountForm,
+    ) -> iced::Element<'_, <Self as Application>::Message> {
+        let title = text("Account").style(DARK_TEXT);
+
+        let info_text: Text = match self.controller.account() {
+            Some(account) => text(format!(
+                "Account #{} {} ({}){}",
+                account.id,
+                account.username,
+                account.email,
+                account
+                    .created_at
+                    .as_ref()
+                    .map(|value| format!(" created {}", value))
+                    .unwrap_or_default()
+            ))
+            .style(DARK_TEXT),
+            None => text("Loading account...").style(YELLOW_TEXT),
+        };
+
+        let username_input: TextInput<_> = text_input("Username", &form.username)
+            .padding(5)
+            .on_input(AppMessage::AccountUsernameChanged);
+        let email_input: TextInput<_> = text_input("Email", &form.email)
+            .padding(5)
+            .on_input(AppMessage::AccountEmailChanged);
+        let save_button: Button<_> = button("Save details")
+            .on_press(AppMessage::SaveAccount)
+            .padding(5);
+
+        let current_password_input: TextInput<_> =
+            text_input("Current password", &form.current_password)
+                .padding(5)
+                .password()
+                .on_input(AppMessage::CurrentPasswordChanged);
+        let new_password_input: TextInput<_> = text_input("New password", &form.new_password)
+            .padding(5)
+            .password()
+            .on_input(AppMessage::NewPasswordChanged);
+        let password_button: Button<_> = button("Change password")
+            .on_press(AppMessage::ChangePassword)
+            .padding(5);
+
+        let back_button: Button<_> = button("Back").on_press(AppMessage::CloseAccount).padding(5);
+
         let content: Column<_> = column![
             title,
-            status_text,
-            email_input,
+            info_text,
             username_input,
-            password_input,
-            submit_button,
-            switch_button
+            email_input,
+            save_button,
+            current_password_input,
+            new_password_input,
+            password_button,
+            back_button
         ]
-        .spacing(10);
+        .spacing(SPACING);
+
+        container(content)
+            .width(Length::Fill)
+            .height(Length::Fill)
+            .padding(SPACING)
+            .into()
+    }
+
+    fn logs_view(&self, logs: &LogsState) -> iced::Element<'_, <Self as Application>::Message> {
+        let title = text("Logs").style(DARK_TEXT);
+
+        // Level filter, the selected level can't be pressed
+        let levels: Row<_> = [
+            Level::Error,
+            Level::Warn,
+            Level::Info,
+            Level::Debug,
+            Level::Trace,
+        ]
+        .into_iter()
+        .fold(row![].spacing(5), |row, level| {
+            let mut level_button: Button<_> = button(text(level.as_str())).padding(5);
+            if logs.level != level {
+                level_button = level_button.on_press(AppMessage::LogLevelChanged(level));
+            }
+            row.push(level_button)
+        });
+
+        let search_input: TextInput<_> = text_input("Search", &logs.search)
+            .padding(5)
+            .on_input(AppMessage::LogSearchChanged);
+
+        let lines = if logs.lines.is_empty() {
+            "No matching log lines".to_string()
+        } else {
+            logs_text(&logs.lines)
+        };
+        let lines = scrollable(text(lines).size(12).width(Length::Fill))
+            .width(Length::Fill)
+            .height(Length::Fill);
+
+        let copy_button: Button<_> = button("Copy logs")
+            .on_press(AppMessage::CopyLogs)
+            .padding(5);
+        let folder_button: Button<_> = button("Open log folder")
+            .on_press(AppMessage::OpenLogFolder)
+            .padding(5);
+        let back_button: Button<_> = button("Back").on_press(AppMessage::CloseLogs).padding(5);
+
+        let content: Column<_> = column![
+            title,
+            levels,
+            search_input,
+            lines,
+            row![copy_button, folder_button, back_button].spacing(SPACING)
+        ]
+        .spacing(SPACING);
+
+        container(content)
+            .width(Length::Fill)
+            .height(Length::Fill)
+            .padding(SPACING)
+            .into()
+    }
+
+    fn update_view(
+        &self,
+        info: &UpdateInfo,
+        progress: Option<&DownloadProgress>,
+    ) -> iced::Element<'_, <Self as Application>::Message> {
+        let title = text("New version is available").style(ORANGE_TEXT);
+        let versions_text: Text = text(format!(
+            "Your version: v{}  Latest version: v{}",
+            info.current_version, info.version
+        ))
+        .style(DARK_TEXT);
+
+        let release_notes = if info.release_notes.is_empty() {
+            "No release notes were provided for this version"
+        } else {
+            info.release_notes.as_str()
+        };
+
+        let notes = scrollable(text(release_notes).width(Length::Fill))
+            .width(Length::Fill)
+            .height(Length::Fill);
+
+        let actions: Column<_> = match progress {
+            // Prompting to update
+            None => {
+                let update_button: Button<_> = button("Update")
+                    .on_press(AppMessage::StartUpdate)
+                    .padding(5);
+                let skip_button: Button<_> = button("Skip this version")
+                    .on_press(AppMessage::SkipUpdate)
+                    .padding(5);
+                let dismiss_button: Button<_> = button("Not now")
+                    .on_press(AppMessage::DismissUpdate)
+                    .padding(5);
+
+                column![row![update_button, skip_button, dismiss_button].spacing(SPACING)]
+            }
+            // Downloading the update
+            Some(progress) => {
+                let bar = progress_bar(0.0..=100.0, progress.percent().unwrap_or_default());
+                let progress_text: Text =
+                    text(format!("Downloading... {}", progress)).style(YELLOW_TEXT);
+                let cancel_button: Button<_> = button("Cancel")
+                    .on_press(AppMessage::CancelUpdate)
+                    .padding(5);
+
+                column![bar, row![progress_text, cancel_button].spacing(SPACING)].spacing(SPACING)
+            }
+        };
+
+        let content: Column<_> = column![title, versions_text, notes, actions].spacing(SPACING);
+
+        container(content)
+            .width(Length::Fill)
+            .height(Length::Fill)
+            .padding(SPACING)
+            .into()
+    }
+
+    fn diagnostics_view(
+        &self,
+        report: Option<&str>,
+    ) -> iced::Element<'_, <Self as Application>::Message> {
+        let title = text("Connection Diagnostics").style(DARK_TEXT);
+
+        let content: Column<_> = match report {
+            None => column![title, text("Running diagnostics...").style(YELLOW_TEXT)],
+            Some(report) => {
+                let report = scrollable(text(report).width(Length::Fill))
+                    .width(Length::Fill)
+                    .height(Length::Fill);
+
+                let copy_button: Button<_> = button("Copy report")
+                    .on_press(AppMessage::CopyDiagnostics)
+                    .padding(5);
+                let close_button: Button<_> = button("Close")
+                    .on_press(AppMessage::CloseDiagnostics)
+                    .padding(5);
+
+                column![
+                    title,
+                    report,
+                    row![copy_button, close_button].spacing(SPACING)
+                ]
+            }
+        }
+        .spacing(SPACING);
 
         container(content)
             .width(Length::Fill)
@@ -504,25 +1539,113 @@ where
     }
 
     fn running_view(&self) -> iced::Element<'_, <Self as Application>::Message> {
-        let status_text: Text = match &self.lookup_result {
-            LookupState::None => text("Not Connected.").style(ORANGE_TEXT),
-            LookupState::Loading => text("Connecting...").style(YELLOW_TEXT),
-            LookupState::Success(lookup_data) => text(format!(
-                "Connected: {} {} version v{}",
-                lookup_data.url.scheme(),
-                lookup_data.url.authority(),
-                lookup_data.version
-            ))
-            .style(Palette::DARK.success),
-            LookupState::Error => text("Failed to connect").style(Palette::DARK.danger),
+        let ConnectionState::Running {
+            lookup_data,
+            status,
+            ..
+        } = self.controller.state()
+        else {
+            return self.base_view();
         };
 
+        let status_text: Text = text(format!(
+            "Connected: {} {} version v{}",
+            lookup_data.url.scheme(),
+            lookup_data.url.authority(),
+            lookup_data.version
+        ))
+        .style(Palette::DARK.success);
+
         let disconnect_button: Button<_> = button("Disconnect")
             .on_press(AppMessage::Disconnect)
             .padding(5)
             .width(Length::Fill);
+        let account_button: Button<_> = button("Account")
+            .on_press(AppMessage::OpenAccount)
+            .padding(5)
+            .width(Length::Fill);
+        let logs_button: Button<_> = button("Logs")
+            .on_press(AppMessage::OpenLogs)
+            .padding(5)
+            .width(Length::Fill);
+        let logout_button: Button<_> = button("Logout")
+            .on_press(AppMessage::Logout)
+            .padding(5)
+            .width(Length::Fill);
+        // Hiding is only possible when the tray icon could be created
+        let mut hide_button: Button<_> = button("Minimise to tray").padding(5).width(Length::Fill);
+        if self.tray.is_some() {
+            hide_button = hide_button.on_press(AppMessage::HideToTray);
+        }
+        let logs_row: Row<_> = row![logs_button, hide_button].spacing(SPACING);
 
-        let content: Column<_> = column![status_text, disconnect_button].spacing(10);
+        // Launching is disabled while the game is already starting or running
+        let mut launch_button: Button<_> = button("Launch game").padding(5).width(Length::Fill);
+        if !self.game_status.is_active() {
+            launch_button = launch_button.on_press(AppMessage::LaunchGame);
+        }
+
+        let game_color = match &self.game_status {
+            GameStatus::Running => Palette::DARK.success,
+            GameStatus::NotStarted => Palette::DARK.danger,
+            GameStatus::NotLaunched | GameStatus::Launching | GameStatus::Exited => DARK_TEXT,
+        };
+        let game_text: Text = text(format!("Game: {}", self.game_status)).style(game_color);
+
+        let connection_color = match status {
+            ConnectionStatus::Connected => Palette::DARK.success,
+            ConnectionStatus::Unreachable | ConnectionStatus::Reauthenticating => YELLOW_TEXT,
+            ConnectionStatus::AuthExpired => Palette::DARK.danger,
+        };
+        let connection_text: Text = text(format!("Status: {}", status)).style(connection_color);
+
+        let tunnel_color = match &self.tunnel_status {
+            TunnelStatus::Running => Palette::DARK.success,
+            TunnelStatus::Disabled | TunnelStatus::Unavailable => DARK_TEXT,
+            TunnelStatus::Failed(_) => Palette::DARK.danger,
+        };
+        let tunnel_text: Text = text(format!("Tunnel: {}", self.tunnel_status)).style(tunnel_color);
+
+        let nat_color = match &self.nat_status {
+            NatStatus::Classified(report) => match report.nat_type {
+                NatType::Open => Palette::DARK.success,
+                NatType::Moderate => YELLOW_TEXT,
+                NatType::Strict => Palette::DARK.danger,
+                NatType::Unknown => DARK_TEXT,
+            },
+            NatStatus::Unknown | NatStatus::Checking => DARK_TEXT,
+        };
+        let nat_text: Text = text(format!("NAT: {}", self.nat_status)).style(nat_color);
+        let nat_advice: Text = match &self.nat_status {
+            NatStatus::Classified(report) => {
+                text(report.recommendation(&self.tunnel_status)).style(DARK_TEXT)
+            }
+            _ => text(""),
+        };
+
+        let port_mapping_color = match &self.port_mapping_status {
+            PortMappingStatus::Mapped { .. } => Palette::DARK.success,
+            PortMappingStatus::Inactive | PortMappingStatus::Requesting => DARK_TEXT,
+            PortMappingStatus::Failed(_) => Palette::DARK.danger,
+        };
+        let port_mapping_text: Text =
+            text(format!("Port mapping: {}", self.port_mapping_status)).style(port_mapping_color);
+
+        let content: Column<_> = column![
+            status_text,
+            connection_text,
+            tunnel_text,
+            nat_text,
+            nat_advice,
+            port_mapping_text,
+            game_text,
+            launch_button,
+            account_button,
+            logs_row,
+            logout_button,
+            disconnect_button
+        ]
+        .spacing(10);
 
         container(content)
             .width(Length::Fill)
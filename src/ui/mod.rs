@@ -4,6 +4,9 @@ pub mod iced;
 // Windows native UI variant
 #[cfg(feature = "native")]
 pub mod native;
+// Tray icon for the iced UI variant
+#[cfg(feature = "iced")]
+mod tray;
 
 #[cfg(feature = "iced")]
 pub use iced::init;
@@ -132,3 +135,34 @@ pub fn show_confirm(title: &str, text: &str) -> bool {
         .show_confirm()
         .unwrap()
 }
+
+/// Shows a controller error to the user, errors without a
+/// title aren't shown
+///
+/// ## Arguments
+/// * `err` - The error to show
+pub fn report_error(err: &crate::controller::ControllerError) {
+    if let Some(title) = err.title() {
+        show_error(title, &err.to_string());
+    }
+}
+
+/// Asks the user to confirm connecting despite any compatibility
+/// warning from the last connection, disconnecting if declined.
+/// Returns whether the connection was kept
+///
+/// ## Arguments
+/// * `controller` - The controller that connected
+pub fn confirm_compatibility(controller: &mut crate::controller::Controller) -> bool {
+    let Some(message) = controller.take_compatibility_warning() else {
+        return true;
+    };
+
+    let message = format!("{}\n\nWould you like to connect anyway?", message);
+    if show_confirm("Server compatibility", &message) {
+        return true;
+    }
+
+    controller.disconnect();
+    false
+}
@@ -1,27 +1,65 @@
-use super::{show_error, show_info, ICON_BYTES, WINDOW_TITLE};
+use super::{
+    confirm_compatibility, report_error, show_error, show_info, show_warning, ICON_BYTES,
+    WINDOW_TITLE,
+};
 use crate::{
-    config::{write_config_file, ClientConfig},
-    core::{
-        api::{lookup_server, LookupData},
-        reqwest::Client,
-        servers::stop_server_tasks,
-    },
+    account::UpdateAccountRequest,
+    config::{read_config_file, ClientConfig},
+    controller::{ConnectionState, Controller, ControllerError, Transition},
+    core::reqwest::Client,
+    diagnostics::run_diagnostics,
+    discovery::{discover_servers, DiscoveredServer, DISCOVERY_ENABLED},
+    heartbeat::HeartbeatEvent,
+    launch::{game_status, launch_game, subscribe_game_status, GameStatus},
+    logging::{logs_text, open_log_folder, recent_logs},
+    nat::{nat_status, subscribe_nat_status, NatStatus},
     patch::{try_patch_game, try_remove_patch},
-    servers::start_all_servers,
-    update,
+    portmap::{port_mapping_status, subscribe_port_mapping_status},
+    servers::{
+        set_failure_dialogs, subscribe_server_failures, subscribe_tunnel_status, tunnel_status,
+        ServerFailure,
+    },
+    target::{parse_target, TargetError},
+    update::{self, source::BoxFuture, DownloadProgress, UpdateInfo, Updater},
+    validation::{server_error_field, validate_create, validate_login, FormField},
 };
+use log::{error, Level};
 use native_windows_derive::{NwgPartial, NwgUi};
 use native_windows_gui::{init as nwg_init, *};
 use parking_lot::Mutex;
-use pocket_ark_client_shared::{
-    api::{create_user, login_user, AuthToken, CreateUserRequest, LoginUserRequest},
-    Url,
+use std::{
+    cell::{Cell, RefCell},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
 };
-use std::{cell::RefCell, sync::Arc};
+use tokio::task::JoinHandle;
 
 /// Size of the created window
 pub const WINDOW_SIZE: (i32, i32) = (500, 400);
 
+/// Longest tooltip the tray icon can show
+const TRAY_TIP_LENGTH: usize = 127;
+
+/// Levels that can be chosen in the log viewer filter
+const LOG_LEVELS: [Level; 5] = [
+    Level::Error,
+    Level::Warn,
+    Level::Info,
+    Level::Debug,
+    Level::Trace,
+];
+
+/// Creates the text for a form field label including the
+/// error for the `field` if there is one
+fn field_label(name: &str, field: FormField, errors: &[(FormField, String)]) -> String {
+    match errors.iter().find(|(error_field, _)| *error_field == field) {
+        Some((_, error)) => format!("{} - {}", name, error),
+        None => name.to_string(),
+    }
+}
+
 /// Partial UI for the connect screen
 #[derive(NwgPartial, Default)]
 pub struct ConnectPartial {
@@ -39,19 +77,24 @@ pub struct ConnectPartial {
     #[nwg_layout_item(layout: grid, row: 1, col_span: 2)]
     target_url_input: TextInput,
 
+    /// Label for the normalised connection URL or why its invalid
+    #[nwg_control(text: "")]
+    #[nwg_layout_item(layout: grid, row: 2, col_span: 2)]
+    target_url_label: Label,
+
     /// Button for connecting
     #[nwg_control(text: "Connect")]
-    #[nwg_layout_item(layout: grid,  row: 2, col_span: 2)]
+    #[nwg_layout_item(layout: grid,  row: 3, col_span: 2)]
     connect_button: Button,
 
     /// Checkbox for whether to remember the connection URL
     #[nwg_control(text: "Save connection URL")]
-    #[nwg_layout_item(layout: grid,row: 3, col_span: 2)]
+    #[nwg_layout_item(layout: grid,row: 4, col_span: 2)]
     remember_checkbox: CheckBox,
 
     /// Label for the state
     #[nwg_control(text: "Disconnected")]
-    #[nwg_layout_item(layout: grid, row: 4, col_span: 2)]
+    #[nwg_layout_item(layout: grid, row: 5, col_span: 2)]
     state_label: Label,
 
     /// Label for patching the game
@@ -59,18 +102,52 @@ pub struct ConnectPartial {
         "You must patch your game in order to make it compatible with\n\
         Pocket Ark.",
     )]
-    #[nwg_layout_item(layout: grid, row: 5, col_span: 2)]
+    #[nwg_layout_item(layout: grid, row: 6, col_span: 2)]
     patch_label: Label,
 
     /// Button for connecting
     #[nwg_control(text: "Patch")]
-    #[nwg_layout_item(layout: grid, col: 0, row: 6, col_span: 1)]
+    #[nwg_layout_item(layout: grid, col: 0, row: 7, col_span: 1)]
     patch_button: Button,
 
     /// Button for connecting
     #[nwg_control(text: "Remove Patch")]
-    #[nwg_layout_item(layout: grid, col: 1, row: 6, col_span: 1)]
+    #[nwg_layout_item(layout: grid, col: 1, row: 7, col_span: 1)]
     remove_patch_button: Button,
+
+    /// Button for running connection diagnostics
+    #[nwg_control(text: "Diagnostics")]
+    #[nwg_layout_item(layout: grid, row: 8, col_span: 2)]
+    diagnostics_button: Button,
+
+    /// Button for finding LAN servers
+    #[nwg_control(text: "Find LAN servers")]
+    #[nwg_layout_item(layout: grid, col: 0, row: 9, col_span: 1)]
+    discover_button: Button,
+
+    /// Label for the LAN discovery state
+    #[nwg_control(text: "")]
+    #[nwg_layout_item(layout: grid, col: 1, row: 9, col_span: 1)]
+    discover_label: Label,
+
+    /// List of discovered LAN servers
+    #[nwg_control]
+    #[nwg_layout_item(layout: grid, row: 10, row_span: 2, col_span: 2)]
+    discovered_list: ListBox<String>,
+}
+
+impl ConnectPartial {
+    /// Removes the LAN discovery controls from the layout, used
+    /// while discovery is disabled
+    fn hide_discovery(&self) {
+        self.grid.remove_child(&self.discover_button);
+        self.grid.remove_child(&self.discover_label);
+        self.grid.remove_child(&self.discovered_list);
+
+        self.discover_button.set_visible(false);
+        self.discover_label.set_visible(false);
+        self.discovered_list.set_visible(false);
+    }
 }
 
 /// Partial UI for the login screen
@@ -158,24 +235,39 @@ pub struct CreatePartial {
     #[nwg_layout_item(layout: grid, row: 5)]
     password_input: TextInput,
 
+    /// Label for the confirm password
+    #[nwg_control(text: "Confirm Password")]
+    #[nwg_layout_item(layout: grid, row: 6)]
+    confirm_password_label: Label,
+
+    /// Input for the confirm password
+    #[nwg_control(limit: 99, password: Some('*'))]
+    #[nwg_layout_item(layout: grid, row: 7)]
+    confirm_password_input: TextInput,
+
     /// Button for logging in
     #[nwg_control(text: "Create")]
-    #[nwg_layout_item(layout: grid, row: 6)]
+    #[nwg_layout_item(layout: grid, row: 8)]
     create_button: Button,
 
     /// Label for the state
     #[nwg_control(text: "Not authenticated")]
-    #[nwg_layout_item(layout: grid, row: 7)]
+    #[nwg_layout_item(layout: grid, row: 9)]
     state_label: Label,
 
     /// Button for logging instead
     #[nwg_control(text: "Already have an account? Login")]
-    #[nwg_layout_item(layout: grid, row: 8)]
+    #[nwg_layout_item(layout: grid, row: 10)]
     swap_button: Button,
 
+    /// Button for logging out
+    #[nwg_control(text: "Logout")]
+    #[nwg_layout_item(layout: grid, row: 11)]
+    logout_button: Button,
+
     /// Button for disconnecting
     #[nwg_control(text: "Disconnect")]
-    #[nwg_layout_item(layout: grid, row: 9)]
+    #[nwg_layout_item(layout: grid, row: 12)]
     disconnect_button: Button,
 }
 
@@ -191,17 +283,224 @@ pub struct RunningPartial {
     #[nwg_layout_item(layout: grid, row: 0)]
     state_label: Label,
 
-    /// Label for keeping the program running
-    #[nwg_control(text: "You must keep this program running while playing.")]
+    /// Connectivity status label updated by the heartbeat
+    #[nwg_control(text: "Status: Server reachable")]
     #[nwg_layout_item(layout: grid, row: 1)]
+    status_label: Label,
+
+    /// Tunnel server status label
+    #[nwg_control(text: "Tunnel: Disabled")]
+    #[nwg_layout_item(layout: grid, row: 2)]
+    tunnel_label: Label,
+
+    /// NAT type label
+    #[nwg_control(text: "NAT: Unknown")]
+    #[nwg_layout_item(layout: grid, row: 3)]
+    nat_label: Label,
+
+    /// Recommendation based on the NAT type
+    #[nwg_control(text: "")]
+    #[nwg_layout_item(layout: grid, row: 4)]
+    nat_advice_label: Label,
+
+    /// Port mapping status label
+    #[nwg_control(text: "Port mapping: Inactive")]
+    #[nwg_layout_item(layout: grid, row: 5)]
+    port_mapping_label: Label,
+
+    /// Launched game status label
+    #[nwg_control(text: "Game: Not launched")]
+    #[nwg_layout_item(layout: grid, row: 6)]
+    game_label: Label,
+
+    /// Label for keeping the program running
+    #[nwg_control(text: "You must keep this program running while playing, minimise it to the tray.")]
+    #[nwg_layout_item(layout: grid, row: 7)]
     keep_alive_label: Label,
 
+    /// Button for launching the game
+    #[nwg_control(text: "Launch game")]
+    #[nwg_layout_item(layout: grid, row: 8)]
+    launch_button: Button,
+
+    /// Button for opening the account panel
+    #[nwg_control(text: "Account")]
+    #[nwg_layout_item(layout: grid, row: 9)]
+    account_button: Button,
+
+    /// Button for opening the log viewer
+    #[nwg_control(text: "Logs")]
+    #[nwg_layout_item(layout: grid, row: 10)]
+    logs_button: Button,
+
+    /// Button for logging out
+    #[nwg_control(text: "Logout")]
+    #[nwg_layout_item(layout: grid, row: 11)]
+    logout_button: Button,
+
     /// Button for disconnecting
     #[nwg_control(text: "Disconnect")]
-    #[nwg_layout_item(layout: grid, row: 2)]
+    #[nwg_layout_item(layout: grid, row: 12)]
     disconnect_button: Button,
 }
 
+/// Partial UI for the log viewer
+#[derive(NwgPartial, Default)]
+pub struct LogsPartial {
+    /// Grid layout for all the content
+    #[nwg_layout]
+    grid: GridLayout,
+
+    /// Filter for the minimum level of lines to show
+    #[nwg_control(
+        collection: LOG_LEVELS.iter().map(|level| level.as_str()).collect(),
+        selected_index: Some(2)
+    )]
+    #[nwg_layout_item(layout: grid, row: 0, col: 0)]
+    level_input: ComboBox<&'static str>,
+
+    /// Input for text the shown lines must contain
+    #[nwg_control(placeholder_text: Some("Search"))]
+    #[nwg_layout_item(layout: grid, row: 0, col: 1, col_span: 3)]
+    search_input: TextInput,
+
+    /// Scrollable text box for the log lines
+    #[nwg_control(readonly: true, flags: "VISIBLE|VSCROLL|AUTOVSCROLL")]
+    #[nwg_layout_item(layout: grid, row: 1, row_span: 6, col_span: 4)]
+    logs_text: TextBox,
+
+    /// Button for reloading the log lines
+    #[nwg_control(text: "Refresh")]
+    #[nwg_layout_item(layout: grid, row: 7, col: 0)]
+    refresh_button: Button,
+
+    /// Button for copying the shown log lines
+    #[nwg_control(text: "Copy logs")]
+    #[nwg_layout_item(layout: grid, row: 7, col: 1)]
+    copy_button: Button,
+
+    /// Button for opening the log folder
+    #[nwg_control(text: "Open folder")]
+    #[nwg_layout_item(layout: grid, row: 7, col: 2)]
+    folder_button: Button,
+
+    /// Button for returning to the running screen
+    #[nwg_control(text: "Back")]
+    #[nwg_layout_item(layout: grid, row: 7, col: 3)]
+    back_button: Button,
+}
+
+/// Partial UI for the account panel
+#[derive(NwgPartial, Default)]
+pub struct AccountPartial {
+    /// Grid layout for all the content
+    #[nwg_layout]
+    grid: GridLayout,
+
+    /// Label for the account details
+    #[nwg_control(text: "Loading account...")]
+    #[nwg_layout_item(layout: grid, row: 0)]
+    info_label: Label,
+
+    /// Label for the username
+    #[nwg_control(text: "Username")]
+    #[nwg_layout_item(layout: grid, row: 1)]
+    username_label: Label,
+
+    /// Input for the username
+    #[nwg_control]
+    #[nwg_layout_item(layout: grid, row: 2)]
+    username_input: TextInput,
+
+    /// Label for the email
+    #[nwg_control(text: "Email")]
+    #[nwg_layout_item(layout: grid, row: 3)]
+    email_label: Label,
+
+    /// Input for the email
+    #[nwg_control]
+    #[nwg_layout_item(layout: grid, row: 4)]
+    email_input: TextInput,
+
+    /// Button for saving the username and email
+    #[nwg_control(text: "Save details")]
+    #[nwg_layout_item(layout: grid, row: 5)]
+    save_button: Button,
+
+    /// Label for the current password
+    #[nwg_control(text: "Current password")]
+    #[nwg_layout_item(layout: grid, row: 6)]
+    current_password_label: Label,
+
+    /// Input for the current password
+    #[nwg_control(password: Some('*'))]
+    #[nwg_layout_item(layout: grid, row: 7)]
+    current_password_input: TextInput,
+
+    /// Label for the new password
+    #[nwg_control(text: "New password")]
+    #[nwg_layout_item(layout: grid, row: 8)]
+    new_password_label: Label,
+
+    /// Input for the new password
+    #[nwg_control(password: Some('*'))]
+    #[nwg_layout_item(layout: grid, row: 9)]
+    new_password_input: TextInput,
+
+    /// Button for changing the password
+    #[nwg_control(text: "Change password")]
+    #[nwg_layout_item(layout: grid, row: 10)]
+    password_button: Button,
+
+    /// Button for returning to the running screen
+    #[nwg_control(text: "Back")]
+    #[nwg_layout_item(layout: grid, row: 11)]
+    back_button: Button,
+}
+
+/// Partial UI for the update prompt and download progress
+#[derive(NwgPartial, Default)]
+pub struct UpdatePartial {
+    /// Grid layout for all the content
+    #[nwg_layout]
+    grid: GridLayout,
+
+    /// Label for the update versions
+    #[nwg_control(text: "New version is available")]
+    #[nwg_layout_item(layout: grid, row: 0, col_span: 3)]
+    version_label: Label,
+
+    /// Scrollable text box for the release notes
+    #[nwg_control(readonly: true, flags: "VISIBLE|VSCROLL|AUTOVSCROLL")]
+    #[nwg_layout_item(layout: grid, row: 1, row_span: 5, col_span: 3)]
+    notes_text: TextBox,
+
+    /// Download progress bar
+    #[nwg_control(range: 0..100)]
+    #[nwg_layout_item(layout: grid, row: 6, col_span: 3)]
+    progress_bar: ProgressBar,
+
+    /// Label for the download progress
+    #[nwg_control(text: "")]
+    #[nwg_layout_item(layout: grid, row: 7, col_span: 3)]
+    progress_label: Label,
+
+    /// Button for updating
+    #[nwg_control(text: "Update")]
+    #[nwg_layout_item(layout: grid, col: 0, row: 8)]
+    update_button: Button,
+
+    /// Button for skipping the version
+    #[nwg_control(text: "Skip this version")]
+    #[nwg_layout_item(layout: grid, col: 1, row: 8)]
+    skip_button: Button,
+
+    /// Button for dismissing the update or cancelling the download
+    #[nwg_control(text: "Not now")]
+    #[nwg_layout_item(layout: grid, col: 2, row: 8)]
+    dismiss_button: Button,
+}
+
 /// Native GUI app
 #[derive(NwgUi, Default)]
 pub struct App {
@@ -217,9 +516,60 @@ pub struct App {
         title: WINDOW_TITLE,
         flags: "WINDOW|VISIBLE|MINIMIZE_BOX"
     )]
-    #[nwg_events(OnWindowClose: [stop_thread_dispatch()])]
+    #[nwg_events(
+        OnWindowClose: [stop_thread_dispatch()],
+        OnWindowMinimize: [App::handle_minimize]
+    )]
     window: Window,
 
+    /// Tray icon the window is minimised to
+    #[nwg_control(parent: window, icon: Some(&data.icon), tip: Some(WINDOW_TITLE))]
+    #[nwg_events(
+        MousePressLeftUp: [App::handle_tray_show],
+        OnContextMenu: [App::handle_tray_menu]
+    )]
+    tray: TrayNotification,
+
+    /// Menu of quick actions shown from the tray icon
+    #[nwg_control(parent: window, popup: true)]
+    tray_menu: Menu,
+
+    /// Tray menu item for showing the window
+    #[nwg_control(parent: tray_menu, text: "Show")]
+    #[nwg_events(OnMenuItemSelected: [App::handle_tray_show])]
+    tray_show_item: MenuItem,
+
+    /// Tray menu item for opening the log viewer
+    #[nwg_control(parent: tray_menu, text: "Open logs")]
+    #[nwg_events(OnMenuItemSelected: [App::handle_tray_logs])]
+    tray_logs_item: MenuItem,
+
+    /// Separator before the connection actions
+    #[nwg_control(parent: tray_menu)]
+    tray_separator: MenuSeparator,
+
+    /// Tray menu item for restarting the local servers
+    #[nwg_control(parent: tray_menu, text: "Reconnect")]
+    #[nwg_events(OnMenuItemSelected: [App::handle_tray_reconnect])]
+    tray_reconnect_item: MenuItem,
+
+    /// Tray menu item for disconnecting
+    #[nwg_control(parent: tray_menu, text: "Disconnect")]
+    #[nwg_events(OnMenuItemSelected: [App::handle_tray_disconnect])]
+    tray_disconnect_item: MenuItem,
+
+    /// Separator before quitting
+    #[nwg_control(parent: tray_menu)]
+    tray_quit_separator: MenuSeparator,
+
+    /// Tray menu item for quitting the client
+    #[nwg_control(parent: tray_menu, text: "Quit")]
+    #[nwg_events(OnMenuItemSelected: [App::handle_tray_quit])]
+    tray_quit_item: MenuItem,
+
+    /// Whether the user has been told the client keeps running in the tray
+    tray_hint_shown: Cell<bool>,
+
     /// Grid layout for all the content
     #[nwg_layout(parent: window)]
     grid: GridLayout,
@@ -233,6 +583,10 @@ pub struct App {
     #[nwg_partial(parent: connect_frame)]
     #[nwg_events(
         (connect_button, OnButtonClick): [App::handle_connect],
+        (target_url_input, OnTextInput): [App::handle_target_changed],
+        (discover_button, OnButtonClick): [App::handle_discover],
+        (discovered_list, OnListBoxSelect): [App::handle_discovered_select],
+        (diagnostics_button, OnButtonClick): [App::handle_diagnostics],
         (patch_button, OnButtonClick): [App::handle_patch],
         (remove_patch_button, OnButtonClick): [App::handle_remove_patch],
     )]
@@ -273,55 +627,182 @@ pub struct App {
 
     /// Running UI
     #[nwg_partial(parent: running_frame)]
-    #[nwg_events((disconnect_button, OnButtonClick): [App::handle_disconnect])]
+    #[nwg_events(
+        (launch_button, OnButtonClick): [App::handle_launch_game],
+        (account_button, OnButtonClick): [App::handle_open_account],
+        (logs_button, OnButtonClick): [App::handle_open_logs],
+        (logout_button, OnButtonClick): [App::handle_logout],
+        (disconnect_button, OnButtonClick): [App::handle_disconnect],
+    )]
     running_ui: RunningPartial,
 
+    /// Frame for the account UI
+    #[nwg_control]
+    #[nwg_layout_item(layout: grid)]
+    account_frame: Frame,
+
+    /// Account UI
+    #[nwg_partial(parent: account_frame)]
+    #[nwg_events(
+        (save_button, OnButtonClick): [App::handle_save_account],
+        (password_button, OnButtonClick): [App::handle_change_password],
+        (back_button, OnButtonClick): [App::handle_close_account],
+    )]
+    account_ui: AccountPartial,
+
+    /// Frame for the log viewer UI
+    #[nwg_control]
+    #[nwg_layout_item(layout: grid)]
+    logs_frame: Frame,
+
+    /// Log viewer UI
+    #[nwg_partial(parent: logs_frame)]
+    #[nwg_events(
+        (level_input, OnComboxBoxSelection): [App::refresh_logs],
+        (search_input, OnTextInput): [App::refresh_logs],
+        (refresh_button, OnButtonClick): [App::refresh_logs],
+        (copy_button, OnButtonClick): [App::handle_copy_logs],
+        (folder_button, OnButtonClick): [App::handle_open_log_folder],
+        (back_button, OnButtonClick): [App::handle_close_logs],
+    )]
+    logs_ui: LogsPartial,
+
+    /// Frame for the update UI
+    #[nwg_control]
+    #[nwg_layout_item(layout: grid)]
+    update_frame: Frame,
+
+    /// Update UI
+    #[nwg_partial(parent: update_frame)]
+    #[nwg_events(
+        (update_button, OnButtonClick): [App::handle_start_update],
+        (skip_button, OnButtonClick): [App::handle_skip_update],
+        (dismiss_button, OnButtonClick): [App::handle_dismiss_update],
+    )]
+    update_ui: UpdatePartial,
+
     /// Current state of the app
     app_state: RefCell<AppState>,
 
-    /// Shared reference for an optional next state decided by
-    /// some other thread or callback
-    next_state: Arc<Mutex<Option<NextState>>>,
+    /// Controller for the connection state
+    controller: RefCell<Controller>,
+
+    /// Result of a controller future completed by another thread
+    transition: Arc<Mutex<Option<Transition>>>,
+
+    /// Notice for when [App::transition] is changed
+    #[nwg_control]
+    #[nwg_events(OnNotice: [App::handle_transition])]
+    transition_notice: Notice,
+
+    /// Servers found by the last LAN discovery
+    discovered_servers: RefCell<Vec<DiscoveredServer>>,
+
+    /// Result of LAN discovery from the discovery task
+    discovery_result: Arc<Mutex<Option<Vec<DiscoveredServer>>>>,
+
+    /// Notice for when [App::discovery_result] is changed
+    #[nwg_control]
+    #[nwg_events(OnNotice: [App::handle_discovery_result])]
+    discovery_notice: Notice,
+
+    /// Report from the diagnostics task
+    diagnostics_result: Arc<Mutex<Option<String>>>,
+
+    /// Notice for when [App::diagnostics_result] is changed
+    #[nwg_control]
+    #[nwg_events(OnNotice: [App::handle_diagnostics_result])]
+    diagnostics_notice: Notice,
+
+    /// Current state of the client update
+    update_state: RefCell<UpdateState>,
+
+    /// Update events from the update task waiting to be handled
+    update_events: Arc<Mutex<Vec<UpdateEvent>>>,
+
+    /// Notice for when [App::update_events] is changed
+    #[nwg_control]
+    #[nwg_events(OnNotice: [App::handle_update_events])]
+    update_notice: Notice,
+
+    /// Background heartbeat task while running
+    heartbeat_task: RefCell<Option<JoinHandle<()>>>,
+
+    /// Heartbeat events waiting to be handled
+    heartbeat_events: Arc<Mutex<Vec<HeartbeatEvent>>>,
+
+    /// Notice for when [App::heartbeat_events] is changed
+    #[nwg_control]
+    #[nwg_events(OnNotice: [App::handle_heartbeat_events])]
+    heartbeat_notice: Notice,
+
+    /// Background task watching the network status while running
+    network_task: RefCell<Option<JoinHandle<()>>>,
+
+    /// Notice for when the tunnel, NAT, port mapping or game status changes
+    #[nwg_control]
+    #[nwg_events(OnNotice: [App::handle_network_status])]
+    network_notice: Notice,
+
+    /// Last seen status of the launched game, used to notice the game exiting
+    game_status: RefCell<GameStatus>,
+
+    /// Server failures waiting to be shown
+    server_failures: Arc<Mutex<Vec<ServerFailure>>>,
 
-    /// Notice for when [App::next_state] is changed
+    /// Notice for when [App::server_failures] is changed
     #[nwg_control]
-    #[nwg_events(OnNotice: [App::handle_next_state])]
-    next_state_notice: Notice,
+    #[nwg_events(OnNotice: [App::handle_server_failures])]
+    server_failure_notice: Notice,
 
     /// Http client for sending requests
     http_client: Client,
-}
 
-enum NextState {
-    /// Don't change the state just update the screen
-    /// state label to show an error occured
-    Error,
-    /// App State to show
-    State(AppState),
+    /// Updater for checking and installing updates
+    updater: Option<Arc<Updater>>,
 }
 
+/// State of the client update process
 #[derive(Default)]
+enum UpdateState {
+    /// No update is available or the update was dismissed
+    #[default]
+    None,
+    /// An update is available and the user is being prompted
+    Available(Arc<UpdateInfo>),
+    /// The update is being downloaded
+    Downloading {
+        /// Flag for cancelling the download
+        cancel: Arc<AtomicBool>,
+    },
+}
+
+/// Events produced by background update tasks
+enum UpdateEvent {
+    /// An update is available
+    Available(UpdateInfo),
+    /// Progress was made on the download
+    Progress(DownloadProgress),
+    /// The download completed
+    Finished(Result<(), String>),
+}
+
+/// Screen currently shown by the app
+#[derive(Default, Clone, Copy)]
 enum AppState {
     /// Connecting state
     #[default]
     Connect,
     /// Logging in state
-    Login {
-        /// Current lookup data
-        lookup_data: LookupData,
-    },
+    Login,
     /// Creating account state
-    Create {
-        /// Current lookup data
-        lookup_data: LookupData,
-    },
+    Create,
     /// Running state
-    Running {
-        /// Current lookup data
-        lookup_data: LookupData,
-        /// Current authentication token
-        auth_token: AuthToken,
-    },
+    Running,
+    /// Account panel while running
+    Account,
+    /// Log viewer while running
+    Logs,
 }
 
 impl App {
@@ -347,66 +828,114 @@ impl App {
         }
     }
 
-    /// Handles changing to a new state provided by an
-    /// external thread
-    fn handle_next_state(&self) {
-        let Some(next_state) = self.next_state.lock().take() else {
+    /// Handles applying the result of a controller future
+    /// completed by an external thread
+    fn handle_transition(&self) {
+        let Some(transition) = self.transition.lock().take() else {
             return;
         };
 
-        match next_state {
+        let changes_state = transition.changes_state();
+        let success_message = transition.success_message();
+
+        let result = self.controller.borrow_mut().apply(transition);
+
+        // Allow submitting again now the request is complete
+        self.login_ui.login_button.set_enabled(true);
+        self.create_ui.create_button.set_enabled(true);
+
+        if let Err(err) = result {
+            if !changes_state {
+                report_error(&err);
+                return;
+            }
+
+            // Field errors from the server are shown next to the input
+            let message = err.to_string();
+            match (server_error_field(&err), *self.app_state.borrow()) {
+                (Some(field), AppState::Login) => self.show_login_errors(&[(field, message)]),
+                (Some(field), AppState::Create) => self.show_create_errors(&[(field, message)]),
+                _ => report_error(&err),
+            }
+
             // Set error labels
-            NextState::Error => match &*self.app_state.borrow() {
+            match *self.app_state.borrow() {
                 AppState::Connect => {
                     self.connect_ui.state_label.set_text("Failed to connect");
                 }
-                AppState::Login { .. } => {
+                AppState::Login => {
                     self.login_ui.state_label.set_text("Failed to login");
                 }
-                AppState::Create { .. } => {
+                AppState::Create => {
                     self.create_ui.state_label.set_text("Failed to create");
                 }
-                _ => {}
-            },
-
-            // Handle changing state
-            NextState::State(next_state) => {
-                // Handle setting up the next state
-                match &next_state {
-                    AppState::Connect => {}
-                    AppState::Login { .. } => {}
-                    AppState::Create { .. } => {}
-                    AppState::Running {
-                        lookup_data,
-                        auth_token,
-                    } => {
-                        // TODO: Update connection state
-
-                        // Start all the servers
-                        start_all_servers(
-                            self.http_client.clone(),
-                            lookup_data.url.clone(),
-                            Arc::new(None),
-                            auth_token.clone(),
-                        );
-                    }
+                AppState::Running | AppState::Account | AppState::Logs => {}
+            }
+            return;
+        }
+
+        if let Some((title, text)) = success_message {
+            show_info(title, text);
+        }
+
+        // Account details changed, refresh the account panel
+        if !changes_state {
+            if let AppState::Account = *self.app_state.borrow() {
+                self.update_visible_frame();
+            }
+            return;
+        }
+
+        // Declining a compatibility warning disconnects again
+        confirm_compatibility(&mut self.controller.borrow_mut());
+
+        let next_state = match self.controller.borrow().state() {
+            ConnectionState::Disconnected | ConnectionState::Connecting => AppState::Connect,
+            ConnectionState::Connected(_) | ConnectionState::Authenticating(_) => {
+                // Keep showing the create screen if that was used
+                match *self.app_state.borrow() {
+                    AppState::Create => AppState::Create,
+                    _ => AppState::Login,
                 }
+            }
+            ConnectionState::Running { .. } => AppState::Running,
+        };
 
-                self.set_app_state(next_state);
+        if let AppState::Running = next_state {
+            self.start_heartbeat();
+            self.start_network_watch();
+        }
+
+        self.set_app_state(next_state);
+
+        // Launch the game straight away when configured to
+        if let AppState::Running = next_state {
+            let launch = read_config_file().is_some_and(|config| config.launch.on_login);
+            if launch && !game_status().is_active() {
+                self.handle_launch_game();
             }
         }
     }
 
+    /// Stores the `transition` future to be run in the background, the
+    /// App is woken up with [App::handle_transition] once complete
+    fn spawn_transition(&self, future: BoxFuture<'static, Transition>) {
+        let sender = self.transition_notice.sender();
+        let transition = self.transition.clone();
+
+        tokio::spawn(async move {
+            let result = future.await;
+            *transition.lock() = Some(result);
+            sender.notice();
+        });
+    }
+
     /// Swaps the current authentication state to the opposite
     /// (i.e Login -> Create, Create -> Login)
     fn swap_auth_state(&self) {
-        let next_state = match &*self.app_state.borrow() {
-            AppState::Login { lookup_data } => AppState::Create {
-                lookup_data: lookup_data.clone(),
-            },
-            AppState::Create { lookup_data } => AppState::Login {
-                lookup_data: lookup_data.clone(),
-            },
+        let next_state = match *self.app_state.borrow() {
+            AppState::Login => AppState::Create,
+            AppState::Create => AppState::Login,
             // Do nothing for other states
             _ => return,
         };
@@ -416,26 +945,36 @@ impl App {
     /// Sets the app state to the provided `state` then
     /// triggers a UI update
     fn set_app_state(&self, state: AppState) {
-        // Swap the state so the old state can be accessed
-        let mut old_state = state;
-        std::mem::swap(&mut *self.app_state.borrow_mut(), &mut old_state);
-
-        // Stop the server tasks if we were running
-        if let AppState::Running { .. } = old_state {
-            stop_server_tasks();
+        *self.app_state.borrow_mut() = state;
+
+        // Stop the heartbeat once we are no longer running
+        if !matches!(
+            state,
+            AppState::Running | AppState::Account | AppState::Logs
+        ) {
+            if let Some(task) = self.heartbeat_task.take() {
+                task.abort();
+            }
+            if let Some(task) = self.network_task.take() {
+                task.abort();
+            }
         }
 
         // Update the current UI
         self.update_visible_frame();
+        self.update_tray();
     }
 
     /// Collection of available frames
-    fn all_frames(&self) -> [&Frame; 4] {
+    fn all_frames(&self) -> [&Frame; 7] {
         [
             &self.connect_frame,
             &self.login_frame,
             &self.create_frame,
             &self.running_frame,
+            &self.account_frame,
+            &self.logs_frame,
+            &self.update_frame,
         ]
     }
 
@@ -461,28 +1000,45 @@ impl App {
     /// Updates the currently visible frame to match the UI
     /// and resizes the window to fit accordingly
     fn update_visible_frame(&self) {
-        match &*self.app_state.borrow() {
+        // Updates take priority over the other screens
+        if !matches!(&*self.update_state.borrow(), UpdateState::None) {
+            self.set_visible_frame(&self.update_frame);
+            self.window.set_size(500, 400);
+            return;
+        }
+
+        match *self.app_state.borrow() {
             AppState::Connect => {
                 self.set_visible_frame(&self.connect_frame);
-                self.window.set_size(500, 340);
+                self.window.set_size(500, 500);
 
                 self.connect_ui.state_label.set_text("Not connected");
             }
-            AppState::Login { .. } => {
+            AppState::Login => {
                 self.set_visible_frame(&self.login_frame);
-                self.window.set_size(500, 320);
+                self.window.set_size(500, 360);
 
                 self.login_ui.state_label.set_text("Not authenticated");
             }
-            AppState::Create { .. } => {
+            AppState::Create => {
                 self.set_visible_frame(&self.create_frame);
-                self.window.set_size(500, 400);
+                self.window.set_size(500, 460);
 
                 self.create_ui.state_label.set_text("Not authenticated");
             }
-            AppState::Running { lookup_data, .. } => {
+            AppState::Running => {
                 self.set_visible_frame(&self.running_frame);
-                self.window.set_size(500, 160);
+                self.window.set_size(500, 490);
+
+                let controller = self.controller.borrow();
+                let ConnectionState::Running {
+                    lookup_data,
+                    status,
+                    ..
+                } = controller.state()
+                else {
+                    return;
+                };
 
                 let text = format!(
                     "Connected: {} {} version v{}",
@@ -490,138 +1046,759 @@ impl App {
                     lookup_data.url.authority(),
                     lookup_data.version
                 );
-                self.running_ui.state_label.set_text(&text)
+                self.running_ui.state_label.set_text(&text);
+                self.running_ui
+                    .status_label
+                    .set_text(&format!("Status: {}", status));
+                self.handle_network_status();
+            }
+            AppState::Account => {
+                self.set_visible_frame(&self.account_frame);
+                self.window.set_size(500, 480);
+
+                let controller = self.controller.borrow();
+                let Some(account) = controller.account() else {
+                    self.account_ui.info_label.set_text("Loading account...");
+                    return;
+                };
+
+                let mut text = format!(
+                    "Account #{} {} ({})",
+                    account.id, account.username, account.email
+                );
+                if let Some(created_at) = &account.created_at {
+                    text.push_str(&format!(" created {}", created_at));
+                }
+                self.account_ui.info_label.set_text(&text);
+                self.account_ui.username_input.set_text(&account.username);
+                self.account_ui.email_input.set_text(&account.email);
+                self.account_ui.current_password_input.set_text("");
+                self.account_ui.new_password_input.set_text("");
+            }
+            AppState::Logs => {
+                self.set_visible_frame(&self.logs_frame);
+                self.window.set_size(500, 480);
+
+                self.refresh_logs();
             }
         }
     }
 
-    /// Handles the "Set" button being pressed, dispatches a connect task
-    /// that will wake up the App with `App::handle_connect_notice` to
-    /// handle the connection result.
-    fn handle_connect(&self) {
-        self.connect_ui.state_label.set_text("Connecting...");
-
-        let target = self.connect_ui.target_url_input.text();
+    /// Handles the connection URL input changing, shows the URL that
+    /// will be connected to or why the URL is invalid
+    fn handle_target_changed(&self) {
+        let text = match parse_target(&self.connect_ui.target_url_input.text()) {
+            Ok(url) => format!("Will connect to {}", url),
+            // No need to show an error before anything is entered
+            Err(TargetError::Empty) => String::new(),
+            Err(err) => err.to_string(),
+        };
 
-        let http_client = self.http_client.clone();
-        let sender = self.next_state_notice.sender();
-        let next_state = self.next_state.clone();
+        self.connect_ui.target_url_label.set_text(&text);
+    }
 
-        let remember = self.connect_ui.remember_checkbox.check_state() == CheckBoxState::Checked;
+    /// Handles the "Find LAN servers" button being pressed, dispatches a
+    /// discovery task that will wake up the App with the servers found
+    fn handle_discover(&self) {
+        self.connect_ui.discover_button.set_enabled(false);
+        self.connect_ui.discover_label.set_text("Searching...");
 
-        // Save the connection URL
-        if remember {
-            let connection_url = target.to_string();
-            write_config_file(ClientConfig { connection_url });
-        }
+        let sender = self.discovery_notice.sender();
+        let discovery_result = self.discovery_result.clone();
 
         tokio::spawn(async move {
-            let state = match lookup_server(http_client, target).await {
-                Ok(lookup_data) => NextState::State(AppState::Login { lookup_data }),
+            let servers = match discover_servers().await {
+                Ok(value) => value,
                 Err(err) => {
-                    show_error("Failed to lookup server", &err.to_string());
-                    NextState::Error
+                    error!("Failed to discover LAN servers: {}", err);
+                    Vec::new()
                 }
             };
 
-            let next_state = &mut *next_state.lock();
-            *next_state = Some(state);
+            *discovery_result.lock() = Some(servers);
             sender.notice();
         });
     }
 
-    fn handle_login(&self) {
-        let AppState::Login { lookup_data } = &*self.app_state.borrow() else {
+    /// Handles the result of LAN discovery
+    fn handle_discovery_result(&self) {
+        let Some(servers) = self.discovery_result.lock().take() else {
             return;
         };
 
-        self.login_ui.state_label.set_text("Authenticating...");
+        self.connect_ui.discover_button.set_enabled(true);
 
-        let email = self.login_ui.email_input.text();
-        let password = self.login_ui.password_input.text();
+        let text = if servers.is_empty() {
+            "No LAN servers found".to_string()
+        } else {
+            format!("Found {} LAN server(s)", servers.len())
+        };
+        self.connect_ui.discover_label.set_text(&text);
 
-        let request = LoginUserRequest { email, password };
+        let items = servers.iter().map(|server| server.to_string()).collect();
+        self.connect_ui.discovered_list.set_collection(items);
 
-        let http_client = self.http_client.clone();
-        let lookup_data = lookup_data.clone();
+        *self.discovered_servers.borrow_mut() = servers;
+    }
+
+    /// Handles a discovered server being selected, fills in its connection URL
+    fn handle_discovered_select(&self) {
+        let Some(index) = self.connect_ui.discovered_list.selection() else {
+            return;
+        };
 
-        let sender = self.next_state_notice.sender();
-        let next_state = self.next_state.clone();
+        if let Some(server) = self.discovered_servers.borrow().get(index) {
+            self.connect_ui
+                .target_url_input
+                .set_text(&server.url.to_string());
+            self.handle_target_changed();
+        }
+    }
 
-        tokio::spawn(async move {
-            let base_url: Url = lookup_data.url.as_ref().clone();
+    /// Handles the "Diagnostics" button being pressed, dispatches a task
+    /// running the diagnostics against the current connection URL
+    fn handle_diagnostics(&self) {
+        self.connect_ui.diagnostics_button.set_enabled(false);
+        self.connect_ui
+            .state_label
+            .set_text("Running diagnostics...");
 
-            let state = match login_user(http_client, base_url, request).await {
-                Ok(auth_token) => NextState::State(AppState::Running {
-                    lookup_data,
-                    auth_token,
-                }),
-                Err(err) => {
-                    show_error("Failed to login", &err.to_string());
-                    NextState::Error
-                }
-            };
+        let http_client = self.http_client.clone();
+        let target = self.connect_ui.target_url_input.text();
+        let game_path = read_config_file().and_then(|config| config.game_path);
 
-            let next_state = &mut *next_state.lock();
-            *next_state = Some(state);
+        let sender = self.diagnostics_notice.sender();
+        let diagnostics_result = self.diagnostics_result.clone();
+
+        tokio::spawn(async move {
+            let report = run_diagnostics(http_client, target, game_path).await;
+            *diagnostics_result.lock() = Some(report.to_string());
             sender.notice();
         });
     }
 
-    fn handle_create(&self) {
-        let AppState::Create { lookup_data } = &*self.app_state.borrow() else {
+    /// Handles the diagnostics report, copies it to the clipboard
+    /// and shows it to the user
+    fn handle_diagnostics_result(&self) {
+        let Some(report) = self.diagnostics_result.lock().take() else {
             return;
         };
 
-        self.create_ui.state_label.set_text("Creating account...");
+        self.connect_ui.diagnostics_button.set_enabled(true);
+        self.connect_ui.state_label.set_text("Diagnostics complete");
 
+        Clipboard::set_data_text(&self.window, &report);
+
+        show_info("Diagnostics report (copied to clipboard)", &report);
+    }
+
+    /// Handles the "Set" button being pressed, dispatches a connect task
+    /// that will wake up the App with [App::handle_transition] to
+    /// handle the connection result.
+    fn handle_connect(&self) {
+        let remember = self.connect_ui.remember_checkbox.check_state() == CheckBoxState::Checked;
+        let target = self.connect_ui.target_url_input.text();
+
+        let connect = {
+            let controller = &mut *self.controller.borrow_mut();
+            controller.set_remember(remember);
+            controller.connect(&target)
+        };
+
+        match connect {
+            Ok(connect) => {
+                self.connect_ui.state_label.set_text("Connecting...");
+                self.spawn_transition(connect);
+            }
+            Err(ControllerError::InvalidState) => {}
+            Err(err) => self.connect_ui.state_label.set_text(&err.to_string()),
+        }
+    }
+
+    fn handle_login(&self) {
+        let email = self.login_ui.email_input.text();
+        let password = self.login_ui.password_input.text();
+
+        let errors: Vec<(FormField, String)> = validate_login(&email, &password)
+            .into_iter()
+            .map(|(field, err)| (field, err.to_string()))
+            .collect();
+        self.show_login_errors(&errors);
+        if !errors.is_empty() {
+            return;
+        }
+
+        let login = self.controller.borrow_mut().login(email, password);
+        if let Ok(login) = login {
+            // Prevent submitting twice
+            self.login_ui.login_button.set_enabled(false);
+            self.login_ui.state_label.set_text("Authenticating...");
+            self.spawn_transition(login);
+        }
+    }
+
+    fn handle_create(&self) {
         let email = self.create_ui.email_input.text();
         let username = self.create_ui.username_input.text();
         let password = self.create_ui.password_input.text();
+        let confirm_password = self.create_ui.confirm_password_input.text();
+
+        let errors: Vec<(FormField, String)> =
+            validate_create(&email, &username, &password, &confirm_password)
+                .into_iter()
+                .map(|(field, err)| (field, err.to_string()))
+                .collect();
+        self.show_create_errors(&errors);
+        if !errors.is_empty() {
+            return;
+        }
+
+        let create = self
+            .controller
+            .borrow_mut()
+            .create(email, username, password);
+        if let Ok(create) = create {
+            // Prevent submitting twice
+            self.create_ui.create_button.set_enabled(false);
+            self.create_ui.state_label.set_text("Creating account...");
+            self.spawn_transition(create);
+        }
+    }
+
+    /// Shows the `errors` next to the matching login form inputs
+    fn show_login_errors(&self, errors: &[(FormField, String)]) {
+        let ui = &self.login_ui;
+        ui.email_label
+            .set_text(&field_label("Email", FormField::Email, errors));
+        ui.password_label
+            .set_text(&field_label("Password", FormField::Password, errors));
+    }
+
+    /// Shows the `errors` next to the matching create form inputs
+    fn show_create_errors(&self, errors: &[(FormField, String)]) {
+        let ui = &self.create_ui;
+        ui.email_label
+            .set_text(&field_label("Email", FormField::Email, errors));
+        ui.username_label
+            .set_text(&field_label("Username", FormField::Username, errors));
+        ui.password_label
+            .set_text(&field_label("Password", FormField::Password, errors));
+        ui.confirm_password_label.set_text(&field_label(
+            "Confirm Password",
+            FormField::ConfirmPassword,
+            errors,
+        ));
+    }
+
+    /// Handles the "Account" button being pressed, shows the account
+    /// panel and loads the account details
+    fn handle_open_account(&self) {
+        let load = self.controller.borrow().load_account();
+        if let Ok(load) = load {
+            self.spawn_transition(load);
+        }
+
+        self.set_app_state(AppState::Account);
+    }
+
+    fn handle_close_account(&self) {
+        self.set_app_state(AppState::Running);
+    }
 
-        let request = CreateUserRequest {
-            email,
-            username,
-            password,
+    fn handle_open_logs(&self) {
+        self.set_app_state(AppState::Logs);
+    }
+
+    fn handle_close_logs(&self) {
+        self.set_app_state(AppState::Running);
+    }
+
+    /// Reloads the log viewer lines matching the level and search filters
+    fn refresh_logs(&self) {
+        let level = self
+            .logs_ui
+            .level_input
+            .selection()
+            .and_then(|index| LOG_LEVELS.get(index).copied())
+            .unwrap_or(Level::Info);
+        let lines = recent_logs(level, &self.logs_ui.search_input.text());
+
+        // Text box lines must use windows line endings
+        let text = if lines.is_empty() {
+            "No matching log lines".to_string()
+        } else {
+            logs_text(&lines).replace('\n', "\r\n")
         };
+        self.logs_ui.logs_text.set_text(&text);
+    }
 
-        let http_client = self.http_client.clone();
-        let lookup_data = lookup_data.clone();
+    /// Handles the "Copy logs" button being pressed, copies the lines
+    /// matching the current filters to the clipboard
+    fn handle_copy_logs(&self) {
+        let text = self.logs_ui.logs_text.text();
+        Clipboard::set_data_text(&self.window, &text);
+    }
+
+    fn handle_open_log_folder(&self) {
+        if let Err(err) = open_log_folder() {
+            show_error("Failed to open log folder", &err.to_string());
+        }
+    }
+
+    /// Handles the "Save details" button being pressed, sends the
+    /// username and email if they were changed
+    fn handle_save_account(&self) {
+        let update = {
+            let controller = self.controller.borrow();
+            let Some(account) = controller.account() else {
+                return;
+            };
+
+            let username = self.account_ui.username_input.text();
+            let email = self.account_ui.email_input.text();
+
+            // Only send the details that changed
+            let request = UpdateAccountRequest {
+                username: (username != account.username).then_some(username),
+                email: (email != account.email).then_some(email),
+            };
+            if request.username.is_none() && request.email.is_none() {
+                return;
+            }
+
+            controller.update_account(request)
+        };
+
+        if let Ok(update) = update {
+            self.spawn_transition(update);
+        }
+    }
+
+    /// Handles the "Change password" button being pressed
+    fn handle_change_password(&self) {
+        let current_password = self.account_ui.current_password_input.text();
+        let new_password = self.account_ui.new_password_input.text();
+
+        let change = self
+            .controller
+            .borrow()
+            .change_password(current_password, new_password);
+        if let Ok(change) = change {
+            self.spawn_transition(change);
+        }
+    }
+
+    fn handle_disconnect(&self) {
+        self.controller.borrow_mut().disconnect();
+        self.set_app_state(AppState::Connect);
+    }
+
+    /// Handles the "Logout" button being pressed, stops the servers and
+    /// returns to the login screen
+    fn handle_logout(&self) {
+        self.controller.borrow_mut().logout();
+        self.login_ui.password_input.set_text("");
+        self.set_app_state(AppState::Login);
+    }
+
+    /// Starts the background heartbeat for the running session
+    /// replacing any existing heartbeat
+    fn start_heartbeat(&self) {
+        let Some(heartbeat) = self.controller.borrow().heartbeat() else {
+            return;
+        };
+
+        let sender = self.heartbeat_notice.sender();
+        let events = self.heartbeat_events.clone();
+
+        let task = tokio::spawn(async move {
+            heartbeat
+                .run(|event| {
+                    events.lock().push(event);
+                    sender.notice();
+                })
+                .await;
+        });
+
+        if let Some(task) = self.heartbeat_task.replace(Some(task)) {
+            task.abort();
+        }
+    }
+
+    /// Starts watching the tunnel, NAT, port mapping and game status
+    /// so the running screen can be updated when they change
+    fn start_network_watch(&self) {
+        let sender = self.network_notice.sender();
+        let mut tunnel = subscribe_tunnel_status();
+        let mut nat = subscribe_nat_status();
+        let mut port_mapping = subscribe_port_mapping_status();
+        let mut game = subscribe_game_status();
+
+        let task = tokio::spawn(async move {
+            loop {
+                let result = tokio::select! {
+                    result = tunnel.changed() => result,
+                    result = nat.changed() => result,
+                    result = port_mapping.changed() => result,
+                    result = game.changed() => result,
+                };
+                if result.is_err() {
+                    return;
+                }
+                sender.notice();
+            }
+        });
+
+        if let Some(task) = self.network_task.replace(Some(task)) {
+            task.abort();
+        }
+    }
 
-        let sender = self.next_state_notice.sender();
-        let next_state = self.next_state.clone();
+    /// Starts watching for failures of the servers for as long as the
+    /// client is open. Servers can still fail while they are stopping
+    /// after leaving the running screen so the watch is never stopped
+    fn start_failure_watch(&self) {
+        let sender = self.server_failure_notice.sender();
+        let failures = self.server_failures.clone();
+        let mut failure = subscribe_server_failures();
 
         tokio::spawn(async move {
-            let base_url: Url = lookup_data.url.as_ref().clone();
+            while failure.changed().await.is_ok() {
+                if let Some(value) = failure.borrow_and_update().clone() {
+                    failures.lock().push(value);
+                    sender.notice();
+                }
+            }
+        });
+    }
 
-            let state = match create_user(http_client, base_url, request).await {
-                Ok(auth_token) => NextState::State(AppState::Running {
-                    lookup_data,
-                    auth_token,
-                }),
-                Err(err) => {
-                    show_error("Failed to create account", &err.to_string());
-                    NextState::Error
+    fn handle_network_status(&self) {
+        let tunnel = tunnel_status();
+        let nat = nat_status();
+
+        let text = format!("Tunnel: {}", tunnel);
+        self.running_ui.tunnel_label.set_text(&text);
+
+        let text = format!("NAT: {}", nat);
+        self.running_ui.nat_label.set_text(&text);
+
+        let advice = match &nat {
+            NatStatus::Classified(report) => report.recommendation(&tunnel),
+            _ => "",
+        };
+        self.running_ui.nat_advice_label.set_text(advice);
+
+        let text = format!("Port mapping: {}", port_mapping_status());
+        self.running_ui.port_mapping_label.set_text(&text);
+
+        let game = game_status();
+        let text = format!("Game: {}", game);
+        self.running_ui.game_label.set_text(&text);
+        self.running_ui.launch_button.set_enabled(!game.is_active());
+
+        self.update_tray();
+
+        // Close the client once the game exits when configured to, the hosts
+        // entry and port mappings are removed as the client shuts down
+        let previous = self.game_status.replace(game.clone());
+        if previous == GameStatus::Running
+            && game == GameStatus::Exited
+            && read_config_file().is_some_and(|config| config.launch.close_on_exit)
+        {
+            self.handle_disconnect();
+            stop_thread_dispatch();
+        }
+    }
+
+    /// Launches the game using the saved config
+    fn handle_launch_game(&self) {
+        let config = read_config_file().unwrap_or_default();
+
+        if let Err(err) = launch_game(&config) {
+            show_error("Failed to launch game", &err.to_string());
+            return;
+        }
+
+        self.running_ui.launch_button.set_enabled(false);
+        self.running_ui.game_label.set_text("Game: Launching...");
+    }
+
+    /// Shows a tray notification for each server that failed
+    fn handle_server_failures(&self) {
+        let failures = std::mem::take(&mut *self.server_failures.lock());
+
+        for failure in failures {
+            self.tray.show(
+                &failure.error,
+                Some(&format!("The {} server stopped", failure.server)),
+                Some(TrayNotificationFlags::ERROR_ICON | TrayNotificationFlags::LARGE_ICON),
+                None,
+            );
+        }
+    }
+
+    /// Updates the tray icon tooltip with the connection
+    /// and server status
+    fn update_tray(&self) {
+        let tip = match self.controller.borrow().state() {
+            ConnectionState::Disconnected => "Not connected".to_string(),
+            ConnectionState::Connecting => "Connecting...".to_string(),
+            ConnectionState::Connected(lookup_data)
+            | ConnectionState::Authenticating(lookup_data) => {
+                format!("Not logged in to {}", lookup_data.url.authority())
+            }
+            ConnectionState::Running {
+                lookup_data,
+                status,
+                ..
+            } => format!(
+                "Connected to {}\nStatus: {}\nTunnel: {}",
+                lookup_data.url.authority(),
+                status,
+                tunnel_status()
+            ),
+        };
+
+        let tip = format!("Pocket Ark Client\n{}", tip);
+        let tip: String = tip.chars().take(TRAY_TIP_LENGTH).collect();
+        self.tray.set_tip(&tip);
+    }
+
+    /// Hides the window when minimised leaving the tray icon
+    fn handle_minimize(&self) {
+        self.window.set_visible(false);
+
+        // Failures raise a tray notification while the window is hidden
+        set_failure_dialogs(false);
+
+        if !self.tray_hint_shown.replace(true) {
+            self.tray.show(
+                "The client is still running, use the tray icon to open it again",
+                Some("Pocket Ark Client"),
+                Some(TrayNotificationFlags::USER_ICON | TrayNotificationFlags::LARGE_ICON),
+                Some(&self.icon),
+            );
+        }
+    }
+
+    fn handle_tray_menu(&self) {
+        let running = self.is_running();
+        self.tray_reconnect_item.set_enabled(running);
+        self.tray_disconnect_item.set_enabled(running);
+
+        let (x, y) = GlobalCursor::position();
+        self.tray_menu.popup(x, y);
+    }
+
+    fn handle_tray_show(&self) {
+        set_failure_dialogs(true);
+        self.window.set_visible(true);
+        self.window.restore();
+        self.window.set_focus();
+    }
+
+    /// Opens the log viewer while running, otherwise the log
+    /// folder as the viewer is only available while running
+    fn handle_tray_logs(&self) {
+        if self.is_running() {
+            self.handle_tray_show();
+            self.handle_open_logs();
+        } else {
+            self.handle_open_log_folder();
+        }
+    }
+
+    fn handle_tray_reconnect(&self) {
+        let result = self.controller.borrow_mut().restart_servers();
+        match result {
+            Ok(()) => self.tray.show(
+                "The local servers were restarted",
+                Some("Reconnected"),
+                Some(TrayNotificationFlags::USER_ICON | TrayNotificationFlags::LARGE_ICON),
+                Some(&self.icon),
+            ),
+            Err(err) => show_error("Failed to reconnect", &err.to_string()),
+        }
+    }
+
+    fn handle_tray_disconnect(&self) {
+        if self.is_running() {
+            self.handle_disconnect();
+        }
+    }
+
+    /// Quits the client, the servers and port mappings are cleaned
+    /// up once the dispatch loop has stopped
+    fn handle_tray_quit(&self) {
+        stop_thread_dispatch();
+    }
+
+    /// Whether the servers are running for an authenticated session
+    fn is_running(&self) -> bool {
+        matches!(
+            self.controller.borrow().state(),
+            ConnectionState::Running { .. }
+        )
+    }
+
+    /// Handles events from the background heartbeat
+    fn handle_heartbeat_events(&self) {
+        let events = std::mem::take(&mut *self.heartbeat_events.lock());
+
+        for event in events {
+            if let HeartbeatEvent::Status(status) = &event {
+                let text = format!("Status: {}", status);
+                self.running_ui.status_label.set_text(&text);
+            }
+
+            let expired = self.controller.borrow_mut().handle_heartbeat(event);
+            self.update_tray();
+
+            // Prompt the user to login again
+            if let Some(email) = expired {
+                show_warning(
+                    "Session expired",
+                    "Your session has expired and couldn't be renewed, please login again. \
+                    The local servers have been stopped until you login",
+                );
+
+                self.login_ui.email_input.set_text(&email);
+                self.login_ui.password_input.set_text("");
+
+                self.set_app_state(AppState::Login);
+            }
+        }
+    }
+
+    /// Handles events from the background update tasks
+    fn handle_update_events(&self) {
+        let events = std::mem::take(&mut *self.update_events.lock());
+
+        for event in events {
+            match event {
+                UpdateEvent::Available(info) => {
+                    let text = format!(
+                        "New version is available. Your version: v{}  Latest version: v{}",
+                        info.current_version, info.version
+                    );
+                    self.update_ui.version_label.set_text(&text);
+
+                    // Text box requires windows line endings
+                    let release_notes = if info.release_notes.is_empty() {
+                        "No release notes were provided for this version".to_string()
+                    } else {
+                        info.release_notes
+                            .replace("\r\n", "\n")
+                            .replace('\n', "\r\n")
+                    };
+                    self.update_ui.notes_text.set_text(&release_notes);
+
+                    self.update_ui.progress_bar.set_pos(0);
+                    self.update_ui.progress_label.set_text("");
+                    self.update_ui.update_button.set_enabled(true);
+                    self.update_ui.skip_button.set_enabled(true);
+                    self.update_ui.dismiss_button.set_text("Not now");
+
+                    *self.update_state.borrow_mut() = UpdateState::Available(Arc::new(info));
+                    self.update_visible_frame();
                 }
-            };
+                UpdateEvent::Progress(progress) => {
+                    let percent = progress.percent().unwrap_or_default();
+                    self.update_ui.progress_bar.set_pos(percent as u32);
+                    self.update_ui
+                        .progress_label
+                        .set_text(&format!("Downloading... {}", progress));
+                }
+                UpdateEvent::Finished(result) => {
+                    *self.update_state.borrow_mut() = UpdateState::None;
+
+                    match result {
+                        // The updated client has started and taken over, stopping
+                        // the dispatch returns from init so the client shuts down
+                        // normally
+                        Ok(()) => stop_thread_dispatch(),
+                        Err(err) => show_error("Failed to update", &err),
+                    }
+
+                    self.update_visible_frame();
+                }
+            }
+        }
+    }
+
+    /// Handles the "Update" button being pressed, starts downloading
+    /// the update in the background
+    fn handle_start_update(&self) {
+        let info = match &*self.update_state.borrow() {
+            UpdateState::Available(info) => info.clone(),
+            _ => return,
+        };
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        *self.update_state.borrow_mut() = UpdateState::Downloading {
+            cancel: cancel.clone(),
+        };
+
+        self.update_ui.update_button.set_enabled(false);
+        self.update_ui.skip_button.set_enabled(false);
+        self.update_ui.dismiss_button.set_text("Cancel");
+        self.update_ui.progress_label.set_text("Downloading...");
 
-            let next_state = &mut *next_state.lock();
-            *next_state = Some(state);
+        let Some(updater) = self.updater.clone() else {
+            return;
+        };
+        let sender = self.update_notice.sender();
+        let events = self.update_events.clone();
+
+        tokio::spawn(async move {
+            let result = updater
+                .install(
+                    &info,
+                    |progress| {
+                        events.lock().push(UpdateEvent::Progress(progress));
+                        sender.notice();
+                    },
+                    &cancel,
+                )
+                .await
+                .map_err(|err| err.to_string());
+
+            events.lock().push(UpdateEvent::Finished(result));
             sender.notice();
         });
     }
 
-    fn handle_disconnect(&self) {
-        self.set_app_state(AppState::Connect);
+    /// Handles the "Skip this version" button being pressed
+    fn handle_skip_update(&self) {
+        if let UpdateState::Available(info) = &*self.update_state.borrow() {
+            update::skip_version(&info.version);
+        }
+
+        *self.update_state.borrow_mut() = UpdateState::None;
+        self.update_visible_frame();
+    }
+
+    /// Handles the "Not now" / "Cancel" button being pressed
+    fn handle_dismiss_update(&self) {
+        if let UpdateState::Downloading { cancel } = &*self.update_state.borrow() {
+            // Download task will report once its cancelled
+            cancel.store(true, Ordering::Release);
+            return;
+        }
+
+        *self.update_state.borrow_mut() = UpdateState::None;
+        self.update_visible_frame();
     }
 }
 
 /// Initializes the user interface
 ///
 /// ## Arguments
-/// * `config` - The client config to use
-/// * `client` - The HTTP client to use
-pub fn init(config: Option<ClientConfig>, client: Client) {
+/// * `config`  - The client config to use
+/// * `client`  - The HTTP client to use
+/// * `updater` - The updater to use, [None] if updating is unavailable
+/// * `updated` - Whether the client was launched after being updated
+pub fn init(config: ClientConfig, client: Client, updater: Option<Arc<Updater>>, updated: bool) {
     // Create tokio async runtime
     let runtime = tokio::runtime::Builder::new_multi_thread()
         .enable_all()
@@ -631,35 +1808,64 @@ pub fn init(config: Option<ClientConfig>, client: Client) {
     // Enter the tokio runtime
     let _enter = runtime.enter();
 
-    // Spawn the updating task
-    tokio::spawn(update::update(client.clone()));
-
     // Initialize nwg
     nwg_init().expect("Failed to initialize native UI");
 
     // Set the default font family
     Font::set_global_family("Segoe UI").expect("Failed to set default font");
 
+    let (target, remember) = config
+        .connection_url
+        .map(|value| (value, true))
+        .unwrap_or_default();
+
     // Build the app UI
     let app = App::build_ui(App {
+        controller: RefCell::new(Controller::from_client(client.clone(), remember)),
         http_client: client,
+        updater: updater.clone(),
         ..Default::default()
     })
     .expect("Failed to build native UI");
 
-    let (target, remember) = config
-        .map(|value| (value.connection_url, true))
-        .unwrap_or_default();
-
     app.connect_ui.target_url_input.set_text(&target);
+    app.handle_target_changed();
 
-    if remember {
+    if !DISCOVERY_ENABLED {
+        app.connect_ui.hide_discovery();
+    }
+
+    if app.controller.borrow().remember() {
         app.connect_ui
             .remember_checkbox
             .set_check_state(CheckBoxState::Checked);
     }
 
     app.set_app_state(AppState::Connect);
+    app.start_failure_watch();
+
+    // The window has been created, let the previous version know it can exit
+    if updated {
+        update::report_startup();
+    }
+
+    // Check for updates in the background
+    if let Some(updater) = updater {
+        let sender = app.update_notice.sender();
+        let events = app.update_events.clone();
+        let (channel, skipped_version) = (config.update_channel, config.skipped_version);
+
+        tokio::spawn(async move {
+            match updater.check(channel, skipped_version).await {
+                Ok(Some(info)) => {
+                    events.lock().push(UpdateEvent::Available(info));
+                    sender.notice();
+                }
+                Ok(None) => {}
+                Err(err) => error!("Failed to check for updates: {}", err),
+            }
+        });
+    }
 
     dispatch_thread_events();
 }
@@ -0,0 +1,253 @@
+//! System tray icon for the iced variant, the window can be hidden to the
+//! tray while the client keeps running in the background. The icon shows the
+//! connection status and provides quick actions through its menu
+//!
+//! The tray icon must be created on a thread running an event loop. On
+//! Linux a separate thread runs a GTK event loop for the icon, on other
+//! platforms the icon is created on the thread running the window
+
+use super::{ICON_BYTES, WINDOW_TITLE};
+use log::error;
+use thiserror::Error;
+use tray_icon::{
+    menu::{Menu, MenuEvent, MenuId, MenuItem, PredefinedMenuItem},
+    BadIcon, Icon, MouseButton, MouseButtonState, TrayIcon, TrayIconBuilder, TrayIconEvent,
+};
+
+#[cfg(target_os = "linux")]
+use std::sync::{Arc, Mutex};
+
+/// Menu item ID for showing the window
+const SHOW_ID: &str = "show";
+/// Menu item ID for opening the logs
+const LOGS_ID: &str = "logs";
+/// Menu item ID for restarting the local servers
+const RECONNECT_ID: &str = "reconnect";
+/// Menu item ID for disconnecting
+const DISCONNECT_ID: &str = "disconnect";
+/// Menu item ID for quitting the client
+const QUIT_ID: &str = "quit";
+
+/// Actions that can be chosen from the tray icon
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrayAction {
+    /// Show the hidden window
+    Show,
+    /// Open the log viewer or the log folder when not running
+    OpenLogs,
+    /// Restart the local servers
+    Reconnect,
+    /// Disconnect from the server
+    Disconnect,
+    /// Quit the client
+    Quit,
+}
+
+impl TrayAction {
+    /// Finds the action for the menu item with the provided `id`
+    fn from_menu_id(id: &MenuId) -> Option<Self> {
+        Some(match id.0.as_str() {
+            SHOW_ID => TrayAction::Show,
+            LOGS_ID => TrayAction::OpenLogs,
+            RECONNECT_ID => TrayAction::Reconnect,
+            DISCONNECT_ID => TrayAction::Disconnect,
+            QUIT_ID => TrayAction::Quit,
+            _ => return None,
+        })
+    }
+}
+
+/// Errors that can occur while creating the tray icon
+#[derive(Debug, Error)]
+enum TrayError {
+    /// The icon couldn't be decoded
+    #[error("Failed to decode icon: {0}")]
+    DecodeIcon(#[from] image::ImageError),
+    /// The decoded icon wasn't valid
+    #[error(transparent)]
+    InvalidIcon(#[from] BadIcon),
+    /// The menu couldn't be created
+    #[error("Failed to create menu: {0}")]
+    Menu(#[from] tray_icon::menu::Error),
+    /// The tray icon couldn't be created
+    #[error("Failed to create tray icon: {0}")]
+    Create(#[from] tray_icon::Error),
+}
+
+/// The tray icon along with the menu item showing the status, these
+/// must stay on the thread that created them
+struct TrayMenu {
+    /// The tray icon, removed once dropped
+    #[cfg_attr(target_os = "linux", allow(dead_code))]
+    icon: TrayIcon,
+    /// Disabled menu item showing the current status
+    status_item: MenuItem,
+}
+
+impl TrayMenu {
+    /// Creates the tray icon and its menu on the current thread
+    fn create() -> Result<Self, TrayError> {
+        let image = image::load_from_memory(ICON_BYTES)?.into_rgba8();
+        let (width, height) = image.dimensions();
+        let icon = Icon::from_rgba(image.into_raw(), width, height)?;
+
+        let status_item = MenuItem::new("Not connected", false, None);
+        let menu = Menu::with_items(&[
+            &status_item,
+            &PredefinedMenuItem::separator(),
+            &MenuItem::with_id(SHOW_ID, "Show", true, None),
+            &MenuItem::with_id(LOGS_ID, "Open logs", true, None),
+            &PredefinedMenuItem::separator(),
+            &MenuItem::with_id(RECONNECT_ID, "Reconnect", true, None),
+            &MenuItem::with_id(DISCONNECT_ID, "Disconnect", true, None),
+            &PredefinedMenuItem::separator(),
+            &MenuItem::with_id(QUIT_ID, "Quit", true, None),
+        ])?;
+
+        let icon = TrayIconBuilder::new()
+            .with_icon(icon)
+            .with_tooltip(WINDOW_TITLE)
+            .with_menu(Box::new(menu))
+            .build()?;
+
+        Ok(Self { icon, status_item })
+    }
+
+    /// Shows the `status` in the menu and the tooltip of the icon
+    fn set_status(&self, status: &str) {
+        self.status_item.set_text(status);
+
+        // Tooltips aren't supported on Linux
+        #[cfg(not(target_os = "linux"))]
+        if let Err(err) = self
+            .icon
+            .set_tooltip(Some(format!("{}\n{}", WINDOW_TITLE, status)))
+        {
+            error!("Failed to set tray tooltip: {}", err);
+        }
+    }
+}
+
+/// Handle to the tray icon for updating its status
+pub struct Tray {
+    /// The tray icon created on this thread
+    #[cfg(not(target_os = "linux"))]
+    menu: TrayMenu,
+    /// Status waiting to be shown by the GTK thread
+    #[cfg(target_os = "linux")]
+    pending_status: Arc<Mutex<Option<String>>>,
+    /// The last status shown
+    status: String,
+}
+
+impl Tray {
+    /// Creates the tray icon, failures are logged and [None] is returned
+    /// so the client can still run as a window
+    #[cfg(not(target_os = "linux"))]
+    pub fn create() -> Option<Self> {
+        match TrayMenu::create() {
+            Ok(menu) => Some(Self {
+                menu,
+                status: String::new(),
+            }),
+            Err(err) => {
+                error!("{}", err);
+                None
+            }
+        }
+    }
+
+    /// Creates the tray icon on a thread running a GTK event loop, pending
+    /// status changes are checked from the event loop
+    #[cfg(target_os = "linux")]
+    pub fn create() -> Option<Self> {
+        use gtk::glib::{timeout_add_local, ControlFlow};
+        use std::{sync::mpsc, time::Duration};
+
+        /// Time between checks for status changes
+        const STATUS_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+        let pending_status: Arc<Mutex<Option<String>>> = Arc::default();
+        let (created_tx, created_rx) = mpsc::channel();
+
+        let pending = pending_status.clone();
+        std::thread::spawn(move || {
+            if let Err(err) = gtk::init() {
+                error!("Failed to initialize GTK for tray icon: {}", err);
+                let _ = created_tx.send(false);
+                return;
+            }
+
+            let menu = match TrayMenu::create() {
+                Ok(value) => value,
+                Err(err) => {
+                    error!("{}", err);
+                    let _ = created_tx.send(false);
+                    return;
+                }
+            };
+            let _ = created_tx.send(true);
+
+            timeout_add_local(STATUS_POLL_INTERVAL, move || {
+                if let Some(status) = pending.lock().ok().and_then(|mut value| value.take()) {
+                    menu.set_status(&status);
+                }
+                ControlFlow::Continue
+            });
+
+            gtk::main();
+        });
+
+        // Wait for the icon so failures fall back to the window only
+        if !created_rx.recv().unwrap_or(false) {
+            return None;
+        }
+
+        Some(Self {
+            pending_status,
+            status: String::new(),
+        })
+    }
+
+    /// Shows the provided `status` on the tray icon
+    pub fn set_status(&mut self, status: String) {
+        if self.status == status {
+            return;
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        self.menu.set_status(&status);
+
+        #[cfg(target_os = "linux")]
+        if let Ok(pending) = &mut self.pending_status.lock() {
+            **pending = Some(status.clone());
+        }
+
+        self.status = status;
+    }
+}
+
+/// Sets the `on_action` callback for actions chosen from the tray icon,
+/// clicking the icon shows the window
+pub fn set_action_handler<F>(on_action: F)
+where
+    F: Fn(TrayAction) + Send + Sync + Clone + 'static,
+{
+    let on_menu_action = on_action.clone();
+    MenuEvent::set_event_handler(Some(move |event: MenuEvent| {
+        if let Some(action) = TrayAction::from_menu_id(&event.id) {
+            on_menu_action(action);
+        }
+    }));
+
+    TrayIconEvent::set_event_handler(Some(move |event: TrayIconEvent| {
+        if let TrayIconEvent::Click {
+            button: MouseButton::Left,
+            button_state: MouseButtonState::Up,
+            ..
+        } = event
+        {
+            on_action(TrayAction::Show);
+        }
+    }));
+}
@@ -0,0 +1,728 @@
+//! Updater module for providing auto-updating functionality
+
+use self::source::{
+    download_bytes, GitHubRelease, GitHubReleaseAsset, GitHubReleaseSource, LocalReleaseSource,
+    ReleaseSource, GITHUB_API_URL,
+};
+use crate::{
+    args::Args,
+    config::{update_config_file, UpdateChannel},
+    core::{reqwest, Version},
+    hex::to_hex,
+    APP_VERSION,
+};
+use log::{debug, error};
+use openssl::{error::ErrorStack, pkey::PKey, sha::Sha256, sign::Verifier};
+use std::{
+    env::current_exe,
+    io,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+use tokio::{fs::File, io::AsyncWriteExt, process::Command, time::sleep};
+
+pub mod source;
+#[cfg(test)]
+mod tests;
+
+/// The GitHub repository to use for releases
+pub const GITHUB_REPOSITORY: &str = "PocketRelay/PocketArkClient";
+// Windows non native asset name
+#[cfg(all(target_family = "windows", not(feature = "native")))]
+pub const ASSET_NAME: &str = "pocket-ark-client.exe";
+// Windows native asset name
+#[cfg(all(target_family = "windows", feature = "native"))]
+pub const ASSET_NAME: &str = "pocket-ark-client-native.exe";
+// Linux asset name
+#[cfg(target_family = "unix")]
+pub const ASSET_NAME: &str = "pocket-ark-client-linux";
+
+/// Name of the release asset containing the SHA-256 checksums of the other
+/// release assets (In the `sha256sum` output format)
+pub const CHECKSUMS_ASSET_NAME: &str = "sha256sums.txt";
+/// Name of the release asset containing the detached Ed25519 signature
+/// of the checksums asset
+pub const SIGNATURE_ASSET_NAME: &str = "sha256sums.txt.sig";
+/// Public key used to verify the signature of release checksums
+pub const UPDATE_PUBLIC_KEY: &[u8] = include_bytes!("../resources/update-public-key.pem");
+/// Argument passed to the new version when its launched after updating
+pub const UPDATED_ARG: &str = "--updated";
+/// Time the new version has to report that it started successfully
+/// before the update is rolled back
+pub const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Errors that can occur while downloading and verifying an update
+#[derive(Debug, Error)]
+pub enum UpdateError {
+    /// Failed to download the update
+    #[error("Failed to download update: {0}")]
+    Download(#[from] reqwest::Error),
+    /// Failed to read or write the update files
+    #[error("Failed to access update files: {0}")]
+    Io(#[from] io::Error),
+    /// The release information was invalid
+    #[error("Failed to read release information: {0}")]
+    InvalidRelease(String),
+    /// The release version couldn't be parsed
+    #[error("Failed to parse version of latest release: {0}")]
+    InvalidVersion(String),
+    /// The release didn't include a binary for this platform
+    #[error("The release is missing the {0} binary, cannot update")]
+    MissingAsset(&'static str),
+    /// The download was cancelled by the user
+    #[error("The update download was cancelled")]
+    Cancelled,
+    /// Failed to swap the downloaded update in place of the current executable
+    #[error("Failed to replace the client executable with the update: {0}")]
+    Swap(io::Error),
+    /// The release didn't include the checksums or signature
+    #[error("The release is missing the {0} file required to verify the update")]
+    MissingVerificationAsset(&'static str),
+    /// The checksums file signature didn't match the embedded public key
+    #[error(
+        "The signature of the update checksums is invalid. \
+        The release may have been tampered with so it will not be installed"
+    )]
+    InvalidSignature,
+    /// The checksums file didn't list the downloaded asset
+    #[error("The update checksums don't include a checksum for {0}")]
+    MissingChecksum(String),
+    /// The checksum of the downloaded asset didn't match
+    #[error(
+        "The downloaded update doesn't match its published checksum. \
+        The download may be corrupted or tampered with so it will not be installed"
+    )]
+    ChecksumMismatch,
+    /// Error from OpenSSL while hashing or verifying
+    #[error("Failed to verify update: {0}")]
+    OpenSsl(#[from] ErrorStack),
+    /// The new version failed the startup health check and was rolled back
+    #[error(
+        "The updated client failed to start ({0}). \
+        The previous version has been restored"
+    )]
+    StartupFailed(String),
+    /// The new version failed to start and the previous version couldn't be restored
+    #[error(
+        "The updated client failed to start ({0}) and the previous version could not be restored: {1}"
+    )]
+    RollbackFailed(String, io::Error),
+    /// The path of the running executable couldn't be determined
+    #[error("Failed to locate the client executable: {0}")]
+    LocateExecutable(io::Error),
+    /// The executable path has no parent directory or file name
+    #[error("Cannot update the client executable at {0}")]
+    InvalidExecutablePath(PathBuf),
+}
+
+/// Details about an available update
+#[derive(Debug, Clone)]
+pub struct UpdateInfo {
+    /// The currently running version
+    pub current_version: Version,
+    /// The version available to update to
+    pub version: Version,
+    /// Release notes for the new version
+    pub release_notes: String,
+    /// The asset to download for this platform
+    pub asset: GitHubReleaseAsset,
+    /// All the assets of the release, used to find the verification files
+    pub assets: Vec<GitHubReleaseAsset>,
+}
+
+/// Progress of an update download
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DownloadProgress {
+    /// Number of bytes downloaded so far
+    pub downloaded: u64,
+    /// Total number of bytes if known
+    pub total: Option<u64>,
+}
+
+impl DownloadProgress {
+    /// Percentage of the download that has completed, [None] if
+    /// the total size is unknown
+    pub fn percent(&self) -> Option<f32> {
+        self.total
+            .filter(|total| *total > 0)
+            .map(|total| (self.downloaded as f32 / total as f32 * 100.0).min(100.0))
+    }
+}
+
+impl std::fmt::Display for DownloadProgress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        const MB: f32 = 1024.0 * 1024.0;
+
+        let downloaded = self.downloaded as f32 / MB;
+        match (self.total, self.percent()) {
+            (Some(total), Some(percent)) => write!(
+                f,
+                "{:.1} MB / {:.1} MB ({:.0}%)",
+                downloaded,
+                total as f32 / MB,
+                percent
+            ),
+            _ => write!(f, "{:.1} MB", downloaded),
+        }
+    }
+}
+
+/// Paths used by the updater
+pub struct UpdatePaths {
+    /// Path to the file executable file
+    pub exe: PathBuf,
+    /// Temporary path for storing the file while download
+    pub tmp_download: PathBuf,
+    /// Temporary path for moving the old plugin before swapping
+    pub tmp_old: PathBuf,
+    /// Marker file created by the new version once it has started
+    pub tmp_ready: PathBuf,
+}
+
+impl UpdatePaths {
+    /// Creates the paths for updating the currently running executable
+    pub fn current() -> Result<Self, UpdateError> {
+        let path = current_exe().map_err(UpdateError::LocateExecutable)?;
+        Self::new(path)
+    }
+
+    /// Creates the paths for updating the executable at `path`, the
+    /// temporary paths are placed next to the executable
+    pub fn new(path: PathBuf) -> Result<Self, UpdateError> {
+        // Find the parent directory of the executable and the name
+        // the temporary files are named after
+        let (Some(parent), Some(file_name)) = (path.parent(), path.file_name()) else {
+            return Err(UpdateError::InvalidExecutablePath(path));
+        };
+        let file_name = file_name.to_string_lossy().to_string();
+
+        Ok(Self {
+            tmp_download: parent.join(format!("{}.tmp-download", file_name)),
+            tmp_old: parent.join(format!("{}.tmp-old", file_name)),
+            tmp_ready: parent.join(format!("{}.tmp-ready", file_name)),
+            exe: path,
+        })
+    }
+
+    /// Removes the temporary download path if it exists. The `tmp_old`
+    /// path is left for the new version to remove once its started
+    pub async fn remove_tmp_download(&self) -> std::io::Result<()> {
+        if self.tmp_download.exists() {
+            tokio::fs::remove_file(&self.tmp_download).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes the previous version from `tmp_old` and the startup marker,
+    /// retries while the previous version is still running and holding
+    /// onto the file
+    pub fn remove_tmp_old(&self) -> std::io::Result<()> {
+        const ATTEMPTS: u32 = 20;
+
+        let mut attempt = 1;
+        while self.tmp_old.exists() {
+            match std::fs::remove_file(&self.tmp_old) {
+                Ok(()) => break,
+                Err(err) if attempt < ATTEMPTS => {
+                    debug!("Previous version is still in use ({}), retrying", err);
+                    attempt += 1;
+                    std::thread::sleep(Duration::from_millis(500));
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        if self.tmp_ready.exists() {
+            std::fs::remove_file(&self.tmp_ready)?;
+        }
+
+        Ok(())
+    }
+
+    /// Restores the previous version from `tmp_old` replacing the
+    /// new version at the `exe` path
+    pub fn rollback(&self) -> std::io::Result<()> {
+        debug!("Rolling back to previous version");
+
+        if self.exe.exists() {
+            std::fs::remove_file(&self.exe)?;
+        }
+
+        std::fs::rename(&self.tmp_old, &self.exe)
+    }
+
+    /// Moves the `plugin` file to `tmp_old` and moves the downloaded
+    /// file from `tmp_download` to `plugin`. The original file is moved
+    /// back if the downloaded file couldn't be moved into place
+    pub async fn swap_plugin_files(&self) -> std::io::Result<()> {
+        debug!("Swapping plugin files with update");
+
+        // Copy the permissions of the current exe so the executable bit
+        // is kept on unix
+        let permissions = tokio::fs::metadata(&self.exe).await?.permissions();
+        tokio::fs::set_permissions(&self.tmp_download, permissions).await?;
+
+        // Move the exe to the `tmp_old` path
+        tokio::fs::rename(&self.exe, &self.tmp_old).await?;
+
+        // Move the downloaded plugin to the `exe` path
+        if let Err(err) = tokio::fs::rename(&self.tmp_download, &self.exe).await {
+            // Restore the original exe
+            if let Err(err) = tokio::fs::rename(&self.tmp_old, &self.exe).await {
+                error!("Failed to restore original executable: {}", err);
+            }
+
+            return Err(err);
+        }
+
+        Ok(())
+    }
+}
+
+/// Verifies the signature of the release `checksums` and finds the expected
+/// SHA-256 checksum for the `asset_name` returning it as a lowercase hex string
+///
+/// ## Arguments
+/// * `public_key` - PEM encoded public key the checksums were signed with
+/// * `checksums`  - The contents of the checksums asset
+/// * `signature`  - The detached signature of the checksums asset
+/// * `asset_name` - The name of the asset to find the checksum for
+pub fn verify_checksums(
+    public_key: &[u8],
+    checksums: &[u8],
+    signature: &[u8],
+    asset_name: &str,
+) -> Result<String, UpdateError> {
+    // Verify the checksums were signed by the expected key
+    let public_key = PKey::public_key_from_pem(public_key)?;
+    let mut verifier = Verifier::new_without_digest(&public_key)?;
+    if !verifier.verify_oneshot(signature, checksums)? {
+        return Err(UpdateError::InvalidSignature);
+    }
+
+    // Find the expected checksum for the asset
+    let checksums = String::from_utf8_lossy(checksums);
+    checksums
+        .lines()
+        .filter_map(|line| line.split_once(char::is_whitespace))
+        // Binary mode entries are prefixed with an asterisk
+        .find(|(_, name)| name.trim().trim_start_matches('*') == asset_name)
+        .map(|(checksum, _)| checksum.trim().to_lowercase())
+        .ok_or_else(|| UpdateError::MissingChecksum(asset_name.to_string()))
+}
+
+/// Selects the update from the `release` if its newer than the
+/// `current_version` and hasn't been skipped
+///
+/// ## Arguments
+/// * `release`         - The latest release
+/// * `current_version` - The currently running version
+/// * `skipped_version` - Version the user chose not to be asked about
+/// * `asset_name`      - Name of the asset for this platform
+pub fn select_update(
+    release: GitHubRelease,
+    current_version: &Version,
+    skipped_version: Option<&str>,
+    asset_name: &'static str,
+) -> Result<Option<UpdateInfo>, UpdateError> {
+    let latest_version = release
+        .tag_name
+        .trim_start_matches('v')
+        .parse::<Version>()
+        .map_err(|err| UpdateError::InvalidVersion(err.to_string()))?;
+
+    // Don't update if we are already on the latest or an unreleased version
+    if *current_version >= latest_version {
+        if *current_version > latest_version {
+            debug!("Future release is installed ({})", current_version);
+        } else {
+            debug!("Latest version is installed ({})", current_version);
+        }
+
+        return Ok(None);
+    }
+
+    // Don't ask about versions the user has chosen to skip
+    if skipped_version.is_some_and(|version| version == latest_version.to_string()) {
+        debug!("Skipping new version ({})", latest_version);
+        return Ok(None);
+    }
+
+    debug!("New version is available ({})", latest_version);
+
+    let asset = release
+        .assets
+        .iter()
+        .find(|asset| asset.name == asset_name)
+        .cloned()
+        .ok_or(UpdateError::MissingAsset(asset_name))?;
+
+    Ok(Some(UpdateInfo {
+        current_version: current_version.clone(),
+        version: latest_version,
+        release_notes: release.body.unwrap_or_default(),
+        asset,
+        assets: release.assets,
+    }))
+}
+
+/// Stores the provided `version` as skipped so the user isn't
+/// asked about updating to it again
+pub fn skip_version(version: &Version) {
+    let version = version.to_string();
+    update_config_file(|config| config.skipped_version = Some(version));
+}
+
+/// Creates the release source to use based on the launch `args`, a local
+/// directory can be used in place of GitHub using `--update-source`
+///
+/// ## Arguments
+/// * `args`        - The launch arguments
+/// * `http_client` - The HTTP client to use for GitHub requests
+pub fn create_release_source(args: &Args, http_client: reqwest::Client) -> Arc<dyn ReleaseSource> {
+    match &args.update_source {
+        Some(path) => {
+            debug!("Using local update source: {}", path.display());
+            Arc::new(LocalReleaseSource::new(path.clone()))
+        }
+        None => Arc::new(GitHubReleaseSource::new(
+            http_client,
+            GITHUB_API_URL,
+            GITHUB_REPOSITORY,
+        )),
+    }
+}
+
+/// Updater for checking, downloading and installing updates from
+/// a [`ReleaseSource`]
+pub struct Updater {
+    /// Source to obtain releases from
+    pub source: Arc<dyn ReleaseSource>,
+    /// Paths of the executable being updated
+    pub paths: UpdatePaths,
+    /// The currently running version
+    pub current_version: Version,
+    /// Name of the release asset for this platform
+    pub asset_name: &'static str,
+    /// PEM encoded public key release checksums must be signed with
+    pub public_key: &'static [u8],
+}
+
+impl Updater {
+    /// Creates an updater for the current executable using the
+    /// provided release `source`
+    pub fn new(source: Arc<dyn ReleaseSource>) -> Result<Self, UpdateError> {
+        let current_version = Version::parse(APP_VERSION)
+            .map_err(|err| UpdateError::InvalidVersion(err.to_string()))?;
+
+        Ok(Self {
+            source,
+            paths: UpdatePaths::current()?,
+            current_version,
+            asset_name: ASSET_NAME,
+            public_key: UPDATE_PUBLIC_KEY,
+        })
+    }
+
+    /// Checks for a new version of the client on the provided update `channel`
+    /// returning the details of the update if one is available
+    ///
+    /// ## Arguments
+    /// * `channel`         - The release channel to check for updates on
+    /// * `skipped_version` - Version the user chose not to be asked about
+    pub async fn check(
+        &self,
+        channel: UpdateChannel,
+        skipped_version: Option<String>,
+    ) -> Result<Option<UpdateInfo>, UpdateError> {
+        if let UpdateChannel::Disabled = channel {
+            debug!("Update checking is disabled");
+            return Ok(None);
+        }
+
+        // Remove temporary files if they exist
+        if let Err(err) = self.paths.remove_tmp_download().await {
+            error!("Failed to remove temporary files: {}", err);
+        }
+
+        debug!("Checking for updates ({:?} channel)", channel);
+
+        let Some(latest_release) = self.source.latest_release(channel).await? else {
+            debug!("Source has no releases to update to");
+            return Ok(None);
+        };
+
+        select_update(
+            latest_release,
+            &self.current_version,
+            skipped_version.as_deref(),
+            self.asset_name,
+        )
+    }
+
+    /// Downloads the update described by `info` streaming it to the temporary
+    /// download path, verifies it and swaps it in place of the current executable.
+    ///
+    /// The download is stopped and the partial download is removed if `cancel`
+    /// is set while downloading
+    ///
+    /// ## Arguments
+    /// * `info`        - The update to download
+    /// * `on_progress` - Callback invoked with the progress of the download
+    /// * `cancel`      - Flag for cancelling the download
+    pub async fn download<F>(
+        &self,
+        info: &UpdateInfo,
+        mut on_progress: F,
+        cancel: &AtomicBool,
+    ) -> Result<(), UpdateError>
+    where
+        F: FnMut(DownloadProgress),
+    {
+        debug!("Downloading release");
+
+        let checksum = match self.download_asset(info, &mut on_progress, cancel).await {
+            Ok(value) => value,
+            Err(err) => {
+                // Delete partially downloaded file if present
+                if let Err(err) = self.paths.remove_tmp_download().await {
+                    error!("Failed to remove temporary files: {}", err);
+                }
+
+                return Err(err);
+            }
+        };
+
+        // Verify the download before its swapped in
+        if let Err(err) = self.verify_download(info, &checksum).await {
+            error!("Failed to verify update: {}", err);
+
+            if let Err(err) = self.paths.remove_tmp_download().await {
+                error!("Failed to remove temporary files: {}", err);
+            }
+
+            return Err(err);
+        }
+
+        // Swap the plugin files with the new version
+        if let Err(err) = self.paths.swap_plugin_files().await {
+            error!("Failed to swap plugin files: {}", err);
+
+            if let Err(err) = self.paths.remove_tmp_download().await {
+                error!("Failed to remove temporary files: {}", err);
+            }
+
+            return Err(UpdateError::Swap(err));
+        }
+
+        Ok(())
+    }
+
+    /// Downloads, verifies and installs the update then launches the new
+    /// version. On success the caller should exit so the new version can
+    /// take over
+    ///
+    /// ## Arguments
+    /// * `info`        - The update to install
+    /// * `on_progress` - Callback invoked with the progress of the download
+    /// * `cancel`      - Flag for cancelling the download
+    pub async fn install<F>(
+        &self,
+        info: &UpdateInfo,
+        on_progress: F,
+        cancel: &AtomicBool,
+    ) -> Result<(), UpdateError>
+    where
+        F: FnMut(DownloadProgress),
+    {
+        self.download(info, on_progress, cancel).await?;
+        self.restart().await
+    }
+
+    /// Verifies the `checksum` of the downloaded asset against the
+    /// signed checksums published with the release
+    ///
+    /// ## Arguments
+    /// * `info`     - The update that was downloaded
+    /// * `checksum` - The checksum of the downloaded file
+    async fn verify_download(&self, info: &UpdateInfo, checksum: &str) -> Result<(), UpdateError> {
+        let find_asset = |name: &'static str| {
+            info.assets
+                .iter()
+                .find(|asset| asset.name == name)
+                .ok_or(UpdateError::MissingVerificationAsset(name))
+        };
+
+        let checksums_asset = find_asset(CHECKSUMS_ASSET_NAME)?;
+        let signature_asset = find_asset(SIGNATURE_ASSET_NAME)?;
+
+        let checksums = download_bytes(self.source.as_ref(), checksums_asset).await?;
+        let signature = download_bytes(self.source.as_ref(), signature_asset).await?;
+
+        let expected = verify_checksums(self.public_key, &checksums, &signature, &info.asset.name)?;
+
+        if expected != checksum {
+            return Err(UpdateError::ChecksumMismatch);
+        }
+
+        Ok(())
+    }
+
+    /// Streams the release asset to the temporary download path reporting
+    /// progress as each chunk arrives, returns the SHA-256 checksum of the
+    /// downloaded file as a lowercase hex string
+    ///
+    /// ## Arguments
+    /// * `info`        - The update to download
+    /// * `on_progress` - Callback invoked with the progress of the download
+    /// * `cancel`      - Flag for cancelling the download
+    async fn download_asset<F>(
+        &self,
+        info: &UpdateInfo,
+        on_progress: &mut F,
+        cancel: &AtomicBool,
+    ) -> Result<String, UpdateError>
+    where
+        F: FnMut(DownloadProgress),
+    {
+        let mut download = self.source.download(&info.asset).await?;
+
+        let mut progress = DownloadProgress {
+            downloaded: 0,
+            total: download.total().or(Some(info.asset.size)),
+        };
+        on_progress(progress);
+
+        let mut file = File::create(&self.paths.tmp_download).await?;
+        let mut hasher = Sha256::new();
+
+        while let Some(chunk) = download.chunk().await? {
+            if cancel.load(Ordering::Acquire) {
+                debug!("Update download cancelled");
+                return Err(UpdateError::Cancelled);
+            }
+
+            hasher.update(&chunk);
+            file.write_all(&chunk).await?;
+
+            progress.downloaded += chunk.len() as u64;
+            on_progress(progress);
+        }
+
+        file.flush().await?;
+
+        Ok(to_hex(&hasher.finish()))
+    }
+
+    /// Launches the updated executable with the same arguments as the current
+    /// process and waits for it to report a successful startup. If the new
+    /// version exits or doesn't report within [`STARTUP_TIMEOUT`] it is stopped
+    /// and the previous version is restored.
+    pub async fn restart(&self) -> Result<(), UpdateError> {
+        let paths = &self.paths;
+
+        // Clear any marker left by a previous update
+        if paths.tmp_ready.exists() {
+            tokio::fs::remove_file(&paths.tmp_ready).await?;
+        }
+
+        let args = std::env::args()
+            .skip(1)
+            .filter(|arg| arg != UPDATED_ARG)
+            .chain(std::iter::once(UPDATED_ARG.to_string()));
+
+        debug!("Launching updated client");
+
+        let mut child = match Command::new(&paths.exe).args(args).spawn() {
+            Ok(value) => value,
+            Err(err) => return Err(rollback_update(paths, err.to_string())),
+        };
+
+        let start = Instant::now();
+
+        loop {
+            // New version has started successfully
+            if paths.tmp_ready.exists() {
+                debug!("Updated client started successfully");
+                REPLACED.store(true, Ordering::Relaxed);
+                return Ok(());
+            }
+
+            let reason = match child.try_wait() {
+                Ok(Some(status)) => Some(format!("exited with {}", status)),
+                Ok(None) if start.elapsed() >= STARTUP_TIMEOUT => {
+                    // Killing also waits for the process to exit
+                    if let Err(err) = child.kill().await {
+                        error!("Failed to stop updated client: {}", err);
+                    }
+                    Some("timed out while starting".to_string())
+                }
+                Ok(None) => None,
+                Err(err) => Some(err.to_string()),
+            };
+
+            if let Some(reason) = reason {
+                return Err(rollback_update(paths, reason));
+            }
+
+            sleep(Duration::from_millis(250)).await;
+        }
+    }
+}
+
+/// Rolls back a failed update producing the error to report to the user
+///
+/// ## Arguments
+/// * `paths`  - The updater paths
+/// * `reason` - Why the updated client failed to start
+fn rollback_update(paths: &UpdatePaths, reason: String) -> UpdateError {
+    error!("Updated client failed to start: {}", reason);
+
+    match paths.rollback() {
+        Ok(()) => UpdateError::StartupFailed(reason),
+        Err(err) => {
+            error!("Failed to restore previous version: {}", err);
+            UpdateError::RollbackFailed(reason, err)
+        }
+    }
+}
+
+/// Set once an updated client has started and taken over from this one
+static REPLACED: AtomicBool = AtomicBool::new(false);
+
+/// Whether an updated client has started and taken over from this one,
+/// the front-ends close once an update has been installed
+pub fn is_replaced() -> bool {
+    REPLACED.load(Ordering::Relaxed)
+}
+
+/// Reports that this version started successfully after an update, allowing
+/// the previous version to exit, then removes the previous version once its
+/// no longer in use. Called by the UI once its window has been created
+pub fn report_startup() {
+    let paths = match UpdatePaths::current() {
+        Ok(value) => value,
+        Err(err) => {
+            error!("Failed to report successful startup: {}", err);
+            return;
+        }
+    };
+
+    if let Err(err) = std::fs::write(&paths.tmp_ready, []) {
+        error!("Failed to report successful startup: {}", err);
+        return;
+    }
+
+    // The previous version may take a moment to exit
+    std::thread::spawn(move || {
+        if let Err(err) = paths.remove_tmp_old() {
+            error!("Failed to remove previous version: {}", err);
+        }
+    });
+}
@@ -0,0 +1,278 @@
+//! Sources that releases can be obtained from by the updater
+
+use super::UpdateError;
+use crate::{
+    config::UpdateChannel,
+    core::reqwest::{self, header},
+};
+use serde::Deserialize;
+use std::{future::Future, path::PathBuf, pin::Pin};
+use tokio::{fs::File, io::AsyncReadExt};
+
+/// Base URL of the GitHub API
+pub const GITHUB_API_URL: &str = "https://api.github.com";
+/// Name of the file listing the releases in a [`LocalReleaseSource`]
+pub const LOCAL_RELEASES_FILE: &str = "releases.json";
+
+/// Boxed future returned by [`ReleaseSource`] and [`AssetDownload`] functions
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Release details from the GitHub releases API
+#[derive(Debug, Clone, Deserialize)]
+pub struct GitHubRelease {
+    /// Name of the release tag (i.e v0.1.0)
+    pub tag_name: String,
+    /// Release notes / changelog of the release
+    #[serde(default)]
+    pub body: Option<String>,
+    /// Whether the release is marked as a pre-release
+    #[serde(default)]
+    pub prerelease: bool,
+    /// The files attached to the release
+    pub assets: Vec<GitHubReleaseAsset>,
+}
+
+/// File attached to a [`GitHubRelease`]
+#[derive(Debug, Clone, Deserialize)]
+pub struct GitHubReleaseAsset {
+    /// File name of the asset
+    pub name: String,
+    /// URL to download the asset from
+    pub browser_download_url: String,
+    /// Size of the asset in bytes
+    pub size: u64,
+}
+
+/// Source that releases and their assets can be obtained from
+pub trait ReleaseSource: Send + Sync {
+    /// Obtains the most recent release for the provided update `channel`
+    fn latest_release(
+        &self,
+        channel: UpdateChannel,
+    ) -> BoxFuture<'_, Result<Option<GitHubRelease>, UpdateError>>;
+
+    /// Starts downloading the provided release `asset`
+    fn download<'a>(
+        &'a self,
+        asset: &'a GitHubReleaseAsset,
+    ) -> BoxFuture<'a, Result<Box<dyn AssetDownload>, UpdateError>>;
+}
+
+/// In progress download of a release asset
+pub trait AssetDownload: Send {
+    /// Total size of the asset in bytes if known
+    fn total(&self) -> Option<u64>;
+
+    /// Obtains the next chunk of the asset, [None] once the
+    /// entire asset has been read
+    fn chunk(&mut self) -> BoxFuture<'_, Result<Option<Vec<u8>>, UpdateError>>;
+}
+
+/// Downloads the entire contents of an `asset` from the `source`,
+/// intended for small assets like the checksums and signature
+pub async fn download_bytes(
+    source: &dyn ReleaseSource,
+    asset: &GitHubReleaseAsset,
+) -> Result<Vec<u8>, UpdateError> {
+    let mut download = source.download(asset).await?;
+    let mut bytes = Vec::new();
+    while let Some(chunk) = download.chunk().await? {
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(bytes)
+}
+
+/// Release source using the GitHub releases API. The API URL can be
+/// changed to point at a stand-in HTTP server
+pub struct GitHubReleaseSource {
+    /// HTTP client to make requests with
+    http_client: reqwest::Client,
+    /// Base URL of the API
+    api_url: String,
+    /// The repository to get releases from (i.e PocketRelay/PocketArkClient)
+    repository: String,
+}
+
+impl GitHubReleaseSource {
+    /// Creates a new release source for the `repository` on GitHub
+    ///
+    /// ## Arguments
+    /// * `http_client` - HTTP client to make requests with
+    /// * `api_url`     - Base URL of the API
+    /// * `repository`  - The repository to get releases from
+    pub fn new(http_client: reqwest::Client, api_url: &str, repository: &str) -> Self {
+        Self {
+            http_client,
+            api_url: api_url.trim_end_matches('/').to_string(),
+            repository: repository.to_string(),
+        }
+    }
+
+    /// Creates a GET request to the GitHub API or asset download URL
+    fn request(&self, url: &str) -> reqwest::RequestBuilder {
+        self.http_client
+            .get(url)
+            .header(header::USER_AGENT, "PocketArkClient")
+    }
+}
+
+impl ReleaseSource for GitHubReleaseSource {
+    fn latest_release(
+        &self,
+        channel: UpdateChannel,
+    ) -> BoxFuture<'_, Result<Option<GitHubRelease>, UpdateError>> {
+        Box::pin(async move {
+            let release = match channel {
+                // The GitHub "latest" release excludes pre-releases
+                UpdateChannel::Stable => {
+                    let url = format!("{}/repos/{}/releases/latest", self.api_url, self.repository);
+
+                    self.request(&url)
+                        .header(header::ACCEPT, "application/vnd.github+json")
+                        .send()
+                        .await?
+                        .error_for_status()?
+                        .json()
+                        .await?
+                }
+                // The most recent release including pre-releases
+                UpdateChannel::Beta => {
+                    let url = format!(
+                        "{}/repos/{}/releases?per_page=1",
+                        self.api_url, self.repository
+                    );
+
+                    let releases: Vec<GitHubRelease> = self
+                        .request(&url)
+                        .header(header::ACCEPT, "application/vnd.github+json")
+                        .send()
+                        .await?
+                        .error_for_status()?
+                        .json()
+                        .await?;
+
+                    match releases.into_iter().next() {
+                        Some(value) => value,
+                        None => return Ok(None),
+                    }
+                }
+                UpdateChannel::Disabled => return Ok(None),
+            };
+
+            Ok(Some(release))
+        })
+    }
+
+    fn download<'a>(
+        &'a self,
+        asset: &'a GitHubReleaseAsset,
+    ) -> BoxFuture<'a, Result<Box<dyn AssetDownload>, UpdateError>> {
+        Box::pin(async move {
+            let response = self
+                .request(&asset.browser_download_url)
+                .header(header::ACCEPT, "application/octet-stream")
+                .send()
+                .await?
+                .error_for_status()?;
+
+            let download: Box<dyn AssetDownload> = Box::new(HttpAssetDownload { response });
+            Ok(download)
+        })
+    }
+}
+
+/// Asset download from a HTTP response
+struct HttpAssetDownload {
+    /// The response the asset is being read from
+    response: reqwest::Response,
+}
+
+impl AssetDownload for HttpAssetDownload {
+    fn total(&self) -> Option<u64> {
+        self.response.content_length()
+    }
+
+    fn chunk(&mut self) -> BoxFuture<'_, Result<Option<Vec<u8>>, UpdateError>> {
+        Box::pin(async move {
+            let chunk = self.response.chunk().await?;
+            Ok(chunk.map(|chunk| chunk.to_vec()))
+        })
+    }
+}
+
+/// Release source reading releases from a local directory. The directory
+/// contains a `releases.json` file with a list of releases (most recent first)
+/// in the GitHub API format, each asset `browser_download_url` is the path of
+/// the asset file relative to the directory
+pub struct LocalReleaseSource {
+    /// The directory containing the releases
+    path: PathBuf,
+}
+
+impl LocalReleaseSource {
+    /// Creates a new release source for the directory at `path`
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+}
+
+impl ReleaseSource for LocalReleaseSource {
+    fn latest_release(
+        &self,
+        channel: UpdateChannel,
+    ) -> BoxFuture<'_, Result<Option<GitHubRelease>, UpdateError>> {
+        Box::pin(async move {
+            let bytes = tokio::fs::read(self.path.join(LOCAL_RELEASES_FILE)).await?;
+            let releases: Vec<GitHubRelease> = serde_json::from_slice(&bytes)
+                .map_err(|err| UpdateError::InvalidRelease(err.to_string()))?;
+
+            let release = releases.into_iter().find(|release| match channel {
+                UpdateChannel::Stable => !release.prerelease,
+                UpdateChannel::Beta => true,
+                UpdateChannel::Disabled => false,
+            });
+
+            Ok(release)
+        })
+    }
+
+    fn download<'a>(
+        &'a self,
+        asset: &'a GitHubReleaseAsset,
+    ) -> BoxFuture<'a, Result<Box<dyn AssetDownload>, UpdateError>> {
+        Box::pin(async move {
+            let file = File::open(self.path.join(&asset.browser_download_url)).await?;
+            let total = file.metadata().await?.len();
+
+            let download: Box<dyn AssetDownload> = Box::new(FileAssetDownload { file, total });
+            Ok(download)
+        })
+    }
+}
+
+/// Asset download from a local file
+struct FileAssetDownload {
+    /// The file the asset is being read from
+    file: File,
+    /// Size of the file
+    total: u64,
+}
+
+impl AssetDownload for FileAssetDownload {
+    fn total(&self) -> Option<u64> {
+        Some(self.total)
+    }
+
+    fn chunk(&mut self) -> BoxFuture<'_, Result<Option<Vec<u8>>, UpdateError>> {
+        Box::pin(async move {
+            let mut buffer = vec![0; 64 * 1024];
+            let count = self.file.read(&mut buffer).await?;
+            if count == 0 {
+                return Ok(None);
+            }
+
+            buffer.truncate(count);
+            Ok(Some(buffer))
+        })
+    }
+}
@@ -0,0 +1,576 @@
+use super::{
+    select_update,
+    source::{
+        GitHubRelease, GitHubReleaseAsset, GitHubReleaseSource, LocalReleaseSource,
+        LOCAL_RELEASES_FILE,
+    },
+    DownloadProgress, UpdateError, UpdatePaths, Updater, CHECKSUMS_ASSET_NAME,
+    SIGNATURE_ASSET_NAME,
+};
+use crate::{
+    config::UpdateChannel,
+    core::{
+        reqwest::{self, StatusCode},
+        Version,
+    },
+    hex::to_hex,
+    proxy::{read_request, write_response, ProxyRequest},
+};
+use openssl::{
+    pkey::{PKey, Private},
+    sha::sha256,
+    sign::Signer,
+};
+use serde_json::{json, Value};
+use std::{
+    io,
+    net::Ipv4Addr,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+use tokio::{
+    io::BufReader,
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+};
+
+/// Name of the platform asset used by the test releases
+const ASSET_NAME: &str = "pocket-ark-client-test";
+
+/// Temporary directory removed when dropped
+struct TestDir(PathBuf);
+
+impl TestDir {
+    fn new() -> Self {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let path = std::env::temp_dir().join(format!(
+            "pocket-ark-update-test-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    fn join(&self, path: &str) -> PathBuf {
+        self.0.join(path)
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Ways the published release can be broken
+#[derive(Default)]
+struct ReleaseOptions {
+    /// Sign the checksums with a different key
+    wrong_key: bool,
+    /// Publish a checksum that doesn't match the asset
+    wrong_checksum: bool,
+    /// Leave the asset out of the checksums
+    missing_checksum: bool,
+}
+
+/// Key pair used to sign the test releases
+struct SigningKey {
+    private: PKey<Private>,
+    public_pem: &'static [u8],
+}
+
+impl SigningKey {
+    fn generate() -> Self {
+        let private = PKey::generate_ed25519().unwrap();
+        let public_pem = private.public_key_to_pem().unwrap().leak();
+        Self {
+            private,
+            public_pem,
+        }
+    }
+
+    fn sign(&self, data: &[u8]) -> Vec<u8> {
+        let mut signer = Signer::new_without_digest(&self.private).unwrap();
+        signer.sign_oneshot_to_vec(data).unwrap()
+    }
+}
+
+fn asset(name: &str) -> GitHubReleaseAsset {
+    GitHubReleaseAsset {
+        name: name.to_string(),
+        browser_download_url: name.to_string(),
+        size: 0,
+    }
+}
+
+fn release(tag_name: &str, asset_names: &[&str]) -> GitHubRelease {
+    GitHubRelease {
+        tag_name: tag_name.to_string(),
+        body: Some("Release notes".to_string()),
+        prerelease: false,
+        assets: asset_names.iter().map(|name| asset(name)).collect(),
+    }
+}
+
+/// Publishes a release of `version` containing `contents` as the platform
+/// asset to the local release source at `dir`
+fn publish_release(
+    dir: &Path,
+    key: &SigningKey,
+    version: &str,
+    contents: &[u8],
+    options: ReleaseOptions,
+) {
+    std::fs::write(dir.join(ASSET_NAME), contents).unwrap();
+
+    let checksum = if options.wrong_checksum {
+        to_hex(&sha256(b"something else"))
+    } else {
+        to_hex(&sha256(contents))
+    };
+    let listed_name = if options.missing_checksum {
+        "other-asset"
+    } else {
+        ASSET_NAME
+    };
+    let checksums = format!("{}  {}\n", checksum, listed_name);
+
+    let signature = if options.wrong_key {
+        SigningKey::generate().sign(checksums.as_bytes())
+    } else {
+        key.sign(checksums.as_bytes())
+    };
+
+    std::fs::write(dir.join(CHECKSUMS_ASSET_NAME), checksums).unwrap();
+    std::fs::write(dir.join(SIGNATURE_ASSET_NAME), signature).unwrap();
+
+    let assets: Vec<_> = [ASSET_NAME, CHECKSUMS_ASSET_NAME, SIGNATURE_ASSET_NAME]
+        .iter()
+        .map(|name| json!({ "name": name, "browser_download_url": name, "size": 0 }))
+        .collect();
+    let releases = json!([
+        { "tag_name": "v9.0.0", "prerelease": true, "assets": assets },
+        { "tag_name": format!("v{}", version), "body": "Fixes", "assets": assets },
+    ]);
+    std::fs::write(dir.join(LOCAL_RELEASES_FILE), releases.to_string()).unwrap();
+}
+
+/// Creates an updater for an executable containing "old" in `dir`
+/// using the releases published in `dir`
+fn create_updater(dir: &TestDir, key: &SigningKey) -> Updater {
+    let exe = dir.join("client");
+    std::fs::write(&exe, b"old").unwrap();
+
+    Updater {
+        source: Arc::new(LocalReleaseSource::new(dir.0.clone())),
+        paths: UpdatePaths::new(exe).unwrap(),
+        current_version: Version::new(0, 1, 0),
+        asset_name: ASSET_NAME,
+        public_key: key.public_pem,
+    }
+}
+
+/// Repository the GitHub stand-in serves releases for
+const REPOSITORY: &str = "PocketRelay/PocketArkClient";
+
+/// Loopback stand-in for the GitHub API serving the releases published
+/// to a directory, asset download URLs point back at the stand-in
+struct GitHubStandIn {
+    /// Base URL of the stand-in API
+    url: String,
+    /// Paths of the requests that were served
+    requests: Arc<Mutex<Vec<String>>>,
+    /// Task accepting connections
+    task: JoinHandle<()>,
+}
+
+impl GitHubStandIn {
+    async fn start(dir: PathBuf) -> Self {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let task = tokio::spawn({
+            let (url, requests) = (url.clone(), requests.clone());
+            async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    let (dir, url, requests) = (dir.clone(), url.clone(), requests.clone());
+                    tokio::spawn(async move {
+                        let _ = Self::handle(stream, &dir, &url, &requests).await;
+                    });
+                }
+            }
+        });
+
+        Self {
+            url,
+            requests,
+            task,
+        }
+    }
+
+    async fn handle(
+        stream: TcpStream,
+        dir: &Path,
+        url: &str,
+        requests: &Mutex<Vec<String>>,
+    ) -> io::Result<()> {
+        let mut stream = BufReader::new(stream);
+        let request = read_request(&mut stream).await?;
+        requests.lock().unwrap().push(request.path.clone());
+
+        let (status, body) = Self::respond(&request, dir, url);
+        write_response(stream.get_mut(), status, &[], &body).await
+    }
+
+    fn respond(request: &ProxyRequest, dir: &Path, url: &str) -> (StatusCode, Vec<u8>) {
+        // GitHub rejects API requests without a user agent
+        let has_user_agent = request
+            .headers
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case("user-agent"));
+        if !has_user_agent {
+            return (StatusCode::FORBIDDEN, Vec::new());
+        }
+
+        if let Some(name) = request.path.strip_prefix("/download/") {
+            return match std::fs::read(dir.join(name)) {
+                Ok(bytes) => (StatusCode::OK, bytes),
+                Err(_) => (StatusCode::NOT_FOUND, Vec::new()),
+            };
+        }
+
+        // Point the published assets at the stand-in with their actual size
+        let releases = std::fs::read(dir.join(LOCAL_RELEASES_FILE)).unwrap();
+        let mut releases: Vec<Value> = serde_json::from_slice(&releases).unwrap();
+        for asset in releases
+            .iter_mut()
+            .flat_map(|release| release["assets"].as_array_mut().unwrap())
+        {
+            let name = asset["name"].as_str().unwrap().to_string();
+            let size = std::fs::metadata(dir.join(&name)).unwrap().len();
+            asset["browser_download_url"] = json!(format!("{}/download/{}", url, name));
+            asset["size"] = json!(size);
+        }
+
+        let latest_path = format!("/repos/{}/releases/latest", REPOSITORY);
+        let list_path = format!("/repos/{}/releases?per_page=1", REPOSITORY);
+
+        let body = if request.path == latest_path {
+            releases
+                .into_iter()
+                .find(|release| !release["prerelease"].as_bool().unwrap_or_default())
+                .unwrap()
+        } else if request.path == list_path {
+            json!(releases.into_iter().take(1).collect::<Vec<_>>())
+        } else {
+            return (StatusCode::NOT_FOUND, Vec::new());
+        };
+
+        (StatusCode::OK, body.to_string().into_bytes())
+    }
+
+    /// Takes the paths of the requests served so far
+    fn take_requests(&self) -> Vec<String> {
+        std::mem::take(&mut *self.requests.lock().unwrap())
+    }
+}
+
+impl Drop for GitHubStandIn {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Checks for and downloads the stable update
+async fn download_update(updater: &Updater, cancel: bool) -> Result<(), UpdateError> {
+    let info = updater
+        .check(UpdateChannel::Stable, None)
+        .await?
+        .expect("Missing update");
+    updater
+        .download(&info, |_| {}, &AtomicBool::new(cancel))
+        .await
+}
+
+#[test]
+fn test_select_newer_version() {
+    let current = Version::new(0, 1, 0);
+
+    let info = select_update(release("v0.2.0", &[ASSET_NAME]), &current, None, ASSET_NAME)
+        .unwrap()
+        .expect("Missing update");
+    assert_eq!(info.version, Version::new(0, 2, 0));
+    assert_eq!(info.asset.name, ASSET_NAME);
+    assert_eq!(info.release_notes, "Release notes");
+
+    // Same, older and skipped versions aren't offered
+    for (tag, skipped) in [
+        ("v0.1.0", None),
+        ("v0.0.9", None),
+        ("v0.2.0", Some("0.2.0")),
+    ] {
+        let update = select_update(release(tag, &[ASSET_NAME]), &current, skipped, ASSET_NAME);
+        assert!(matches!(update, Ok(None)), "{} was offered", tag);
+    }
+}
+
+#[test]
+fn test_select_invalid_version() {
+    let update = select_update(
+        release("latest", &[ASSET_NAME]),
+        &Version::new(0, 1, 0),
+        None,
+        ASSET_NAME,
+    );
+    assert!(matches!(update, Err(UpdateError::InvalidVersion(_))));
+}
+
+#[test]
+fn test_select_missing_asset() {
+    let update = select_update(
+        release("v0.2.0", &["pocket-ark-client-other"]),
+        &Version::new(0, 1, 0),
+        None,
+        ASSET_NAME,
+    );
+    assert!(matches!(update, Err(UpdateError::MissingAsset(ASSET_NAME))));
+}
+
+#[test]
+fn test_invalid_executable_path() {
+    assert!(matches!(
+        UpdatePaths::new(PathBuf::from("/")),
+        Err(UpdateError::InvalidExecutablePath(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_check_channels() {
+    let dir = TestDir::new();
+    let key = SigningKey::generate();
+    publish_release(&dir.0, &key, "0.2.0", b"new", ReleaseOptions::default());
+    let updater = create_updater(&dir, &key);
+
+    let stable = updater.check(UpdateChannel::Stable, None).await.unwrap();
+    assert_eq!(stable.unwrap().version, Version::new(0, 2, 0));
+
+    // Pre-releases are only offered on the beta channel
+    let beta = updater.check(UpdateChannel::Beta, None).await.unwrap();
+    assert_eq!(beta.unwrap().version, Version::new(9, 0, 0));
+
+    let disabled = updater.check(UpdateChannel::Disabled, None).await.unwrap();
+    assert!(disabled.is_none());
+}
+
+#[tokio::test]
+async fn test_download_swap_and_rollback() {
+    let dir = TestDir::new();
+    let key = SigningKey::generate();
+    publish_release(&dir.0, &key, "0.2.0", b"new", ReleaseOptions::default());
+    let updater = create_updater(&dir, &key);
+    let paths = &updater.paths;
+
+    download_update(&updater, false).await.unwrap();
+
+    assert_eq!(std::fs::read(&paths.exe).unwrap(), b"new");
+    assert_eq!(std::fs::read(&paths.tmp_old).unwrap(), b"old");
+    assert!(!paths.tmp_download.exists());
+
+    paths.rollback().unwrap();
+
+    assert_eq!(std::fs::read(&paths.exe).unwrap(), b"old");
+    assert!(!paths.tmp_old.exists());
+}
+
+/// Downloads a release broken by the `options` asserting the executable
+/// is left untouched and returning the error
+async fn download_rejected(options: ReleaseOptions) -> UpdateError {
+    let dir = TestDir::new();
+    let key = SigningKey::generate();
+    publish_release(&dir.0, &key, "0.2.0", b"new", options);
+    let updater = create_updater(&dir, &key);
+    let paths = &updater.paths;
+
+    let err = download_update(&updater, false).await.unwrap_err();
+
+    assert_eq!(std::fs::read(&paths.exe).unwrap(), b"old");
+    assert!(!paths.tmp_download.exists());
+    assert!(!paths.tmp_old.exists());
+    err
+}
+
+#[tokio::test]
+async fn test_reject_invalid_signature() {
+    let err = download_rejected(ReleaseOptions {
+        wrong_key: true,
+        ..Default::default()
+    })
+    .await;
+    assert!(matches!(err, UpdateError::InvalidSignature));
+}
+
+#[tokio::test]
+async fn test_reject_checksum_mismatch() {
+    let err = download_rejected(ReleaseOptions {
+        wrong_checksum: true,
+        ..Default::default()
+    })
+    .await;
+    assert!(matches!(err, UpdateError::ChecksumMismatch));
+}
+
+#[tokio::test]
+async fn test_reject_missing_checksum() {
+    let err = download_rejected(ReleaseOptions {
+        missing_checksum: true,
+        ..Default::default()
+    })
+    .await;
+    assert!(matches!(err, UpdateError::MissingChecksum(name) if name == ASSET_NAME));
+}
+
+#[tokio::test]
+async fn test_cancel_download() {
+    let dir = TestDir::new();
+    let key = SigningKey::generate();
+    publish_release(&dir.0, &key, "0.2.0", b"new", ReleaseOptions::default());
+    let updater = create_updater(&dir, &key);
+    let paths = &updater.paths;
+
+    let err = download_update(&updater, true).await.unwrap_err();
+
+    assert!(matches!(err, UpdateError::Cancelled));
+    assert_eq!(std::fs::read(&paths.exe).unwrap(), b"old");
+    assert!(!paths.tmp_download.exists());
+}
+
+/// Installs a script as the updated executable with the previous
+/// version moved aside, as left by a successful download
+#[cfg(unix)]
+fn install_script(dir: &TestDir, key: &SigningKey, script: &str) -> Updater {
+    use std::os::unix::fs::PermissionsExt;
+
+    let updater = create_updater(dir, key);
+    let paths = &updater.paths;
+    std::fs::rename(&paths.exe, &paths.tmp_old).unwrap();
+    std::fs::write(&paths.exe, script).unwrap();
+    std::fs::set_permissions(&paths.exe, std::fs::Permissions::from_mode(0o755)).unwrap();
+    updater
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_restart_reported_startup() {
+    let dir = TestDir::new();
+    let key = SigningKey::generate();
+    let updater = install_script(&dir, &key, "#!/bin/sh\ntouch \"$0.tmp-ready\"\n");
+
+    updater.restart().await.unwrap();
+
+    // The new version is kept
+    assert!(std::fs::read(&updater.paths.exe)
+        .unwrap()
+        .starts_with(b"#!"));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_restart_rolls_back_failed_startup() {
+    let dir = TestDir::new();
+    let key = SigningKey::generate();
+    let updater = install_script(&dir, &key, "#!/bin/sh\nexit 3\n");
+
+    let err = updater.restart().await.unwrap_err();
+
+    assert!(matches!(err, UpdateError::StartupFailed(_)));
+    assert_eq!(std::fs::read(&updater.paths.exe).unwrap(), b"old");
+    assert!(!updater.paths.tmp_old.exists());
+}
+
+#[tokio::test]
+async fn test_github_release_source() {
+    let dir = TestDir::new();
+    let key = SigningKey::generate();
+
+    // Large enough to arrive over multiple chunks
+    let contents: Vec<u8> = (0..512 * 1024).map(|value| (value % 251) as u8).collect();
+    publish_release(&dir.0, &key, "0.2.0", &contents, ReleaseOptions::default());
+
+    let stand_in = GitHubStandIn::start(dir.0.clone()).await;
+    let source = GitHubReleaseSource::new(reqwest::Client::new(), &stand_in.url, REPOSITORY);
+    let updater = Updater {
+        source: Arc::new(source),
+        ..create_updater(&dir, &key)
+    };
+
+    // Pre-releases are only offered on the beta channel
+    let beta = updater.check(UpdateChannel::Beta, None).await.unwrap();
+    assert_eq!(beta.unwrap().version, Version::new(9, 0, 0));
+
+    let info = updater
+        .check(UpdateChannel::Stable, None)
+        .await
+        .unwrap()
+        .expect("Missing update");
+    assert_eq!(info.version, Version::new(0, 2, 0));
+    assert_eq!(info.release_notes, "Fixes");
+    assert_eq!(info.asset.name, ASSET_NAME);
+    assert_eq!(info.asset.size, contents.len() as u64);
+    assert_eq!(
+        stand_in.take_requests(),
+        [
+            format!("/repos/{}/releases?per_page=1", REPOSITORY),
+            format!("/repos/{}/releases/latest", REPOSITORY),
+        ]
+    );
+
+    let mut progress: Vec<DownloadProgress> = Vec::new();
+    updater
+        .download(&info, |value| progress.push(value), &AtomicBool::new(false))
+        .await
+        .unwrap();
+
+    assert_eq!(std::fs::read(&updater.paths.exe).unwrap(), contents);
+
+    // The asset is streamed with the total size from the response
+    let last = progress.last().unwrap();
+    assert!(progress.len() > 2, "Download wasn't streamed");
+    assert_eq!(last.downloaded, contents.len() as u64);
+    assert_eq!(last.total, Some(contents.len() as u64));
+
+    let mut downloads = stand_in.take_requests();
+    downloads.sort();
+    let mut expected: Vec<String> = [ASSET_NAME, CHECKSUMS_ASSET_NAME, SIGNATURE_ASSET_NAME]
+        .iter()
+        .map(|name| format!("/download/{}", name))
+        .collect();
+    expected.sort();
+    assert_eq!(downloads, expected);
+}
+
+#[tokio::test]
+async fn test_github_missing_release() {
+    let dir = TestDir::new();
+    let key = SigningKey::generate();
+    publish_release(&dir.0, &key, "0.2.0", b"new", ReleaseOptions::default());
+
+    // Requests for another repository aren't found
+    let stand_in = GitHubStandIn::start(dir.0.clone()).await;
+    let source = GitHubReleaseSource::new(reqwest::Client::new(), &stand_in.url, "Other/Repo");
+    let updater = Updater {
+        source: Arc::new(source),
+        ..create_updater(&dir, &key)
+    };
+
+    let err = updater
+        .check(UpdateChannel::Stable, None)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, UpdateError::Download(_)), "{:?}", err);
+}
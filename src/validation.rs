@@ -0,0 +1,306 @@
+//! Client-side validation for the login and account creation forms,
+//! catching mistakes before they are sent to the server
+
+use crate::{account::AccountError, controller::ControllerError};
+use thiserror::Error;
+
+/// Minimum number of characters in a username
+pub const MIN_USERNAME_LENGTH: usize = 3;
+/// Maximum number of characters in a username
+pub const MAX_USERNAME_LENGTH: usize = 16;
+/// Minimum number of characters in a password
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+/// Maximum number of characters in a password
+pub const MAX_PASSWORD_LENGTH: usize = 99;
+
+/// Form fields that errors can be shown next to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FormField {
+    /// Email input
+    Email,
+    /// Username input
+    Username,
+    /// Password input
+    Password,
+    /// Confirm password input
+    ConfirmPassword,
+}
+
+/// Reasons a form field is invalid
+#[derive(Debug, Clone, Error)]
+pub enum ValidationError {
+    /// No email was entered
+    #[error("Email is required")]
+    EmptyEmail,
+    /// The email isn't a valid email address
+    #[error("Email address is not valid")]
+    InvalidEmail,
+    /// The username is too short or too long
+    #[error(
+        "Username must be between {} and {} characters",
+        MIN_USERNAME_LENGTH,
+        MAX_USERNAME_LENGTH
+    )]
+    UsernameLength,
+    /// The username contains disallowed characters
+    #[error("Username may only contain letters, numbers, '_', '-' and '.'")]
+    UsernameCharacters,
+    /// No password was entered
+    #[error("Password is required")]
+    EmptyPassword,
+    /// The password is too short or too long
+    #[error(
+        "Password must be between {} and {} characters",
+        MIN_PASSWORD_LENGTH,
+        MAX_PASSWORD_LENGTH
+    )]
+    PasswordLength,
+    /// The password is missing letters or numbers
+    #[error("Password must contain both letters and numbers")]
+    WeakPassword,
+    /// The confirm password doesn't match the password
+    #[error("Passwords do not match")]
+    PasswordMismatch,
+}
+
+/// Checks that `email` looks like an email address
+pub fn validate_email(email: &str) -> Result<(), ValidationError> {
+    if email.is_empty() {
+        return Err(ValidationError::EmptyEmail);
+    }
+
+    let (local, domain) = email.split_once('@').ok_or(ValidationError::InvalidEmail)?;
+
+    let valid = !local.is_empty()
+        && !domain.contains('@')
+        && !email.chars().any(char::is_whitespace)
+        && domain
+            .split('.')
+            .all(|part| !part.is_empty() && !part.starts_with('-') && !part.ends_with('-'))
+        && domain.contains('.');
+
+    if valid {
+        Ok(())
+    } else {
+        Err(ValidationError::InvalidEmail)
+    }
+}
+
+/// Checks the length and characters of `username`
+pub fn validate_username(username: &str) -> Result<(), ValidationError> {
+    let length = username.chars().count();
+    if !(MIN_USERNAME_LENGTH..=MAX_USERNAME_LENGTH).contains(&length) {
+        return Err(ValidationError::UsernameLength);
+    }
+
+    if !username
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+    {
+        return Err(ValidationError::UsernameCharacters);
+    }
+
+    Ok(())
+}
+
+/// Checks a new `password` is long enough and contains both
+/// letters and numbers
+pub fn validate_password(password: &str) -> Result<(), ValidationError> {
+    if password.is_empty() {
+        return Err(ValidationError::EmptyPassword);
+    }
+
+    let length = password.chars().count();
+    if !(MIN_PASSWORD_LENGTH..=MAX_PASSWORD_LENGTH).contains(&length) {
+        return Err(ValidationError::PasswordLength);
+    }
+
+    let has_letter = password.chars().any(char::is_alphabetic);
+    let has_number = password.chars().any(|c| c.is_ascii_digit());
+    if !has_letter || !has_number {
+        return Err(ValidationError::WeakPassword);
+    }
+
+    Ok(())
+}
+
+/// Checks the `confirm` password matches the `password`
+pub fn validate_confirm_password(password: &str, confirm: &str) -> Result<(), ValidationError> {
+    if password == confirm {
+        Ok(())
+    } else {
+        Err(ValidationError::PasswordMismatch)
+    }
+}
+
+/// Validates the login form, only the email format is checked for
+/// the password so accounts made before these rules can still login
+///
+/// ## Arguments
+/// * `email`    - The entered email
+/// * `password` - The entered password
+pub fn validate_login(email: &str, password: &str) -> Vec<(FormField, ValidationError)> {
+    let mut errors = Vec::new();
+    if let Err(err) = validate_email(email) {
+        errors.push((FormField::Email, err));
+    }
+    if password.is_empty() {
+        errors.push((FormField::Password, ValidationError::EmptyPassword));
+    }
+    errors
+}
+
+/// Validates the account creation form
+///
+/// ## Arguments
+/// * `email`            - The entered email
+/// * `username`         - The entered username
+/// * `password`         - The entered password
+/// * `confirm_password` - The entered password confirmation
+pub fn validate_create(
+    email: &str,
+    username: &str,
+    password: &str,
+    confirm_password: &str,
+) -> Vec<(FormField, ValidationError)> {
+    let checks = [
+        (FormField::Email, validate_email(email)),
+        (FormField::Username, validate_username(username)),
+        (FormField::Password, validate_password(password)),
+        (
+            FormField::ConfirmPassword,
+            validate_confirm_password(password, confirm_password),
+        ),
+    ];
+
+    checks
+        .into_iter()
+        .filter_map(|(field, result)| result.err().map(|err| (field, err)))
+        .collect()
+}
+
+/// Finds the form field a server `error` from logging in or creating an
+/// account refers to so it can be shown next to the matching input
+pub fn server_error_field(error: &ControllerError) -> Option<FormField> {
+    match error {
+        ControllerError::Login(AccountError::InvalidCredentials) => Some(FormField::Password),
+        ControllerError::Create(AccountError::EmailTaken) => Some(FormField::Email),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        server_error_field, validate_create, validate_email, validate_login, validate_password,
+        validate_username, FormField, ValidationError,
+    };
+    use crate::{account::AccountError, controller::ControllerError, core::reqwest::StatusCode};
+
+    #[test]
+    fn test_validate_email() {
+        for email in ["test@example.com", "a.b-c@sub.example.org"] {
+            assert!(validate_email(email).is_ok(), "{} was rejected", email);
+        }
+
+        assert!(matches!(
+            validate_email(""),
+            Err(ValidationError::EmptyEmail)
+        ));
+        for email in [
+            "example.com",
+            "@example.com",
+            "test@example",
+            "test@@example.com",
+            "test@-example.com",
+            "test@example..com",
+            "te st@example.com",
+        ] {
+            assert!(
+                matches!(validate_email(email), Err(ValidationError::InvalidEmail)),
+                "{} was accepted",
+                email
+            );
+        }
+    }
+
+    #[test]
+    fn test_validate_username_and_password() {
+        assert!(validate_username("Test_User-1.2").is_ok());
+        assert!(matches!(
+            validate_username("ab"),
+            Err(ValidationError::UsernameLength)
+        ));
+        assert!(matches!(
+            validate_username(&"a".repeat(17)),
+            Err(ValidationError::UsernameLength)
+        ));
+        assert!(matches!(
+            validate_username("test user"),
+            Err(ValidationError::UsernameCharacters)
+        ));
+
+        assert!(validate_password("password123").is_ok());
+        assert!(matches!(
+            validate_password(""),
+            Err(ValidationError::EmptyPassword)
+        ));
+        assert!(matches!(
+            validate_password("pass1"),
+            Err(ValidationError::PasswordLength)
+        ));
+        assert!(matches!(
+            validate_password("password"),
+            Err(ValidationError::WeakPassword)
+        ));
+        assert!(matches!(
+            validate_password("12345678"),
+            Err(ValidationError::WeakPassword)
+        ));
+    }
+
+    #[test]
+    fn test_validate_forms() {
+        assert!(validate_login("test@example.com", "old").is_empty());
+
+        let fields: Vec<FormField> = validate_login("invalid", "")
+            .into_iter()
+            .map(|(field, _)| field)
+            .collect();
+        assert_eq!(fields, [FormField::Email, FormField::Password]);
+
+        assert!(
+            validate_create("test@example.com", "Test", "password123", "password123").is_empty()
+        );
+
+        let fields: Vec<FormField> =
+            validate_create("test@example.com", "Test", "password123", "password")
+                .into_iter()
+                .map(|(field, _)| field)
+                .collect();
+        assert_eq!(fields, [FormField::ConfirmPassword]);
+    }
+
+    #[test]
+    fn test_server_error_field() {
+        assert_eq!(
+            server_error_field(&ControllerError::Login(AccountError::InvalidCredentials)),
+            Some(FormField::Password)
+        );
+        assert_eq!(
+            server_error_field(&ControllerError::Create(AccountError::EmailTaken)),
+            Some(FormField::Email)
+        );
+
+        // Errors not about a single field are reported normally, even when
+        // the message mentions a field
+        for error in [
+            ControllerError::Login(AccountError::Server(StatusCode::INTERNAL_SERVER_ERROR)),
+            ControllerError::Create(AccountError::InvalidDetails),
+            ControllerError::Lookup("Unknown email domain".to_string()),
+            ControllerError::Account(AccountError::InvalidCredentials),
+        ] {
+            assert_eq!(server_error_field(&error), None, "{:?}", error);
+        }
+    }
+}